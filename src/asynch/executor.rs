@@ -47,10 +47,28 @@ pub struct Spawner {
 
 impl Spawner {
     pub fn spawn(&self, future: impl Future<Output = ()> + Send + 'static) {
+        self.spawn_task(None, future);
+    }
+
+    // The name shows up in crash reports if the task panics.
+    pub fn spawn_named(
+        &self,
+        name: &'static str,
+        future: impl Future<Output = ()> + Send + 'static,
+    ) {
+        self.spawn_task(Some(name), future);
+    }
+
+    fn spawn_task(
+        &self,
+        name: Option<&'static str>,
+        future: impl Future<Output = ()> + Send + 'static,
+    ) {
         let future = future.boxed();
 
         let task = Arc::new(Task {
             future: cs::Mutex::new(RefCell::new(Some(future))),
+            name,
             sender: self.sender.clone(),
         });
 
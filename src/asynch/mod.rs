@@ -1,3 +1,4 @@
 pub mod executor;
 pub mod task;
-pub mod channel;
\ No newline at end of file
+pub mod channel;
+pub(crate) mod reactor;
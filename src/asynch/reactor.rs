@@ -0,0 +1,147 @@
+use critical_section as cs;
+use std::{
+    cell::{Cell, RefCell},
+    task::Waker,
+};
+
+use crate::ffi;
+
+// The host only reports that *some* I/O became ready, not which handle it was, so every waiting
+// task is woken and re-polls its own handle. Tasks that are still blocked simply register again.
+static IO_WAITERS: cs::Mutex<RefCell<Vec<Waker>>> = cs::Mutex::new(RefCell::new(Vec::new()));
+
+// Pending timers as (id, deadline in microseconds, waker). The host only keeps a single timer for
+// us, so it is always armed for the earliest deadline and re-armed whenever it fires. Timers remove
+// their entry when dropped, so a finished task isn't woken by a deadline it no longer cares about.
+static TIMERS: cs::Mutex<RefCell<Vec<(u64, u64, Waker)>>> =
+    cs::Mutex::new(RefCell::new(Vec::new()));
+static NEXT_TIMER_ID: cs::Mutex<Cell<u64>> = cs::Mutex::new(Cell::new(0));
+
+static NET_WAITERS: cs::Mutex<RefCell<Vec<Waker>>> = cs::Mutex::new(RefCell::new(Vec::new()));
+
+static INPUT_WAITERS: cs::Mutex<RefCell<Vec<Waker>>> = cs::Mutex::new(RefCell::new(Vec::new()));
+
+pub(crate) fn register_io(waker: &Waker, readable: bool, writable: bool) {
+    cs::with(|cs| {
+        let mut waiters = IO_WAITERS.borrow_ref_mut(cs);
+
+        if !waiters.iter().any(|w| w.will_wake(waker)) {
+            waiters.push(waker.clone());
+        }
+    });
+
+    unsafe { ffi::asynch::register_io_wake(io_wake, readable, writable) }
+}
+
+pub(crate) fn register_net_change(waker: &Waker) {
+    cs::with(|cs| {
+        let mut waiters = NET_WAITERS.borrow_ref_mut(cs);
+
+        if !waiters.iter().any(|w| w.will_wake(waker)) {
+            waiters.push(waker.clone());
+        }
+    });
+
+    unsafe { ffi::net::register_net_change_wake(net_change_wake) }
+}
+
+pub(crate) fn register_input(waker: &Waker) {
+    cs::with(|cs| {
+        let mut waiters = INPUT_WAITERS.borrow_ref_mut(cs);
+
+        if !waiters.iter().any(|w| w.will_wake(waker)) {
+            waiters.push(waker.clone());
+        }
+    });
+
+    unsafe { ffi::input::register_input_wake(input_wake) }
+}
+
+// Registers or updates the timer with `id`, returning its id.
+pub(crate) fn register_timer(id: Option<u64>, deadline: u64, waker: &Waker) -> u64 {
+    let (id, earliest) = cs::with(|cs| {
+        let mut timers = TIMERS.borrow_ref_mut(cs);
+
+        let id = match id.and_then(|id| timers.iter_mut().find(|(i, ..)| *i == id)) {
+            Some((id, d, w)) => {
+                *d = deadline;
+                if !w.will_wake(waker) {
+                    *w = waker.clone();
+                }
+                *id
+            }
+            None => {
+                let next_id = NEXT_TIMER_ID.borrow(cs);
+                let id = next_id.get();
+                next_id.set(id.wrapping_add(1));
+
+                timers.push((id, deadline, waker.clone()));
+                id
+            }
+        };
+
+        (id, timers.iter().map(|(_, d, _)| *d).min())
+    });
+
+    if let Some(earliest) = earliest {
+        arm_timer(earliest);
+    }
+
+    id
+}
+
+pub(crate) fn cancel_timer(id: u64) {
+    cs::with(|cs| TIMERS.borrow_ref_mut(cs).retain(|(i, ..)| *i != id));
+}
+
+fn arm_timer(deadline: u64) {
+    let now = unsafe { ffi::time::get_time() };
+
+    unsafe { ffi::asynch::register_timer_wake(timer_wake, deadline.saturating_sub(now)) }
+}
+
+extern "C" fn io_wake() {
+    let waiters = cs::with(|cs| std::mem::take(&mut *IO_WAITERS.borrow_ref_mut(cs)));
+
+    for waker in waiters {
+        waker.wake();
+    }
+}
+
+extern "C" fn net_change_wake() {
+    let waiters = cs::with(|cs| std::mem::take(&mut *NET_WAITERS.borrow_ref_mut(cs)));
+
+    for waker in waiters {
+        waker.wake();
+    }
+}
+
+extern "C" fn input_wake() {
+    let waiters = cs::with(|cs| std::mem::take(&mut *INPUT_WAITERS.borrow_ref_mut(cs)));
+
+    for waker in waiters {
+        waker.wake();
+    }
+}
+
+extern "C" fn timer_wake() {
+    let now = unsafe { ffi::time::get_time() };
+
+    let (expired, next) = cs::with(|cs| {
+        let mut timers = TIMERS.borrow_ref_mut(cs);
+        let (expired, pending) = std::mem::take(&mut *timers)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(_, d, _)| *d <= now);
+
+        *timers = pending;
+        (expired, timers.iter().map(|(_, d, _)| *d).min())
+    });
+
+    for (_, _, waker) in expired {
+        waker.wake();
+    }
+
+    if let Some(next) = next {
+        arm_timer(next);
+    }
+}
@@ -4,15 +4,19 @@ use futures::{
     task::{waker_ref, ArcWake, WakerRef},
 };
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     sync::{Arc, Mutex},
     task::{Context, Poll, Wake, Waker},
 };
 
 use super::channel::Sender;
 
+// The name of the task being polled, if it has one.
+static CURRENT: cs::Mutex<Cell<Option<&'static str>>> = cs::Mutex::new(Cell::new(None));
+
 pub struct Task {
     pub(crate) future: cs::Mutex<RefCell<Option<BoxFuture<'static, ()>>>>,
+    pub(crate) name: Option<&'static str>,
     pub(crate) sender: Sender,
 }
 
@@ -21,20 +25,30 @@ impl Task {
         cs::with(|cs| {
             let mut slot = self.future.borrow_ref_mut(cs);
 
+            // A task can be woken more than once before it runs, or by a leftover waker after it
+            // finished, so there may be nothing left to poll.
             let Some(mut fut) = slot.take() else {
-                panic!("Attempted to poll task after it returned `Poll::Ready`")
+                return;
             };
 
             let waker = waker_ref(&self);
             let mut ctx = Context::from_waker(&waker);
 
-            if fut.as_mut().poll(&mut ctx).is_pending() {
+            CURRENT.borrow(cs).set(self.name);
+            let poll = fut.as_mut().poll(&mut ctx);
+            CURRENT.borrow(cs).set(None);
+
+            if poll.is_pending() {
                 *slot = Some(fut)
             }
         });
     }
 }
 
+pub(crate) fn current_name() -> Option<&'static str> {
+    cs::with(|cs| CURRENT.borrow(cs).get())
+}
+
 impl Wake for Task {
     fn wake(self: Arc<Self>) {
         self.wake_by_ref();
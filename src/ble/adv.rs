@@ -0,0 +1,215 @@
+use crate::{ffi, time::Duration};
+
+use super::{Address, Uuid};
+
+const AD_FLAGS: u8 = 0x01;
+const AD_INCOMPLETE_UUID16: u8 = 0x02;
+const AD_COMPLETE_UUID16: u8 = 0x03;
+const AD_INCOMPLETE_UUID128: u8 = 0x06;
+const AD_COMPLETE_UUID128: u8 = 0x07;
+const AD_SHORT_NAME: u8 = 0x08;
+const AD_COMPLETE_NAME: u8 = 0x09;
+const AD_TX_POWER: u8 = 0x0a;
+const AD_SERVICE_DATA_UUID16: u8 = 0x16;
+const AD_MANUFACTURER_DATA: u8 = 0xff;
+
+// LE General Discoverable, BR/EDR not supported.
+const FLAGS_GENERAL_DISCOVERABLE: u8 = 0x06;
+const MAX_AD_LEN: usize = 31;
+
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(100);
+
+// A received advertising or scan response packet.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Advertisement {
+    pub address: Address,
+    pub rssi: i8,
+    pub connectable: bool,
+    pub name: Option<String>,
+    pub services: Vec<Uuid>,
+    pub service_data: Vec<(Uuid, Vec<u8>)>,
+    // Keyed by company identifier.
+    pub manufacturer_data: Vec<(u16, Vec<u8>)>,
+    pub tx_power: Option<i8>,
+    pub raw: Vec<u8>,
+}
+
+impl Advertisement {
+    pub fn advertises(&self, service: Uuid) -> bool {
+        self.services.contains(&service) || self.service_data.iter().any(|(u, _)| *u == service)
+    }
+
+    pub(crate) fn parse(result: ffi::ble::ScanResult, raw: Vec<u8>) -> Self {
+        let mut adv = Self {
+            address: Address::from_ffi(result.address),
+            rssi: result.rssi,
+            connectable: result.connectable,
+            name: None,
+            services: Vec::new(),
+            service_data: Vec::new(),
+            manufacturer_data: Vec::new(),
+            tx_power: None,
+            raw: Vec::new(),
+        };
+
+        for (ty, data) in structures(&raw) {
+            match ty {
+                AD_INCOMPLETE_UUID16 | AD_COMPLETE_UUID16 => adv.services.extend(
+                    data.chunks_exact(2)
+                        .map(|c| Uuid::from_u16(u16::from_le_bytes([c[0], c[1]]))),
+                ),
+                AD_INCOMPLETE_UUID128 | AD_COMPLETE_UUID128 => adv.services.extend(
+                    data.chunks_exact(16)
+                        .map(|c| Uuid::from_u128(u128::from_le_bytes(c.try_into().unwrap()))),
+                ),
+                // A complete name wins over a shortened one, whichever comes first.
+                AD_SHORT_NAME if adv.name.is_none() => {
+                    adv.name = Some(String::from_utf8_lossy(data).into_owned())
+                }
+                AD_COMPLETE_NAME => adv.name = Some(String::from_utf8_lossy(data).into_owned()),
+                AD_TX_POWER => adv.tx_power = data.first().map(|&p| p as i8),
+                AD_SERVICE_DATA_UUID16 if data.len() >= 2 => adv.service_data.push((
+                    Uuid::from_u16(u16::from_le_bytes([data[0], data[1]])),
+                    data[2..].to_vec(),
+                )),
+                AD_MANUFACTURER_DATA if data.len() >= 2 => adv
+                    .manufacturer_data
+                    .push((u16::from_le_bytes([data[0], data[1]]), data[2..].to_vec())),
+                _ => {}
+            }
+        }
+
+        adv.raw = raw;
+        adv
+    }
+}
+
+// Splits advertising data into its (type, data) structures, stopping at the first malformed one.
+fn structures(mut raw: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    std::iter::from_fn(move || loop {
+        let (&len, rest) = raw.split_first()?;
+        let len = len as usize;
+
+        // A zero length marks padding at the end of the packet.
+        if len == 0 || rest.len() < len {
+            return None;
+        }
+
+        let (structure, rest) = rest.split_at(len);
+        raw = rest;
+
+        if let Some((&ty, data)) = structure.split_first() {
+            return Some((ty, data));
+        }
+    })
+}
+
+// What to put in our own advertisements when acting as a peripheral.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct AdvertisingData {
+    name: Option<String>,
+    services: Vec<Uuid>,
+    manufacturer_data: Option<(u16, Vec<u8>)>,
+    interval: Duration,
+}
+
+impl AdvertisingData {
+    pub fn new() -> Self {
+        Self {
+            name: None,
+            services: Vec::new(),
+            manufacturer_data: None,
+            interval: DEFAULT_INTERVAL,
+        }
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn with_service(mut self, service: Uuid) -> Self {
+        self.services.push(service);
+        self
+    }
+
+    pub fn with_manufacturer_data(mut self, company: u16, data: impl Into<Vec<u8>>) -> Self {
+        self.manufacturer_data = Some((company, data.into()));
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub(crate) fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    // Returns the advertising data and scan response. Flags, services and manufacturer data go in
+    // the advertisement and the name goes in the scan response, shortened if it doesn't fit.
+    // Anything else that doesn't fit in 31 bytes is left out.
+    pub(crate) fn encode(&self) -> (Vec<u8>, Vec<u8>) {
+        let mut adv = Vec::new();
+        push(&mut adv, AD_FLAGS, &[FLAGS_GENERAL_DISCOVERABLE]);
+
+        let short = self
+            .services
+            .iter()
+            .filter_map(Uuid::as_u16)
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+        if !short.is_empty() {
+            push(&mut adv, AD_COMPLETE_UUID16, &short);
+        }
+
+        for service in self.services.iter().filter(|s| s.as_u16().is_none()) {
+            push(
+                &mut adv,
+                AD_COMPLETE_UUID128,
+                &service.as_u128().to_le_bytes(),
+            );
+        }
+
+        if let Some((company, data)) = &self.manufacturer_data {
+            let mut payload = company.to_le_bytes().to_vec();
+            payload.extend_from_slice(data);
+            push(&mut adv, AD_MANUFACTURER_DATA, &payload);
+        }
+
+        let mut scan_rsp = Vec::new();
+        if let Some(name) = &self.name {
+            let max = MAX_AD_LEN - 2;
+
+            match name.len() <= max {
+                true => push(&mut scan_rsp, AD_COMPLETE_NAME, name.as_bytes()),
+                false => {
+                    let end = (0..=max)
+                        .rev()
+                        .find(|&i| name.is_char_boundary(i))
+                        .unwrap_or(0);
+                    push(&mut scan_rsp, AD_SHORT_NAME, &name.as_bytes()[..end]);
+                }
+            }
+        }
+
+        (adv, scan_rsp)
+    }
+}
+
+impl Default for AdvertisingData {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn push(out: &mut Vec<u8>, ty: u8, data: &[u8]) {
+    if out.len() + data.len() + 2 > MAX_AD_LEN {
+        return;
+    }
+
+    out.push(data.len() as u8 + 1);
+    out.push(ty);
+    out.extend_from_slice(data);
+}
@@ -0,0 +1,286 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+
+use crate::{
+    asynch::reactor,
+    ffi::{self, io::Handle},
+    net,
+};
+
+use super::{collect, retry, Address, Advertisement, Properties, Uuid};
+
+// Attribute values are at most 512 bytes, and advertising data at most 255 with extended
+// advertising.
+const MAX_VALUE_LEN: usize = 512;
+const MAX_ADV_LEN: usize = 255;
+
+// Starts scanning for advertisements. Active scans also request scan responses, which is
+// usually where the device name is.
+pub fn scan(active: bool) -> io::Result<Scanner> {
+    let mut handle = 0;
+    net::cvt(unsafe { ffi::ble::ble_scan_start(active, &mut handle) } as isize)?;
+
+    Ok(Scanner { handle })
+}
+
+// A stream of advertisements. Scanning stops when this is dropped.
+#[derive(Debug)]
+pub struct Scanner {
+    handle: Handle,
+}
+
+impl Stream for Scanner {
+    type Item = io::Result<Advertisement>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut result = ffi::ble::ScanResult::default();
+        let mut raw = vec![0; MAX_ADV_LEN];
+
+        let ret = unsafe {
+            ffi::ble::ble_scan_next(self.handle, &mut result, raw.as_mut_ptr(), raw.len())
+        };
+
+        match net::cvt(ret) {
+            Ok(len) => {
+                raw.truncate(len);
+                Poll::Ready(Some(Ok(Advertisement::parse(result, raw))))
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                reactor::register_io(cx.waker(), true, false);
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Some(Err(e))),
+        }
+    }
+}
+
+impl Drop for Scanner {
+    fn drop(&mut self) {
+        unsafe { ffi::ble::ble_scan_stop(self.handle) }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Service {
+    uuid: Uuid,
+    start: u16,
+    end: u16,
+}
+
+impl Service {
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Characteristic {
+    uuid: Uuid,
+    handle: u16,
+    properties: Properties,
+}
+
+impl Characteristic {
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    // The attribute handle of the characteristic's value.
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    pub fn properties(&self) -> Properties {
+        self.properties
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Notification {
+    pub handle: u16,
+    pub value: Vec<u8>,
+}
+
+impl Notification {
+    pub fn is_from(&self, characteristic: &Characteristic) -> bool {
+        self.handle == characteristic.handle
+    }
+}
+
+// A connection to a peripheral's GATT server. The link is closed when this is dropped.
+#[derive(Debug)]
+pub struct Connection {
+    handle: Handle,
+    address: Address,
+}
+
+impl Connection {
+    pub async fn connect(address: Address) -> io::Result<Self> {
+        let mut handle = 0;
+        let addr = address.to_ffi();
+        net::cvt(unsafe { ffi::ble::ble_connect(&addr, &mut handle) } as isize)?;
+
+        // Construct it first so a failed connection is still cleaned up.
+        let conn = Self { handle, address };
+        retry(|| unsafe { ffi::ble::ble_connect_status(handle) } as isize).await?;
+
+        Ok(conn)
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    // The negotiated ATT MTU.
+    pub fn mtu(&self) -> io::Result<usize> {
+        net::cvt(unsafe { ffi::ble::ble_mtu(self.handle) } as isize)
+    }
+
+    pub async fn services(&self) -> io::Result<Vec<Service>> {
+        let services =
+            collect(|ptr, len| unsafe { ffi::ble::ble_discover_services(self.handle, ptr, len) })
+                .await?;
+
+        Ok(services
+            .into_iter()
+            .map(|s| Service {
+                uuid: Uuid::from_ffi(s.uuid),
+                start: s.start,
+                end: s.end,
+            })
+            .collect())
+    }
+
+    pub async fn characteristics(&self, service: &Service) -> io::Result<Vec<Characteristic>> {
+        let characteristics = collect(|ptr, len| unsafe {
+            ffi::ble::ble_discover_characteristics(
+                self.handle,
+                service.start,
+                service.end,
+                ptr,
+                len,
+            )
+        })
+        .await?;
+
+        Ok(characteristics
+            .into_iter()
+            .map(|c| Characteristic {
+                uuid: Uuid::from_ffi(c.uuid),
+                handle: c.value_handle,
+                properties: Properties(c.properties),
+            })
+            .collect())
+    }
+
+    // Looks up a single characteristic by its service and characteristic UUIDs.
+    pub async fn characteristic(&self, service: Uuid, uuid: Uuid) -> io::Result<Characteristic> {
+        let not_found = || io::Error::from(io::ErrorKind::NotFound);
+
+        let services = self.services().await?;
+        let service = services
+            .iter()
+            .find(|s| s.uuid == service)
+            .ok_or_else(not_found)?;
+
+        self.characteristics(service)
+            .await?
+            .into_iter()
+            .find(|c| c.uuid == uuid)
+            .ok_or_else(not_found)
+    }
+
+    pub async fn read(&self, characteristic: &Characteristic) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0; MAX_VALUE_LEN];
+        let len = retry(|| unsafe {
+            ffi::ble::ble_read(
+                self.handle,
+                characteristic.handle,
+                buf.as_mut_ptr(),
+                buf.len(),
+            )
+        })
+        .await?;
+        buf.truncate(len);
+
+        Ok(buf)
+    }
+
+    // Writes a value and waits for the peripheral to confirm it.
+    pub async fn write(&self, characteristic: &Characteristic, value: &[u8]) -> io::Result<()> {
+        self.write_inner(characteristic, value, true).await
+    }
+
+    // Writes a value without waiting for a response. The value must fit in MTU - 3 bytes.
+    pub async fn write_without_response(
+        &self,
+        characteristic: &Characteristic,
+        value: &[u8],
+    ) -> io::Result<()> {
+        self.write_inner(characteristic, value, false).await
+    }
+
+    async fn write_inner(
+        &self,
+        characteristic: &Characteristic,
+        value: &[u8],
+        with_response: bool,
+    ) -> io::Result<()> {
+        retry(|| unsafe {
+            ffi::ble::ble_write(
+                self.handle,
+                characteristic.handle,
+                value.as_ptr(),
+                value.len(),
+                with_response,
+            ) as isize
+        })
+        .await
+        .map(drop)
+    }
+
+    // Enables notifications, or indications if that's all the characteristic supports.
+    pub async fn subscribe(&self, characteristic: &Characteristic) -> io::Result<()> {
+        self.set_subscribed(characteristic, true).await
+    }
+
+    pub async fn unsubscribe(&self, characteristic: &Characteristic) -> io::Result<()> {
+        self.set_subscribed(characteristic, false).await
+    }
+
+    async fn set_subscribed(
+        &self,
+        characteristic: &Characteristic,
+        enable: bool,
+    ) -> io::Result<()> {
+        retry(|| unsafe {
+            ffi::ble::ble_subscribe(self.handle, characteristic.handle, enable) as isize
+        })
+        .await
+        .map(drop)
+    }
+
+    // Waits for the next notification or indication from any subscribed characteristic.
+    pub async fn notification(&self) -> io::Result<Notification> {
+        let mut handle = 0;
+        let mut value = vec![0; MAX_VALUE_LEN];
+        let len = retry(|| unsafe {
+            ffi::ble::ble_notification(self.handle, &mut handle, value.as_mut_ptr(), value.len())
+        })
+        .await?;
+        value.truncate(len);
+
+        Ok(Notification { handle, value })
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        unsafe { ffi::ble::ble_disconnect(self.handle) }
+    }
+}
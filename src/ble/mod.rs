@@ -0,0 +1,232 @@
+use std::{fmt, io, ops::BitOr, str::FromStr, task::Poll};
+
+use futures::future::poll_fn;
+
+use crate::{asynch::reactor, ffi, net};
+
+mod adv;
+mod central;
+mod peripheral;
+
+pub use adv::{Advertisement, AdvertisingData};
+pub use central::{scan, Characteristic, Connection, Notification, Scanner, Service};
+pub use peripheral::{advertise, Advertiser, LocalService, ServerEvent, ServiceBuilder};
+
+// The Bluetooth base UUID, 00000000-0000-1000-8000-00805f9b34fb. Short UUIDs are aliases for it
+// with bits 96..128 replaced.
+const BASE_UUID: u128 = 0x0000_0000_0000_1000_8000_0080_5f9b_34fb;
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub struct Uuid(u128);
+
+impl Uuid {
+    pub const HEART_RATE_SERVICE: Self = Self::from_u16(0x180d);
+    pub const HEART_RATE_MEASUREMENT: Self = Self::from_u16(0x2a37);
+    pub const BATTERY_SERVICE: Self = Self::from_u16(0x180f);
+    pub const BATTERY_LEVEL: Self = Self::from_u16(0x2a19);
+    pub const CYCLING_SPEED_AND_CADENCE_SERVICE: Self = Self::from_u16(0x1816);
+    pub const CSC_MEASUREMENT: Self = Self::from_u16(0x2a5b);
+    pub const CYCLING_POWER_SERVICE: Self = Self::from_u16(0x1818);
+    pub const CYCLING_POWER_MEASUREMENT: Self = Self::from_u16(0x2a63);
+
+    pub const fn from_u128(uuid: u128) -> Self {
+        Self(uuid)
+    }
+
+    pub const fn from_u16(uuid: u16) -> Self {
+        Self(BASE_UUID | ((uuid as u128) << 96))
+    }
+
+    pub const fn as_u128(&self) -> u128 {
+        self.0
+    }
+
+    // The short form, if this is an alias of the base UUID.
+    pub const fn as_u16(&self) -> Option<u16> {
+        let short = (self.0 >> 96) as u32;
+
+        match self.0 & !(0xffff_ffff << 96) == BASE_UUID && short <= u16::MAX as u32 {
+            true => Some(short as u16),
+            false => None,
+        }
+    }
+
+    fn to_ffi(self) -> ffi::ble::Uuid {
+        ffi::ble::Uuid {
+            bytes: self.0.to_be_bytes(),
+        }
+    }
+
+    fn from_ffi(uuid: ffi::ble::Uuid) -> Self {
+        Self(u128::from_be_bytes(uuid.bytes))
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = format!("{:032x}", self.0);
+
+        write!(
+            f,
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+}
+
+impl FromStr for Uuid {
+    type Err = io::Error;
+
+    // Accepts either the full hyphenated form or a 4-digit short UUID.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "invalid UUID");
+
+        if s.len() == 4 {
+            return u16::from_str_radix(s, 16)
+                .map(Self::from_u16)
+                .map_err(|_| invalid());
+        }
+
+        let hex = s.replace('-', "");
+        if hex.len() != 32 || s.len() != 36 {
+            return Err(invalid());
+        }
+
+        u128::from_str_radix(&hex, 16)
+            .map(Self)
+            .map_err(|_| invalid())
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub struct Address {
+    bytes: [u8; 6],
+    random: bool,
+}
+
+impl Address {
+    pub const fn new(bytes: [u8; 6], random: bool) -> Self {
+        Self { bytes, random }
+    }
+
+    pub const fn bytes(&self) -> [u8; 6] {
+        self.bytes
+    }
+
+    pub const fn is_random(&self) -> bool {
+        self.random
+    }
+
+    fn to_ffi(self) -> ffi::ble::Address {
+        ffi::ble::Address {
+            bytes: self.bytes,
+            kind: match self.random {
+                true => ffi::ble::ADDRESS_RANDOM,
+                false => ffi::ble::ADDRESS_PUBLIC,
+            },
+        }
+    }
+
+    fn from_ffi(addr: ffi::ble::Address) -> Self {
+        Self {
+            bytes: addr.bytes,
+            random: addr.kind == ffi::ble::ADDRESS_RANDOM,
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.bytes;
+        write!(f, "{a:02X}:{b:02X}:{c:02X}:{d:02X}:{e:02X}:{g:02X}")
+    }
+}
+
+impl FromStr for Address {
+    type Err = io::Error;
+
+    // Parses a public address in the usual colon-separated form.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "invalid Bluetooth address");
+
+        let mut bytes = [0; 6];
+        let mut parts = s.split(':');
+
+        for byte in &mut bytes {
+            let part = parts.next().filter(|p| p.len() == 2).ok_or_else(invalid)?;
+            *byte = u8::from_str_radix(part, 16).map_err(|_| invalid())?;
+        }
+
+        match parts.next() {
+            Some(_) => Err(invalid()),
+            None => Ok(Self::new(bytes, false)),
+        }
+    }
+}
+
+// GATT characteristic properties.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub struct Properties(u8);
+
+impl Properties {
+    pub const BROADCAST: Self = Self(0x01);
+    pub const READ: Self = Self(0x02);
+    pub const WRITE_WITHOUT_RESPONSE: Self = Self(0x04);
+    pub const WRITE: Self = Self(0x08);
+    pub const NOTIFY: Self = Self(0x10);
+    pub const INDICATE: Self = Self(0x20);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn bits(&self) -> u8 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Properties {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+// Calls `f` until it stops returning would-block, waiting for the host's I/O wake in between.
+async fn retry(mut f: impl FnMut() -> isize) -> io::Result<usize> {
+    poll_fn(|cx| match net::cvt(f()) {
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+            reactor::register_io(cx.waker(), true, true);
+            Poll::Pending
+        }
+        res => Poll::Ready(res),
+    })
+    .await
+}
+
+// Fills a growing array through `f`, which returns the total number of available entries.
+async fn collect<T: Copy + Default>(
+    mut f: impl FnMut(*mut T, usize) -> isize,
+) -> io::Result<Vec<T>> {
+    let mut items = vec![T::default(); 8];
+
+    loop {
+        let len = retry(|| f(items.as_mut_ptr(), items.len())).await?;
+
+        if len <= items.len() {
+            items.truncate(len);
+            return Ok(items);
+        }
+
+        items.resize(len, T::default());
+    }
+}
@@ -0,0 +1,228 @@
+use std::{io, ptr};
+
+use crate::{
+    ffi::{self, io::Handle},
+    net,
+};
+
+use super::{retry, Address, AdvertisingData, Properties, Uuid};
+
+const MAX_VALUE_LEN: usize = 512;
+
+// Describes a GATT service to expose while acting as a peripheral.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct ServiceBuilder {
+    uuid: Uuid,
+    characteristics: Vec<(Uuid, Properties, Vec<u8>)>,
+}
+
+impl ServiceBuilder {
+    pub fn new(uuid: Uuid) -> Self {
+        Self {
+            uuid,
+            characteristics: Vec::new(),
+        }
+    }
+
+    pub fn with_characteristic(
+        mut self,
+        uuid: Uuid,
+        properties: Properties,
+        value: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.characteristics.push((uuid, properties, value.into()));
+        self
+    }
+
+    pub fn register(self) -> io::Result<LocalService> {
+        let defs = self
+            .characteristics
+            .iter()
+            .map(|(uuid, properties, value)| ffi::ble::CharacteristicDef {
+                uuid: uuid.to_ffi(),
+                properties: properties.bits(),
+                value_ptr: value.as_ptr(),
+                value_len: value.len(),
+            })
+            .collect::<Vec<_>>();
+
+        let uuid = self.uuid.to_ffi();
+        let mut value_handles = vec![0; defs.len()];
+        let mut handle = 0;
+
+        net::cvt(unsafe {
+            ffi::ble::ble_add_service(
+                &uuid,
+                defs.as_ptr(),
+                defs.len(),
+                value_handles.as_mut_ptr(),
+                &mut handle,
+            )
+        } as isize)?;
+
+        let characteristics = self
+            .characteristics
+            .iter()
+            .map(|(uuid, ..)| *uuid)
+            .zip(value_handles)
+            .collect();
+
+        Ok(LocalService {
+            handle,
+            uuid: self.uuid,
+            characteristics,
+        })
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum ServerEvent {
+    Connected(Address),
+    Disconnected(Address),
+    Write {
+        address: Address,
+        characteristic: Uuid,
+        value: Vec<u8>,
+    },
+    Subscribed {
+        address: Address,
+        characteristic: Uuid,
+    },
+    Unsubscribed {
+        address: Address,
+        characteristic: Uuid,
+    },
+}
+
+// A service registered with the host's GATT server. It is removed when this is dropped.
+#[derive(Debug)]
+pub struct LocalService {
+    handle: Handle,
+    uuid: Uuid,
+    // Characteristic UUIDs and their value handles.
+    characteristics: Vec<(Uuid, u16)>,
+}
+
+impl LocalService {
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    // Updates the value returned to centrals that read the characteristic.
+    pub fn set_value(&self, characteristic: Uuid, value: &[u8]) -> io::Result<()> {
+        let attr = self.value_handle(characteristic)?;
+
+        net::cvt(
+            unsafe { ffi::ble::ble_set_value(self.handle, attr, value.as_ptr(), value.len()) }
+                as isize,
+        )
+        .map(drop)
+    }
+
+    // Sets the value and pushes it to every subscribed central.
+    pub async fn notify(&self, characteristic: Uuid, value: &[u8]) -> io::Result<()> {
+        let attr = self.value_handle(characteristic)?;
+
+        retry(|| unsafe {
+            ffi::ble::ble_notify(self.handle, attr, value.as_ptr(), value.len()) as isize
+        })
+        .await
+        .map(drop)
+    }
+
+    pub async fn next_event(&self) -> io::Result<ServerEvent> {
+        loop {
+            let mut event = ffi::ble::ServerEvent::default();
+            let mut value = vec![0; MAX_VALUE_LEN];
+
+            let len = retry(|| unsafe {
+                ffi::ble::ble_server_event(self.handle, &mut event, value.as_mut_ptr(), value.len())
+            })
+            .await?;
+            value.truncate(len);
+
+            let address = Address::from_ffi(event.address);
+            let characteristic = self
+                .characteristics
+                .iter()
+                .find(|(_, attr)| *attr == event.attr)
+                .map(|(uuid, _)| *uuid);
+
+            let event = match (event.kind, characteristic) {
+                (ffi::ble::SERVER_EVENT_CONNECTED, _) => ServerEvent::Connected(address),
+                (ffi::ble::SERVER_EVENT_DISCONNECTED, _) => ServerEvent::Disconnected(address),
+                (ffi::ble::SERVER_EVENT_WRITE, Some(characteristic)) => ServerEvent::Write {
+                    address,
+                    characteristic,
+                    value,
+                },
+                (ffi::ble::SERVER_EVENT_SUBSCRIBED, Some(characteristic)) => {
+                    ServerEvent::Subscribed {
+                        address,
+                        characteristic,
+                    }
+                }
+                (ffi::ble::SERVER_EVENT_UNSUBSCRIBED, Some(characteristic)) => {
+                    ServerEvent::Unsubscribed {
+                        address,
+                        characteristic,
+                    }
+                }
+                // Events for attributes we don't know about aren't interesting.
+                _ => continue,
+            };
+
+            return Ok(event);
+        }
+    }
+
+    fn value_handle(&self, characteristic: Uuid) -> io::Result<u16> {
+        self.characteristics
+            .iter()
+            .find(|(uuid, _)| *uuid == characteristic)
+            .map(|(_, attr)| *attr)
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+}
+
+impl Drop for LocalService {
+    fn drop(&mut self) {
+        unsafe { ffi::ble::ble_remove_service(self.handle) }
+    }
+}
+
+// Starts advertising. Advertising stops when the returned `Advertiser` is dropped.
+pub fn advertise(data: &AdvertisingData) -> io::Result<Advertiser> {
+    let (adv, scan_rsp) = data.encode();
+    let interval_ms = data.interval().as_millis().min(u32::MAX as u128) as u32;
+    let mut handle = 0;
+
+    let scan_rsp_ptr = match scan_rsp.is_empty() {
+        true => ptr::null(),
+        false => scan_rsp.as_ptr(),
+    };
+
+    net::cvt(unsafe {
+        ffi::ble::ble_advertise_start(
+            adv.as_ptr(),
+            adv.len(),
+            scan_rsp_ptr,
+            scan_rsp.len(),
+            interval_ms,
+            &mut handle,
+        )
+    } as isize)?;
+
+    Ok(Advertiser { handle })
+}
+
+#[derive(Debug)]
+pub struct Advertiser {
+    handle: Handle,
+}
+
+impl Drop for Advertiser {
+    fn drop(&mut self) {
+        unsafe { ffi::ble::ble_advertise_stop(self.handle) }
+    }
+}
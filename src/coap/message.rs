@@ -0,0 +1,334 @@
+use super::Error;
+
+pub(crate) const VERSION: u8 = 1;
+const PAYLOAD_MARKER: u8 = 0xff;
+const MAX_TOKEN_LEN: usize = 8;
+
+pub(crate) const OPTION_URI_HOST: u16 = 3;
+pub(crate) const OPTION_ETAG: u16 = 4;
+pub(crate) const OPTION_OBSERVE: u16 = 6;
+pub(crate) const OPTION_URI_PATH: u16 = 11;
+pub(crate) const OPTION_CONTENT_FORMAT: u16 = 12;
+pub(crate) const OPTION_MAX_AGE: u16 = 14;
+pub(crate) const OPTION_URI_QUERY: u16 = 15;
+pub(crate) const OPTION_ACCEPT: u16 = 17;
+pub(crate) const OPTION_BLOCK2: u16 = 23;
+pub(crate) const OPTION_BLOCK1: u16 = 27;
+pub(crate) const OPTION_SIZE1: u16 = 60;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub(crate) enum Type {
+    Confirmable = 0,
+    NonConfirmable = 1,
+    Acknowledgement = 2,
+    Reset = 3,
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub(crate) struct Message {
+    pub mtype: Type,
+    pub code: u8,
+    pub message_id: u16,
+    pub token: Vec<u8>,
+    // Kept sorted by option number, which the encoding relies on.
+    pub options: Vec<(u16, Vec<u8>)>,
+    pub payload: Vec<u8>,
+}
+
+// The value of a Block1 or Block2 option (RFC 7959).
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub(crate) struct Block {
+    pub num: u32,
+    pub more: bool,
+    // The block size is 2^(szx + 4) bytes.
+    pub szx: u8,
+}
+
+impl Block {
+    pub(crate) fn size(&self) -> usize {
+        1 << (self.szx + 4)
+    }
+
+    pub(crate) fn offset(&self) -> usize {
+        self.num as usize * self.size()
+    }
+
+    pub(crate) fn szx_for(size: usize) -> u8 {
+        (size.clamp(16, 1024).ilog2() - 4) as u8
+    }
+
+    fn from_uint(value: u32) -> Option<Self> {
+        let szx = (value & 0x07) as u8;
+
+        // SZX 7 is reserved.
+        (szx != 7).then_some(Self {
+            num: value >> 4,
+            more: value & 0x08 != 0,
+            szx,
+        })
+    }
+
+    fn to_uint(self) -> u32 {
+        (self.num << 4) | ((self.more as u32) << 3) | self.szx as u32
+    }
+}
+
+impl Message {
+    pub(crate) fn new(mtype: Type, code: u8, message_id: u16, token: Vec<u8>) -> Self {
+        Self {
+            mtype,
+            code,
+            message_id,
+            token,
+            options: Vec::new(),
+            payload: Vec::new(),
+        }
+    }
+
+    // An empty message, used for bare ACKs and RSTs.
+    pub(crate) fn empty(mtype: Type, message_id: u16) -> Self {
+        Self::new(mtype, 0, message_id, Vec::new())
+    }
+
+    pub(crate) fn option(&self, number: u16) -> Option<&[u8]> {
+        self.options
+            .iter()
+            .find(|(n, _)| *n == number)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    pub(crate) fn uint_option(&self, number: u16) -> Option<u32> {
+        self.option(number).map(|value| {
+            value
+                .iter()
+                .take(4)
+                .fold(0, |acc, &b| (acc << 8) | b as u32)
+        })
+    }
+
+    pub(crate) fn block_option(&self, number: u16) -> Option<Block> {
+        self.uint_option(number).and_then(Block::from_uint)
+    }
+
+    pub(crate) fn add_option(&mut self, number: u16, value: impl Into<Vec<u8>>) {
+        // Insert after any existing options with the same number, so repeated options keep their
+        // order.
+        let idx = self.options.partition_point(|(n, _)| *n <= number);
+        self.options.insert(idx, (number, value.into()));
+    }
+
+    // Uints are encoded with as few bytes as possible, so zero is an empty value.
+    pub(crate) fn add_uint_option(&mut self, number: u16, value: u32) {
+        let bytes = value.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&b| b == 0).count();
+
+        self.add_option(number, &bytes[skip..]);
+    }
+
+    pub(crate) fn set_uint_option(&mut self, number: u16, value: u32) {
+        self.remove_option(number);
+        self.add_uint_option(number, value);
+    }
+
+    pub(crate) fn set_block_option(&mut self, number: u16, block: Block) {
+        self.set_uint_option(number, block.to_uint());
+    }
+
+    pub(crate) fn remove_option(&mut self, number: u16) {
+        self.options.retain(|(n, _)| *n != number);
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(4 + self.token.len() + self.payload.len() + 32);
+
+        out.push((VERSION << 6) | ((self.mtype as u8) << 4) | self.token.len() as u8);
+        out.push(self.code);
+        out.extend_from_slice(&self.message_id.to_be_bytes());
+        out.extend_from_slice(&self.token);
+
+        let mut last = 0;
+
+        for (number, value) in &self.options {
+            let (delta, delta_ext) = split_nibble(number - last);
+            let (len, len_ext) = split_nibble(value.len() as u16);
+
+            out.push((delta << 4) | len);
+            out.extend_from_slice(&delta_ext);
+            out.extend_from_slice(&len_ext);
+            out.extend_from_slice(value);
+
+            last = *number;
+        }
+
+        if !self.payload.is_empty() {
+            out.push(PAYLOAD_MARKER);
+            out.extend_from_slice(&self.payload);
+        }
+
+        out
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self, Error> {
+        let [first, code, id_hi, id_lo, rest @ ..] = buf else {
+            return Err(Error::Malformed("truncated header"));
+        };
+
+        if first >> 6 != VERSION {
+            return Err(Error::Malformed("unknown version"));
+        }
+
+        let mtype = match (first >> 4) & 0x03 {
+            0 => Type::Confirmable,
+            1 => Type::NonConfirmable,
+            2 => Type::Acknowledgement,
+            _ => Type::Reset,
+        };
+
+        let token_len = (first & 0x0f) as usize;
+        if token_len > MAX_TOKEN_LEN || rest.len() < token_len {
+            return Err(Error::Malformed("invalid token length"));
+        }
+
+        let (token, mut rest) = rest.split_at(token_len);
+        let mut message = Self::new(
+            mtype,
+            *code,
+            u16::from_be_bytes([*id_hi, *id_lo]),
+            token.to_vec(),
+        );
+
+        let mut number = 0u16;
+
+        while let [byte, tail @ ..] = rest {
+            if *byte == PAYLOAD_MARKER {
+                if tail.is_empty() {
+                    return Err(Error::Malformed("payload marker without payload"));
+                }
+
+                message.payload = tail.to_vec();
+                break;
+            }
+
+            let (delta, tail) = read_nibble(byte >> 4, tail)?;
+            let (len, tail) = read_nibble(byte & 0x0f, tail)?;
+
+            number = number
+                .checked_add(delta)
+                .ok_or(Error::Malformed("option number overflow"))?;
+
+            let value = tail
+                .get(..len as usize)
+                .ok_or(Error::Malformed("truncated option"))?;
+
+            message.options.push((number, value.to_vec()));
+            rest = &tail[len as usize..];
+        }
+
+        Ok(message)
+    }
+}
+
+// Splits an option delta or length into its 4-bit header nibble and extended bytes.
+fn split_nibble(value: u16) -> (u8, Vec<u8>) {
+    match value {
+        0..=12 => (value as u8, Vec::new()),
+        13..=268 => (13, vec![(value - 13) as u8]),
+        _ => (14, (value - 269).to_be_bytes().to_vec()),
+    }
+}
+
+fn read_nibble(nibble: u8, buf: &[u8]) -> Result<(u16, &[u8]), Error> {
+    match (nibble, buf) {
+        (0..=12, buf) => Ok((nibble as u16, buf)),
+        (13, [ext, rest @ ..]) => Ok((*ext as u16 + 13, rest)),
+        (14, [hi, lo, rest @ ..]) => u16::from_be_bytes([*hi, *lo])
+            .checked_add(269)
+            .map(|value| (value, rest))
+            .ok_or(Error::Malformed("option value overflow")),
+        (15, _) => Err(Error::Malformed("reserved option nibble")),
+        _ => Err(Error::Malformed("truncated option")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut message = Message::new(Type::Confirmable, 0x01, 0xbeef, vec![1, 2, 3, 4]);
+        message.add_option(OPTION_URI_PATH, "sensors");
+        message.add_option(OPTION_URI_HOST, "example.com");
+        message.add_option(OPTION_URI_PATH, "temp");
+        message.add_uint_option(OPTION_OBSERVE, 0);
+        message.add_uint_option(OPTION_CONTENT_FORMAT, 50);
+        message.add_option(OPTION_ETAG, vec![0xaa; 300]);
+        // Deltas that need one and two extension bytes.
+        message.add_option(300, "x");
+        message.add_option(2000, "y");
+        message.payload = b"payload".to_vec();
+
+        let decoded = Message::decode(&message.encode()).unwrap();
+        assert_eq!(decoded, message);
+
+        let paths = decoded
+            .options
+            .iter()
+            .filter(|(n, _)| *n == OPTION_URI_PATH)
+            .map(|(_, v)| v.as_slice())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, [&b"sensors"[..], b"temp"]);
+        assert_eq!(decoded.uint_option(OPTION_OBSERVE), Some(0));
+        assert_eq!(decoded.uint_option(OPTION_CONTENT_FORMAT), Some(50));
+    }
+
+    #[test]
+    fn empty() {
+        let message = Message::empty(Type::Acknowledgement, 0x1234);
+
+        assert_eq!(message.encode(), [0x60, 0x00, 0x12, 0x34]);
+        assert_eq!(Message::decode(&message.encode()).unwrap(), message);
+    }
+
+    #[test]
+    fn blocks() {
+        let mut message = Message::empty(Type::Confirmable, 0);
+        let block = Block {
+            num: 70_000,
+            more: true,
+            szx: Block::szx_for(1024),
+        };
+
+        message.set_block_option(OPTION_BLOCK2, Block { num: 1, ..block });
+        message.set_block_option(OPTION_BLOCK2, block);
+
+        let decoded = Message::decode(&message.encode()).unwrap();
+        assert_eq!(decoded.block_option(OPTION_BLOCK2), Some(block));
+        assert_eq!(block.size(), 1024);
+        assert_eq!(block.offset(), 70_000 * 1024);
+        assert_eq!(Block::szx_for(100), 2);
+
+        // SZX 7 is reserved.
+        message.set_uint_option(OPTION_BLOCK1, 0x17);
+        assert_eq!(message.block_option(OPTION_BLOCK1), None);
+    }
+
+    #[test]
+    fn invalid() {
+        let cases: &[&[u8]] = &[
+            &[0x40, 0, 0],
+            // Version 2, and a token longer than 8 bytes.
+            &[0x80, 0, 0, 0],
+            &[0x49, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+            // Payload marker with nothing after it, and the reserved option nibble.
+            &[0x40, 0, 0, 0, 0xff],
+            &[0x40, 0, 0, 0, 0xf0],
+            // An option longer than the message.
+            &[0x40, 0, 0, 0, 0x13, b'a'],
+        ];
+
+        for case in cases {
+            assert!(Message::decode(case).is_err(), "{case:?}");
+        }
+    }
+}
@@ -0,0 +1,728 @@
+use std::{fmt, io, str::Utf8Error};
+
+use thiserror::Error;
+
+use crate::{
+    net::{self, IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    rng,
+    time::{self, Duration, Instant},
+};
+
+mod message;
+
+use message::{
+    Block, Message, Type, OPTION_ACCEPT, OPTION_BLOCK1, OPTION_BLOCK2, OPTION_CONTENT_FORMAT,
+    OPTION_ETAG, OPTION_MAX_AGE, OPTION_OBSERVE, OPTION_SIZE1, OPTION_URI_HOST, OPTION_URI_PATH,
+    OPTION_URI_QUERY,
+};
+
+pub const DEFAULT_PORT: u16 = 5683;
+
+pub const FORMAT_TEXT: u16 = 0;
+pub const FORMAT_LINK: u16 = 40;
+pub const FORMAT_XML: u16 = 41;
+pub const FORMAT_OCTET_STREAM: u16 = 42;
+pub const FORMAT_JSON: u16 = 50;
+pub const FORMAT_CBOR: u16 = 60;
+
+// Transmission parameters from RFC 7252 section 4.8.
+const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_secs(2);
+const DEFAULT_MAX_RETRANSMIT: u32 = 4;
+const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_BLOCK_SIZE: usize = 512;
+const DEFAULT_MAX_BODY_SIZE: usize = 64 * 1024;
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(60);
+// Extra time given to the server to send a notification before we re-register an observation.
+const MAX_AGE_MARGIN: Duration = Duration::from_secs(10);
+// Notifications older than this are always considered fresh (RFC 7641 section 3.4).
+const OBSERVE_FRESHNESS: Duration = Duration::from_secs(128);
+const MAX_DATAGRAM: usize = 1500;
+const TOKEN_LEN: usize = 4;
+
+#[repr(u8)]
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum Method {
+    Get = 1,
+    Post = 2,
+    Put = 3,
+    Delete = 4,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Code(u8);
+
+impl Code {
+    pub const CREATED: Self = Self::new(2, 1);
+    pub const DELETED: Self = Self::new(2, 2);
+    pub const VALID: Self = Self::new(2, 3);
+    pub const CHANGED: Self = Self::new(2, 4);
+    pub const CONTENT: Self = Self::new(2, 5);
+    pub const CONTINUE: Self = Self::new(2, 31);
+    pub const BAD_REQUEST: Self = Self::new(4, 0);
+    pub const UNAUTHORIZED: Self = Self::new(4, 1);
+    pub const FORBIDDEN: Self = Self::new(4, 3);
+    pub const NOT_FOUND: Self = Self::new(4, 4);
+    pub const METHOD_NOT_ALLOWED: Self = Self::new(4, 5);
+    pub const REQUEST_ENTITY_INCOMPLETE: Self = Self::new(4, 8);
+    pub const REQUEST_ENTITY_TOO_LARGE: Self = Self::new(4, 13);
+    pub const INTERNAL_SERVER_ERROR: Self = Self::new(5, 0);
+    pub const SERVICE_UNAVAILABLE: Self = Self::new(5, 3);
+
+    pub const fn new(class: u8, detail: u8) -> Self {
+        Self((class << 5) | (detail & 0x1f))
+    }
+
+    pub const fn class(&self) -> u8 {
+        self.0 >> 5
+    }
+
+    pub const fn detail(&self) -> u8 {
+        self.0 & 0x1f
+    }
+
+    pub const fn is_success(&self) -> bool {
+        self.class() == 2
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:02}", self.class(), self.detail())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("server did not respond in time")]
+    Timeout,
+    #[error("server reset the exchange")]
+    Reset,
+    #[error("malformed message: {0}")]
+    Malformed(&'static str),
+    #[error("resource changed during a block-wise transfer")]
+    ResourceChanged,
+    #[error("response body exceeds the limit of {limit} bytes")]
+    BodyTooLarge { limit: usize },
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Request {
+    method: Method,
+    path: String,
+    queries: Vec<String>,
+    content_format: Option<u16>,
+    accept: Option<u16>,
+    payload: Vec<u8>,
+    confirmable: bool,
+}
+
+impl Request {
+    pub fn new(method: Method, path: &str) -> Self {
+        let (path, queries) = match path.split_once('?') {
+            Some((path, query)) => (path, query.split('&').map(str::to_owned).collect()),
+            None => (path, Vec::new()),
+        };
+
+        Self {
+            method,
+            path: path.to_owned(),
+            queries,
+            content_format: None,
+            accept: None,
+            payload: Vec::new(),
+            confirmable: true,
+        }
+    }
+
+    pub fn get(path: &str) -> Self {
+        Self::new(Method::Get, path)
+    }
+
+    pub fn post(path: &str) -> Self {
+        Self::new(Method::Post, path)
+    }
+
+    pub fn put(path: &str) -> Self {
+        Self::new(Method::Put, path)
+    }
+
+    pub fn delete(path: &str) -> Self {
+        Self::new(Method::Delete, path)
+    }
+
+    pub fn with_query(mut self, query: impl Into<String>) -> Self {
+        self.queries.push(query.into());
+        self
+    }
+
+    pub fn with_content_format(mut self, content_format: u16) -> Self {
+        self.content_format = Some(content_format);
+        self
+    }
+
+    pub fn with_accept(mut self, accept: u16) -> Self {
+        self.accept = Some(accept);
+        self
+    }
+
+    pub fn with_payload(mut self, payload: impl Into<Vec<u8>>) -> Self {
+        self.payload = payload.into();
+        self
+    }
+
+    // Non-confirmable requests are sent once and never retransmitted.
+    pub fn with_confirmable(mut self, confirmable: bool) -> Self {
+        self.confirmable = confirmable;
+        self
+    }
+
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Response {
+    code: Code,
+    content_format: Option<u16>,
+    max_age: Option<u32>,
+    etag: Option<Vec<u8>>,
+    payload: Vec<u8>,
+}
+
+impl Response {
+    fn from_message(message: &Message, payload: Vec<u8>) -> Self {
+        Self {
+            code: Code(message.code),
+            content_format: message.uint_option(OPTION_CONTENT_FORMAT).map(|f| f as u16),
+            max_age: message.uint_option(OPTION_MAX_AGE),
+            etag: message.option(OPTION_ETAG).map(<[u8]>::to_vec),
+            payload,
+        }
+    }
+
+    pub fn code(&self) -> Code {
+        self.code
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.code.is_success()
+    }
+
+    pub fn content_format(&self) -> Option<u16> {
+        self.content_format
+    }
+
+    // How long the response may be cached for, in seconds.
+    pub fn max_age(&self) -> Option<u32> {
+        self.max_age
+    }
+
+    pub fn etag(&self) -> Option<&[u8]> {
+        self.etag.as_deref()
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    pub fn text(&self) -> Result<&str, Utf8Error> {
+        std::str::from_utf8(&self.payload)
+    }
+
+    pub fn into_payload(self) -> Vec<u8> {
+        self.payload
+    }
+}
+
+// A CoAP client talking to a single server. Exchanges are performed one at a time, so responses
+// are matched to requests by token and anything else the server sends is rejected with a reset.
+pub struct Client {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    // Only sent when the server is addressed by name, as it may be hosting several virtual
+    // servers.
+    host: Option<String>,
+    message_id: u16,
+    ack_timeout: Duration,
+    max_retransmit: u32,
+    response_timeout: Duration,
+    block_size: usize,
+    max_body_size: usize,
+}
+
+impl Client {
+    pub fn new(host: &str, port: u16) -> Result<Self, Error> {
+        let peer = net::lookup_host(host, port)?;
+
+        let local = match peer {
+            SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+            SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+        };
+
+        Ok(Self {
+            socket: UdpSocket::bind(local)?,
+            peer,
+            host: host.parse::<IpAddr>().is_err().then(|| host.to_owned()),
+            message_id: rng::random(),
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            max_retransmit: DEFAULT_MAX_RETRANSMIT,
+            response_timeout: DEFAULT_RESPONSE_TIMEOUT,
+            block_size: DEFAULT_BLOCK_SIZE,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        })
+    }
+
+    pub fn with_ack_timeout(mut self, ack_timeout: Duration) -> Self {
+        self.ack_timeout = ack_timeout;
+        self
+    }
+
+    pub fn with_max_retransmit(mut self, max_retransmit: u32) -> Self {
+        self.max_retransmit = max_retransmit;
+        self
+    }
+
+    // How long to wait for a separate response once the server has acknowledged a request.
+    pub fn with_response_timeout(mut self, response_timeout: Duration) -> Self {
+        self.response_timeout = response_timeout;
+        self
+    }
+
+    // The preferred block size for block-wise transfers, rounded down to a power of two between
+    // 16 and 1024 bytes. The server may ask for smaller blocks.
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = 1 << (Block::szx_for(block_size) + 4);
+        self
+    }
+
+    pub fn with_max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    pub async fn get(&mut self, path: &str) -> Result<Response, Error> {
+        self.send(&Request::get(path)).await
+    }
+
+    pub async fn post(
+        &mut self,
+        path: &str,
+        content_format: u16,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<Response, Error> {
+        let request = Request::post(path)
+            .with_content_format(content_format)
+            .with_payload(payload);
+
+        self.send(&request).await
+    }
+
+    pub async fn put(
+        &mut self,
+        path: &str,
+        content_format: u16,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<Response, Error> {
+        let request = Request::put(path)
+            .with_content_format(content_format)
+            .with_payload(payload);
+
+        self.send(&request).await
+    }
+
+    pub async fn delete(&mut self, path: &str) -> Result<Response, Error> {
+        self.send(&Request::delete(path)).await
+    }
+
+    // Sends a request, splitting large payloads into Block1 transfers and reassembling Block2
+    // responses.
+    pub async fn send(&mut self, request: &Request) -> Result<Response, Error> {
+        let message = self.build(request);
+        let reply = self.upload(&message, &request.payload).await?;
+
+        self.download(&message, reply).await
+    }
+
+    // Registers for notifications about a resource (RFC 7641). The first item the observation
+    // yields is the current state of the resource.
+    pub async fn observe(&mut self, request: &Request) -> Result<Observation<'_>, Error> {
+        let mut message = self.build(request);
+        message.set_uint_option(OPTION_OBSERVE, 0);
+
+        let reply = self.transmit(message.clone()).await?;
+        let sequence = reply.uint_option(OPTION_OBSERVE);
+        let registered = sequence.is_some() && Code(reply.code).is_success();
+        let refresh_at = Instant::now() + max_age(&reply) + MAX_AGE_MARGIN;
+
+        let first = self.download(&message, reply).await?;
+
+        Ok(Observation {
+            client: self,
+            request: message,
+            registered,
+            pending: Some(first),
+            last: sequence.map(|s| (s, Instant::now())),
+            refresh_at,
+        })
+    }
+
+    fn build(&mut self, request: &Request) -> Message {
+        let mtype = match request.confirmable {
+            true => Type::Confirmable,
+            false => Type::NonConfirmable,
+        };
+
+        let mut message = Message::new(mtype, request.method as u8, 0, new_token());
+
+        if let Some(host) = &self.host {
+            message.add_option(OPTION_URI_HOST, host.as_bytes());
+        }
+        for segment in request.path.split('/').filter(|s| !s.is_empty()) {
+            message.add_option(OPTION_URI_PATH, segment.as_bytes());
+        }
+        for query in &request.queries {
+            message.add_option(OPTION_URI_QUERY, query.as_bytes());
+        }
+        if let Some(format) = request.content_format {
+            message.add_uint_option(OPTION_CONTENT_FORMAT, format as u32);
+        }
+        if let Some(accept) = request.accept {
+            message.add_uint_option(OPTION_ACCEPT, accept as u32);
+        }
+
+        message
+    }
+
+    // Sends the request payload, using Block1 if it doesn't fit in a single block, and returns the
+    // server's final reply.
+    async fn upload(&mut self, message: &Message, payload: &[u8]) -> Result<Message, Error> {
+        if payload.len() <= self.block_size {
+            let mut message = message.clone();
+            message.payload = payload.to_vec();
+
+            return self.transmit(message).await;
+        }
+
+        let mut szx = Block::szx_for(self.block_size);
+        let mut offset = 0;
+
+        loop {
+            let size = 1 << (szx + 4);
+            let end = (offset + size).min(payload.len());
+            let block = Block {
+                num: (offset / size) as u32,
+                more: end < payload.len(),
+                szx,
+            };
+
+            let mut part = message.clone();
+            part.token = new_token();
+            part.set_block_option(OPTION_BLOCK1, block);
+            if block.num == 0 {
+                part.set_uint_option(OPTION_SIZE1, payload.len() as u32);
+            }
+            part.payload = payload[offset..end].to_vec();
+
+            let reply = self.transmit(part).await?;
+
+            // Anything other than 2.31 Continue before the last block is the server giving up,
+            // which the caller gets to see as the response.
+            if !block.more || Code(reply.code) != Code::CONTINUE {
+                return Ok(reply);
+            }
+
+            // The server may ask for smaller blocks. Block sizes are powers of two, so the offset
+            // stays aligned.
+            if let Some(requested) = reply.block_option(OPTION_BLOCK1) {
+                szx = szx.min(requested.szx);
+            }
+
+            offset = end;
+        }
+    }
+
+    // Fetches the remaining blocks of a Block2 response and builds the final response.
+    async fn download(&mut self, request: &Message, reply: Message) -> Result<Response, Error> {
+        let mut payload = reply.payload.clone();
+        let mut block = reply.block_option(OPTION_BLOCK2);
+        let etag = reply.option(OPTION_ETAG).map(<[u8]>::to_vec);
+
+        while let Some(current) = block.filter(|b| b.more && Code(reply.code).is_success()) {
+            if payload.len() + current.size() > self.max_body_size {
+                return Err(Error::BodyTooLarge {
+                    limit: self.max_body_size,
+                });
+            }
+
+            let mut next = request.clone();
+            next.token = new_token();
+            next.payload.clear();
+            next.remove_option(OPTION_BLOCK1);
+            next.remove_option(OPTION_SIZE1);
+            next.remove_option(OPTION_OBSERVE);
+            next.set_block_option(
+                OPTION_BLOCK2,
+                Block {
+                    num: (payload.len() / current.size()) as u32,
+                    more: false,
+                    szx: current.szx,
+                },
+            );
+
+            let part = self.transmit(next).await?;
+            if !Code(part.code).is_success() {
+                return Ok(Response::from_message(&part, part.payload.clone()));
+            }
+
+            if part.option(OPTION_ETAG).map(<[u8]>::to_vec) != etag {
+                return Err(Error::ResourceChanged);
+            }
+
+            let received = part
+                .block_option(OPTION_BLOCK2)
+                .ok_or(Error::Malformed("missing Block2 option"))?;
+            if received.offset() != payload.len() {
+                return Err(Error::Malformed("unexpected Block2 offset"));
+            }
+
+            payload.extend_from_slice(&part.payload);
+            block = Some(received);
+        }
+
+        Ok(Response::from_message(&reply, payload))
+    }
+
+    // Sends a single message and waits for the reply carrying its token. Confirmable messages are
+    // retransmitted with an exponential backoff until the server acknowledges them.
+    async fn transmit(&mut self, mut message: Message) -> Result<Message, Error> {
+        self.message_id = self.message_id.wrapping_add(1);
+        message.message_id = self.message_id;
+
+        let encoded = message.encode();
+        let mut acknowledged = message.mtype != Type::Confirmable;
+
+        // The initial timeout is randomized between ACK_TIMEOUT and 1.5 times that, so many
+        // clients don't retransmit in lockstep.
+        let mut timeout = self.ack_timeout.mul_f32(1.0 + rng::random::<f32>() * 0.5);
+        let mut retransmits = 0;
+        let mut deadline = Instant::now()
+            + match acknowledged {
+                true => self.response_timeout,
+                false => timeout,
+            };
+
+        self.socket.send_to(&encoded, self.peer).await?;
+
+        loop {
+            let Some(reply) = self.recv_until(deadline).await? else {
+                if acknowledged || retransmits >= self.max_retransmit {
+                    return Err(Error::Timeout);
+                }
+
+                retransmits += 1;
+                timeout *= 2;
+                deadline = Instant::now() + timeout;
+
+                self.socket.send_to(&encoded, self.peer).await?;
+                continue;
+            };
+
+            let is_reply = reply.message_id == message.message_id
+                && matches!(reply.mtype, Type::Acknowledgement | Type::Reset);
+
+            match reply.mtype {
+                Type::Reset if is_reply => return Err(Error::Reset),
+                // An empty ACK means the response will follow separately.
+                Type::Acknowledgement if is_reply && reply.code == 0 => {
+                    acknowledged = true;
+                    deadline = Instant::now() + self.response_timeout;
+                }
+                Type::Acknowledgement if is_reply && reply.token == message.token => {
+                    return Ok(reply)
+                }
+                Type::Confirmable | Type::NonConfirmable
+                    if reply.code != 0 && reply.token == message.token =>
+                {
+                    if reply.mtype == Type::Confirmable {
+                        self.acknowledge(&reply).await?;
+                    }
+
+                    return Ok(reply);
+                }
+                _ => self.reject(&reply).await?,
+            }
+        }
+    }
+
+    async fn recv_until(&self, deadline: Instant) -> Result<Option<Message>, Error> {
+        let mut buf = [0; MAX_DATAGRAM];
+
+        loop {
+            let Ok(res) = time::timeout_at(deadline, self.socket.recv_from(&mut buf)).await else {
+                return Ok(None);
+            };
+            let (len, from) = res?;
+
+            // Garbage and datagrams from other hosts are silently dropped.
+            if from != self.peer {
+                continue;
+            }
+            if let Ok(message) = Message::decode(&buf[..len]) {
+                return Ok(Some(message));
+            }
+        }
+    }
+
+    async fn acknowledge(&self, message: &Message) -> io::Result<()> {
+        let ack = Message::empty(Type::Acknowledgement, message.message_id);
+        self.socket.send_to(&ack.encode(), self.peer).await?;
+
+        Ok(())
+    }
+
+    // Resets requests and notifications that don't belong to the current exchange. This is also
+    // how the server learns about observations that have been dropped.
+    async fn reject(&self, message: &Message) -> io::Result<()> {
+        if matches!(message.mtype, Type::Confirmable | Type::NonConfirmable) && message.code != 0 {
+            let reset = Message::empty(Type::Reset, message.message_id);
+            self.socket.send_to(&reset.encode(), self.peer).await?;
+        }
+
+        Ok(())
+    }
+}
+
+// An active observation of a resource. The client is borrowed for as long as the observation
+// lives. Dropping it without calling `cancel` makes the client reset the next notification,
+// which cancels the observation on the server as well.
+pub struct Observation<'a> {
+    client: &'a mut Client,
+    request: Message,
+    registered: bool,
+    pending: Option<Response>,
+    last: Option<(u32, Instant)>,
+    refresh_at: Instant,
+}
+
+impl Observation<'_> {
+    // Whether the server accepted the registration and hasn't ended it since.
+    pub fn is_registered(&self) -> bool {
+        self.registered
+    }
+
+    // Waits for the next notification. Returns `None` once the server has ended the observation,
+    // or if it never accepted it in the first place.
+    pub async fn next(&mut self) -> Result<Option<Response>, Error> {
+        if let Some(response) = self.pending.take() {
+            return Ok(Some(response));
+        }
+
+        while self.registered {
+            let notification = match self.client.recv_until(self.refresh_at).await? {
+                Some(message) => message,
+                // The server went quiet for longer than the last Max-Age, so it may have lost
+                // track of us. Registering again with the same token is harmless if it hasn't.
+                None => self.client.transmit(self.request.clone()).await?,
+            };
+
+            if notification.token != self.request.token
+                || notification.code == 0
+                || !matches!(
+                    notification.mtype,
+                    Type::Confirmable | Type::NonConfirmable | Type::Acknowledgement
+                )
+            {
+                self.client.reject(&notification).await?;
+                continue;
+            }
+
+            if notification.mtype == Type::Confirmable {
+                self.client.acknowledge(&notification).await?;
+            }
+
+            if let Some(response) = self.accept(notification).await? {
+                return Ok(Some(response));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Deregisters from the server.
+    pub async fn cancel(self) -> Result<(), Error> {
+        if !self.registered {
+            return Ok(());
+        }
+
+        let mut request = self.request;
+        request.set_uint_option(OPTION_OBSERVE, 1);
+        self.client.transmit(request).await?;
+
+        Ok(())
+    }
+
+    async fn accept(&mut self, notification: Message) -> Result<Option<Response>, Error> {
+        let Some(sequence) = notification.uint_option(OPTION_OBSERVE) else {
+            // A response without the Observe option, or an error, ends the observation.
+            self.registered = false;
+            return self
+                .client
+                .download(&self.request, notification)
+                .await
+                .map(Some);
+        };
+
+        if !Code(notification.code).is_success() {
+            self.registered = false;
+        }
+
+        if let Some((last, at)) = self.last {
+            if !is_fresh(last, at, sequence) {
+                return Ok(None);
+            }
+        }
+
+        self.last = Some((sequence, Instant::now()));
+        self.refresh_at = Instant::now() + max_age(&notification) + MAX_AGE_MARGIN;
+
+        self.client
+            .download(&self.request, notification)
+            .await
+            .map(Some)
+    }
+}
+
+fn new_token() -> Vec<u8> {
+    rng::random::<[u8; TOKEN_LEN]>().to_vec()
+}
+
+fn max_age(message: &Message) -> Duration {
+    message
+        .uint_option(OPTION_MAX_AGE)
+        .map_or(DEFAULT_MAX_AGE, |s| Duration::from_secs(s as u64))
+}
+
+// Notifications can be reordered in transit. A notification is newer if its 24-bit sequence
+// number is ahead of the last one, modulo wraparound (RFC 7641 section 3.4).
+fn is_fresh(last: u32, received_at: Instant, sequence: u32) -> bool {
+    const HALF: u32 = 1 << 23;
+
+    (last < sequence && sequence - last < HALF)
+        || (last > sequence && last - sequence > HALF)
+        || received_at.elapsed() > OBSERVE_FRESHNESS
+}
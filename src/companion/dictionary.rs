@@ -0,0 +1,349 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::Error;
+
+const TYPE_BYTES: u8 = 0;
+const TYPE_STRING: u8 = 1;
+const TYPE_UINT: u8 = 2;
+const TYPE_INT: u8 = 3;
+
+const MESSAGE_DICTIONARY: u8 = 0;
+const MESSAGE_DATA: u8 = 1;
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Value {
+    Bytes(Vec<u8>),
+    String(String),
+    UInt(u64),
+    Int(i64),
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(value: Vec<u8>) -> Self {
+        Self::Bytes(value)
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(value: &[u8]) -> Self {
+        Self::Bytes(value.to_vec())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_owned())
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::UInt(value as u64)
+    }
+}
+
+macro_rules! impl_from_int {
+    ($variant:ident: $($ty:ty),*) => {
+        $(
+            impl From<$ty> for Value {
+                fn from(value: $ty) -> Self {
+                    Self::$variant(value.into())
+                }
+            }
+        )*
+    };
+}
+
+impl_from_int!(UInt: u8, u16, u32, u64);
+impl_from_int!(Int: i8, i16, i32, i64);
+
+// Key-value pairs with integer keys, in the spirit of Pebble's AppMessage dictionaries. Keys are
+// kept in insertion order.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Dictionary(Vec<(u32, Value)>);
+
+impl Dictionary {
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn with(mut self, key: u32, value: impl Into<Value>) -> Self {
+        self.insert(key, value);
+        self
+    }
+
+    pub fn insert(&mut self, key: u32, value: impl Into<Value>) {
+        let value = value.into();
+
+        match self.0.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => *v = value,
+            None => self.0.push((key, value)),
+        }
+    }
+
+    pub fn get(&self, key: u32) -> Option<&Value> {
+        self.0.iter().find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    pub fn get_str(&self, key: u32) -> Option<&str> {
+        match self.get(key)? {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn get_bytes(&self, key: u32) -> Option<&[u8]> {
+        match self.get(key)? {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn get_int(&self, key: u32) -> Option<i64> {
+        match *self.get(key)? {
+            Value::Int(i) => Some(i),
+            Value::UInt(u) => i64::try_from(u).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn get_uint(&self, key: u32) -> Option<u64> {
+        match *self.get(key)? {
+            Value::UInt(u) => Some(u),
+            Value::Int(i) => u64::try_from(i).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn remove(&mut self, key: u32) -> Option<Value> {
+        let idx = self.0.iter().position(|(k, _)| *k == key)?;
+        Some(self.0.remove(idx).1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &Value)> {
+        self.0.iter().map(|(k, v)| (*k, v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // Each entry is the key as a little-endian u32, a type byte, the value length as a
+    // little-endian u16 and the value. Integers use the smallest of 1, 2, 4 or 8 bytes.
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), Error> {
+        for (key, value) in &self.0 {
+            let (ty, bytes) = match value {
+                Value::Bytes(b) => (TYPE_BYTES, b.clone()),
+                Value::String(s) => (TYPE_STRING, s.as_bytes().to_vec()),
+                Value::UInt(u) => {
+                    let width = int_width(64 - u.leading_zeros());
+                    (TYPE_UINT, u.to_le_bytes()[..width].to_vec())
+                }
+                Value::Int(i) => {
+                    // One extra bit is needed for the sign.
+                    let redundant = match *i < 0 {
+                        true => i.leading_ones(),
+                        false => i.leading_zeros(),
+                    };
+                    let width = int_width(65 - redundant);
+                    (TYPE_INT, i.to_le_bytes()[..width].to_vec())
+                }
+            };
+
+            let len = u16::try_from(bytes.len()).map_err(|_| Error::ValueTooLarge { key: *key })?;
+
+            out.extend_from_slice(&key.to_le_bytes());
+            out.push(ty);
+            out.extend_from_slice(&len.to_le_bytes());
+            out.extend_from_slice(&bytes);
+        }
+
+        Ok(())
+    }
+
+    fn decode(mut buf: &[u8]) -> Result<Self, Error> {
+        let mut dict = Self::new();
+
+        while !buf.is_empty() {
+            let [k0, k1, k2, k3, ty, l0, l1, rest @ ..] = buf else {
+                return Err(Error::Malformed("truncated dictionary entry"));
+            };
+
+            let len = u16::from_le_bytes([*l0, *l1]) as usize;
+            let bytes = rest
+                .get(..len)
+                .ok_or(Error::Malformed("truncated dictionary value"))?;
+
+            let value = match (*ty, len) {
+                (TYPE_BYTES, _) => Value::Bytes(bytes.to_vec()),
+                (TYPE_STRING, _) => Value::String(
+                    String::from_utf8(bytes.to_vec())
+                        .map_err(|_| Error::Malformed("invalid UTF-8 string"))?,
+                ),
+                (TYPE_UINT, 1 | 2 | 4 | 8) => {
+                    let mut raw = [0; 8];
+                    raw[..len].copy_from_slice(bytes);
+                    Value::UInt(u64::from_le_bytes(raw))
+                }
+                (TYPE_INT, 1 | 2 | 4 | 8) => {
+                    // Sign-extend from the encoded width.
+                    let fill = if bytes[len - 1] & 0x80 != 0 { 0xff } else { 0 };
+                    let mut raw = [fill; 8];
+                    raw[..len].copy_from_slice(bytes);
+                    Value::Int(i64::from_le_bytes(raw))
+                }
+                _ => return Err(Error::Malformed("invalid dictionary value")),
+            };
+
+            dict.insert(u32::from_le_bytes([*k0, *k1, *k2, *k3]), value);
+            buf = &rest[len..];
+        }
+
+        Ok(dict)
+    }
+}
+
+impl FromIterator<(u32, Value)> for Dictionary {
+    fn from_iter<T: IntoIterator<Item = (u32, Value)>>(iter: T) -> Self {
+        let mut dict = Self::new();
+
+        for (key, value) in iter {
+            dict.insert(key, value);
+        }
+
+        dict
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Message {
+    Dictionary(Dictionary),
+    // Opaque bytes, e.g. a postcard-encoded value.
+    Data(Vec<u8>),
+}
+
+impl Message {
+    pub fn typed<T: Serialize + ?Sized>(value: &T) -> Result<Self, Error> {
+        Ok(Self::Data(postcard::to_allocvec(value)?))
+    }
+
+    pub fn as_dictionary(&self) -> Option<&Dictionary> {
+        match self {
+            Self::Dictionary(dict) => Some(dict),
+            Self::Data(_) => None,
+        }
+    }
+
+    pub fn decode_typed<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        match self {
+            Self::Data(data) => Ok(postcard::from_bytes(data)?),
+            Self::Dictionary(_) => Err(Error::Malformed("expected a data message")),
+        }
+    }
+
+    pub(crate) fn encode(&self) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::new();
+
+        match self {
+            Self::Dictionary(dict) => {
+                out.push(MESSAGE_DICTIONARY);
+                dict.encode(&mut out)?;
+            }
+            Self::Data(data) => {
+                out.push(MESSAGE_DATA);
+                out.extend_from_slice(data);
+            }
+        }
+
+        Ok(out)
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self, Error> {
+        match buf {
+            [MESSAGE_DICTIONARY, rest @ ..] => Ok(Self::Dictionary(Dictionary::decode(rest)?)),
+            [MESSAGE_DATA, rest @ ..] => Ok(Self::Data(rest.to_vec())),
+            _ => Err(Error::Malformed("unknown message type")),
+        }
+    }
+}
+
+impl From<Dictionary> for Message {
+    fn from(dict: Dictionary) -> Self {
+        Self::Dictionary(dict)
+    }
+}
+
+fn int_width(bits: u32) -> usize {
+    match bits {
+        0..=8 => 1,
+        9..=16 => 2,
+        17..=32 => 4,
+        _ => 8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let dict = Dictionary::new()
+            .with(0, "hello")
+            .with(1, &b"\x00\xff"[..])
+            .with(2, 0u8)
+            .with(3, 300u16)
+            .with(4, u64::MAX)
+            .with(5, -1i8)
+            .with(6, -129i16)
+            .with(7, i64::MIN)
+            .with(8, 127i64)
+            .with(u32::MAX, "");
+
+        let encoded = Message::from(dict.clone()).encode().unwrap();
+        assert_eq!(
+            Message::decode(&encoded).unwrap(),
+            Message::Dictionary(dict)
+        );
+    }
+
+    #[test]
+    fn data_round_trip() {
+        let message = Message::typed(&(1u32, "two")).unwrap();
+        let decoded = Message::decode(&message.encode().unwrap()).unwrap();
+
+        assert_eq!(
+            decoded.decode_typed::<(u32, String)>().unwrap(),
+            (1, "two".into())
+        );
+    }
+
+    #[test]
+    fn value_too_large() {
+        let dict = Dictionary::new().with(7, vec![0; u16::MAX as usize + 1]);
+
+        assert!(matches!(
+            Message::from(dict).encode(),
+            Err(Error::ValueTooLarge { key: 7 })
+        ));
+    }
+
+    #[test]
+    fn truncated() {
+        let encoded = Message::from(Dictionary::new().with(0, "hello"))
+            .encode()
+            .unwrap();
+
+        assert!(Message::decode(&encoded[..encoded.len() - 1]).is_err());
+    }
+}
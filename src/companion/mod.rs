@@ -0,0 +1,552 @@
+use std::{
+    collections::VecDeque,
+    io,
+    task::{Context, Poll},
+};
+
+use futures::{future::poll_fn, stream::StreamExt};
+use thiserror::Error;
+
+use crate::{
+    asynch::reactor,
+    ffi, net, rng,
+    time::{self, Duration, Instant},
+};
+
+mod dictionary;
+
+pub use dictionary::{Dictionary, Message, Value};
+
+const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_secs(2);
+const DEFAULT_RETRIES: u32 = 3;
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024;
+
+const PACKET_DATA: u8 = 0;
+const PACKET_ACK: u8 = 1;
+const PACKET_NACK: u8 = 2;
+// Packet kind, session, transaction ID, chunk index and chunk count.
+const HEADER_LEN: usize = 10;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("serialization error: {0}")]
+    Serialization(#[from] postcard::Error),
+    #[error("peer did not acknowledge the message")]
+    Timeout,
+    #[error("peer rejected the message")]
+    Rejected,
+    #[error("message exceeds {limit} bytes")]
+    MessageTooLarge { limit: usize },
+    #[error("dictionary value for key {key} exceeds {} bytes", u16::MAX)]
+    ValueTooLarge { key: u32 },
+    #[error("link MTU of {0} bytes is too small")]
+    MtuTooSmall(usize),
+    #[error("malformed message: {0}")]
+    Malformed(&'static str),
+}
+
+// A packet-based transport for a `Channel`. Packets may be lost, but must not be corrupted or
+// split.
+pub trait Link {
+    // Fails with `NotConnected` while the link is down.
+    fn mtu(&self) -> io::Result<usize>;
+
+    // Links that can drop and come back report it here, so the channel can start over.
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    fn poll_send(&mut self, cx: &mut Context<'_>, packet: &[u8]) -> Poll<io::Result<()>>;
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>>;
+}
+
+// The link to the companion app on the paired phone, provided by the host.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct HostLink;
+
+impl HostLink {
+    pub fn is_connected(&self) -> bool {
+        unsafe { ffi::companion::companion_is_connected() }
+    }
+}
+
+impl Link for HostLink {
+    fn mtu(&self) -> io::Result<usize> {
+        // The host has no MTU to report while the phone is away.
+        match net::cvt(unsafe { ffi::companion::companion_mtu() } as isize)? {
+            0 => Err(io::ErrorKind::NotConnected.into()),
+            mtu => Ok(mtu),
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        HostLink::is_connected(self)
+    }
+
+    fn poll_send(&mut self, cx: &mut Context<'_>, packet: &[u8]) -> Poll<io::Result<()>> {
+        let ret = unsafe { ffi::companion::companion_send(packet.as_ptr(), packet.len()) };
+
+        match net::cvt(ret as isize) {
+            Ok(_) => Poll::Ready(Ok(())),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                reactor::register_io(cx.waker(), false, true);
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let ret = unsafe { ffi::companion::companion_recv(buf.as_mut_ptr(), buf.len()) };
+
+        match net::cvt(ret) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                reactor::register_io(cx.waker(), true, false);
+                Poll::Pending
+            }
+            res => Poll::Ready(res),
+        }
+    }
+}
+
+// An in-process link, standing in for the phone when running both ends locally.
+pub struct Loopback {
+    tx: flume::Sender<Vec<u8>>,
+    rx: flume::r#async::RecvStream<'static, Vec<u8>>,
+    mtu: usize,
+}
+
+impl Loopback {
+    pub fn pair(mtu: usize) -> (Self, Self) {
+        let (a_tx, a_rx) = flume::unbounded();
+        let (b_tx, b_rx) = flume::unbounded();
+
+        let a = Self {
+            tx: a_tx,
+            rx: b_rx.into_stream(),
+            mtu,
+        };
+        let b = Self {
+            tx: b_tx,
+            rx: a_rx.into_stream(),
+            mtu,
+        };
+
+        (a, b)
+    }
+}
+
+impl Link for Loopback {
+    fn mtu(&self) -> io::Result<usize> {
+        Ok(self.mtu)
+    }
+
+    fn poll_send(&mut self, _cx: &mut Context<'_>, packet: &[u8]) -> Poll<io::Result<()>> {
+        let packet = packet[..packet.len().min(self.mtu)].to_vec();
+
+        Poll::Ready(
+            self.tx
+                .send(packet)
+                .map_err(|_| io::ErrorKind::NotConnected.into()),
+        )
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        self.rx.poll_next_unpin(cx).map(|packet| match packet {
+            Some(packet) => {
+                let len = packet.len().min(buf.len());
+                buf[..len].copy_from_slice(&packet[..len]);
+                Ok(len)
+            }
+            None => Err(io::ErrorKind::NotConnected.into()),
+        })
+    }
+}
+
+// A message being reassembled from its chunks.
+struct Partial {
+    session: u32,
+    id: u8,
+    chunks: Vec<Option<Vec<u8>>>,
+}
+
+// Reliable message exchange over a `Link`. Messages are split into chunks that fit the link's
+// MTU and sent one message at a time; the peer acknowledges each complete message, and messages
+// that go unacknowledged are sent again. Incoming messages that arrive while waiting for an
+// acknowledgement are queued for `recv`.
+//
+// Every packet carries a random session ID picked when the channel is created, so a peer that
+// restarts and reuses transaction IDs isn't mistaken for a retransmission.
+pub struct Channel<L = HostLink> {
+    link: L,
+    ack_timeout: Duration,
+    retries: u32,
+    max_message_size: usize,
+    session: u32,
+    next_id: u8,
+    connected: bool,
+    partial: Option<Partial>,
+    // The last delivered message as (session, ID), so a retransmission caused by a lost ACK isn't
+    // delivered twice.
+    last_delivered: Option<(u32, u8)>,
+    inbox: VecDeque<Message>,
+}
+
+impl Channel<HostLink> {
+    pub fn new() -> Self {
+        Self::with_link(HostLink)
+    }
+}
+
+impl Default for Channel<HostLink> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<L: Link> Channel<L> {
+    pub fn with_link(link: L) -> Self {
+        Self {
+            link,
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            session: rng::random(),
+            next_id: 0,
+            connected: true,
+            partial: None,
+            last_delivered: None,
+            inbox: VecDeque::new(),
+        }
+    }
+
+    pub fn with_ack_timeout(mut self, ack_timeout: Duration) -> Self {
+        self.ack_timeout = ack_timeout;
+        self
+    }
+
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    // Applies to both directions. Larger incoming messages are rejected.
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    pub fn link(&self) -> &L {
+        &self.link
+    }
+
+    pub fn into_link(self) -> L {
+        self.link
+    }
+
+    // Sends a message and waits until the peer has acknowledged it.
+    pub async fn send(&mut self, message: impl Into<Message>) -> Result<(), Error> {
+        let payload = message.into().encode()?;
+
+        if payload.len() > self.max_message_size {
+            return Err(Error::MessageTooLarge {
+                limit: self.max_message_size,
+            });
+        }
+
+        let mtu = self.link.mtu()?;
+        let chunk_size = mtu
+            .checked_sub(HEADER_LEN)
+            .filter(|&size| size > 0)
+            .ok_or(Error::MtuTooSmall(mtu))?;
+
+        let chunks = payload.chunks(chunk_size).collect::<Vec<_>>();
+        let count = u16::try_from(chunks.len()).map_err(|_| Error::MessageTooLarge {
+            limit: chunk_size * u16::MAX as usize,
+        })?;
+
+        self.check_connection();
+
+        let session = self.session;
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        for _ in 0..=self.retries {
+            for (index, chunk) in chunks.iter().enumerate() {
+                let mut packet = header(PACKET_DATA, session, id, index as u16, count);
+                packet.extend_from_slice(chunk);
+
+                self.send_packet(&packet).await?;
+            }
+
+            let deadline = Instant::now() + self.ack_timeout;
+
+            while let Ok(packet) = time::timeout_at(deadline, self.recv_packet()).await {
+                match self.handle(&packet?).await? {
+                    Some((PACKET_ACK, s, ack)) if (s, ack) == (session, id) => return Ok(()),
+                    Some((PACKET_NACK, s, nack)) if (s, nack) == (session, id) => {
+                        return Err(Error::Rejected)
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Err(Error::Timeout)
+    }
+
+    pub async fn send_typed<T: serde::Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.send(Message::typed(value)?).await
+    }
+
+    pub async fn recv(&mut self) -> Result<Message, Error> {
+        self.check_connection();
+
+        loop {
+            if let Some(message) = self.inbox.pop_front() {
+                return Ok(message);
+            }
+
+            let packet = self.recv_packet().await?;
+            self.handle(&packet).await?;
+        }
+    }
+
+    pub async fn recv_typed<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, Error> {
+        self.recv().await?.decode_typed()
+    }
+
+    // Starts over after the link comes back, since the peer may have restarted meanwhile.
+    fn check_connection(&mut self) {
+        let connected = self.link.is_connected();
+
+        if connected && !self.connected {
+            self.partial = None;
+            self.last_delivered = None;
+        }
+        self.connected = connected;
+    }
+
+    // Handles an incoming packet. Data chunks are reassembled and acknowledged, while ACKs and
+    // NACKs are returned as (kind, session, transaction ID) for `send` to match up.
+    async fn handle(&mut self, packet: &[u8]) -> Result<Option<(u8, u32, u8)>, Error> {
+        let Some((&[kind, s0, s1, s2, s3, id, i0, i1, c0, c1], chunk)) =
+            packet.split_first_chunk::<HEADER_LEN>()
+        else {
+            return Ok(None);
+        };
+
+        let session = u32::from_le_bytes([s0, s1, s2, s3]);
+
+        if kind != PACKET_DATA {
+            return Ok(Some((kind, session, id)));
+        }
+
+        let index = u16::from_le_bytes([i0, i1]) as usize;
+        let count = u16::from_le_bytes([c0, c1]) as usize;
+
+        if self.last_delivered == Some((session, id)) {
+            // Our ACK got lost, so the peer is sending the whole message again.
+            if index + 1 == count {
+                self.send_packet(&header(PACKET_ACK, session, id, 0, 0))
+                    .await?;
+            }
+            return Ok(None);
+        }
+
+        if index >= count {
+            return Ok(None);
+        }
+
+        // Only the last chunk can be short, so a message can't take more chunks than this. Empty
+        // chunks before it would let a peer claim any number of them.
+        let chunk_size = self.link.mtu()?.saturating_sub(HEADER_LEN).max(1);
+        let max_chunks = self.max_message_size / chunk_size + 1;
+
+        if count > max_chunks || (chunk.is_empty() && index + 1 < count) {
+            self.partial = None;
+            self.send_packet(&header(PACKET_NACK, session, id, 0, 0))
+                .await?;
+            return Ok(None);
+        }
+
+        let partial = match &mut self.partial {
+            Some(partial)
+                if (partial.session, partial.id) == (session, id)
+                    && partial.chunks.len() == count =>
+            {
+                partial
+            }
+            partial => partial.insert(Partial {
+                session,
+                id,
+                chunks: vec![None; count],
+            }),
+        };
+        partial.chunks[index] = Some(chunk.to_vec());
+
+        if partial.chunks.iter().any(Option::is_none) {
+            return Ok(None);
+        }
+
+        let payload = self
+            .partial
+            .take()
+            .into_iter()
+            .flat_map(|p| p.chunks)
+            .flatten()
+            .flatten()
+            .collect::<Vec<_>>();
+
+        match Message::decode(&payload) {
+            Ok(message) if payload.len() <= self.max_message_size => {
+                self.last_delivered = Some((session, id));
+                self.inbox.push_back(message);
+                self.send_packet(&header(PACKET_ACK, session, id, 0, 0))
+                    .await?;
+            }
+            _ => {
+                self.send_packet(&header(PACKET_NACK, session, id, 0, 0))
+                    .await?
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn send_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        poll_fn(|cx| self.link.poll_send(cx, packet)).await
+    }
+
+    async fn recv_packet(&mut self) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0; self.link.mtu()?.max(HEADER_LEN)];
+        let len = poll_fn(|cx| self.link.poll_recv(cx, &mut buf)).await?;
+        buf.truncate(len);
+
+        Ok(buf)
+    }
+}
+
+fn header(kind: u8, session: u32, id: u8, index: u16, count: u16) -> Vec<u8> {
+    let mut header = vec![kind];
+    header.extend_from_slice(&session.to_le_bytes());
+    header.push(id);
+    header.extend_from_slice(&index.to_le_bytes());
+    header.extend_from_slice(&count.to_le_bytes());
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, join};
+
+    use super::*;
+
+    // Drops the first `acks` acknowledgements sent through it.
+    struct LoseAcks<L> {
+        link: L,
+        acks: usize,
+    }
+
+    impl<L: Link> Link for LoseAcks<L> {
+        fn mtu(&self) -> io::Result<usize> {
+            self.link.mtu()
+        }
+
+        fn poll_send(&mut self, cx: &mut Context<'_>, packet: &[u8]) -> Poll<io::Result<()>> {
+            if packet[0] == PACKET_ACK && self.acks > 0 {
+                self.acks -= 1;
+                return Poll::Ready(Ok(()));
+            }
+
+            self.link.poll_send(cx, packet)
+        }
+
+        fn poll_recv(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+            self.link.poll_recv(cx, buf)
+        }
+    }
+
+    fn message(n: u64) -> Message {
+        Dictionary::new().with(0, n).with(1, "x".repeat(100)).into()
+    }
+
+    #[test]
+    fn round_trip() {
+        let (a, b) = Loopback::pair(32);
+        let (mut a, mut b) = (Channel::with_link(a), Channel::with_link(b));
+
+        let (sent, received) = block_on(async { join!(a.send(message(1)), b.recv()) });
+
+        sent.unwrap();
+        assert_eq!(received.unwrap(), message(1));
+    }
+
+    #[test]
+    fn lost_ack() {
+        let (a, b) = Loopback::pair(32);
+        let mut a = Channel::with_link(a).with_ack_timeout(Duration::from_millis(20));
+        let mut b = Channel::with_link(LoseAcks { link: b, acks: 1 });
+
+        let (sent, received) = block_on(async {
+            join!(
+                async {
+                    a.send(message(1)).await?;
+                    a.send(message(2)).await
+                },
+                async { (b.recv().await.unwrap(), b.recv().await.unwrap()) },
+            )
+        });
+
+        // The retransmission is acknowledged again but not delivered twice.
+        sent.unwrap();
+        assert_eq!(received, (message(1), message(2)));
+    }
+
+    #[test]
+    fn bogus_chunk_counts() {
+        let (mut a, b) = Loopback::pair(32);
+        let mut b = Channel::with_link(b);
+
+        let mut too_many = header(PACKET_DATA, 1, 1, 0, 2000);
+        too_many.push(0);
+
+        // An empty chunk claiming the largest possible message, and more chunks than the largest
+        // message allowed needs.
+        for (id, packet) in [(0, header(PACKET_DATA, 1, 0, 0, u16::MAX)), (1, too_many)] {
+            let nack = block_on(async {
+                b.handle(&packet).await.unwrap();
+
+                let mut buf = [0; 32];
+                let len = poll_fn(|cx| a.poll_recv(cx, &mut buf)).await.unwrap();
+                buf[..len].to_vec()
+            });
+
+            assert!(b.partial.is_none());
+            assert_eq!(nack, header(PACKET_NACK, 1, id, 0, 0));
+        }
+    }
+
+    #[test]
+    fn peer_restart() {
+        let (a, b) = Loopback::pair(32);
+        let (mut a, mut b) = (Channel::with_link(a), Channel::with_link(b));
+
+        let (sent, received) = block_on(async { join!(a.send(message(1)), b.recv()) });
+        sent.unwrap();
+        assert_eq!(received.unwrap(), message(1));
+
+        // A new channel starts its transaction IDs over, just like the first one did.
+        let mut a = Channel::with_link(a.into_link());
+
+        let (sent, received) = block_on(async { join!(a.send(message(2)), b.recv()) });
+        sent.unwrap();
+        assert_eq!(received.unwrap(), message(2));
+    }
+}
@@ -0,0 +1,122 @@
+use crate::syscalls;
+
+use super::io::Handle;
+
+pub const ADDRESS_PUBLIC: u8 = 0;
+pub const ADDRESS_RANDOM: u8 = 1;
+
+pub const SERVER_EVENT_CONNECTED: u8 = 0;
+pub const SERVER_EVENT_DISCONNECTED: u8 = 1;
+pub const SERVER_EVENT_WRITE: u8 = 2;
+pub const SERVER_EVENT_SUBSCRIBED: u8 = 3;
+pub const SERVER_EVENT_UNSUBSCRIBED: u8 = 4;
+
+// Operations that take a while return -1 (would block) until they complete, and are simply called
+// again with the same arguments once the I/O wake fires. Calls that fill an array return the total
+// number of entries, which may be more than fit.
+syscalls! {
+    pub fn ble_scan_start(active: bool, handle: *mut Handle) -> i32;
+    // Returns the length of the raw advertising data, which is truncated if it doesn't fit.
+    pub fn ble_scan_next(handle: Handle, result: *mut ScanResult, adv_ptr: *mut u8, adv_len: usize)
+        -> isize;
+    pub fn ble_scan_stop(handle: Handle);
+
+    pub fn ble_connect(addr: *const Address, handle: *mut Handle) -> i32;
+    pub fn ble_connect_status(handle: Handle) -> i32;
+    pub fn ble_mtu(handle: Handle) -> i32;
+    pub fn ble_discover_services(handle: Handle, services: *mut Service, len: usize) -> isize;
+    pub fn ble_discover_characteristics(
+        handle: Handle,
+        start: u16,
+        end: u16,
+        characteristics: *mut Characteristic,
+        len: usize,
+    ) -> isize;
+    pub fn ble_read(handle: Handle, attr: u16, ptr: *mut u8, len: usize) -> isize;
+    pub fn ble_write(handle: Handle, attr: u16, ptr: *const u8, len: usize, with_response: bool)
+        -> i32;
+    pub fn ble_subscribe(handle: Handle, attr: u16, enable: bool) -> i32;
+    pub fn ble_notification(handle: Handle, attr: *mut u16, ptr: *mut u8, len: usize) -> isize;
+    pub fn ble_disconnect(handle: Handle);
+
+    // `value_handles` receives the attribute handle of each characteristic's value.
+    pub fn ble_add_service(
+        uuid: *const Uuid,
+        characteristics: *const CharacteristicDef,
+        len: usize,
+        value_handles: *mut u16,
+        handle: *mut Handle,
+    ) -> i32;
+    pub fn ble_set_value(handle: Handle, attr: u16, ptr: *const u8, len: usize) -> i32;
+    // Notifies or indicates every subscribed central.
+    pub fn ble_notify(handle: Handle, attr: u16, ptr: *const u8, len: usize) -> i32;
+    pub fn ble_server_event(handle: Handle, event: *mut ServerEvent, ptr: *mut u8, len: usize)
+        -> isize;
+    pub fn ble_remove_service(handle: Handle);
+
+    pub fn ble_advertise_start(
+        adv_ptr: *const u8,
+        adv_len: usize,
+        scan_rsp_ptr: *const u8,
+        scan_rsp_len: usize,
+        interval_ms: u32,
+        handle: *mut Handle,
+    ) -> i32;
+    pub fn ble_advertise_stop(handle: Handle);
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Address {
+    // Most significant byte first, as it is usually written.
+    pub bytes: [u8; 6],
+    pub kind: u8,
+}
+
+// UUIDs are always passed in their full 128-bit form, most significant byte first.
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Uuid {
+    pub bytes: [u8; 16],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct ScanResult {
+    pub address: Address,
+    pub rssi: i8,
+    pub connectable: bool,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Service {
+    pub uuid: Uuid,
+    pub start: u16,
+    pub end: u16,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Characteristic {
+    pub uuid: Uuid,
+    pub value_handle: u16,
+    pub properties: u8,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct CharacteristicDef {
+    pub uuid: Uuid,
+    pub properties: u8,
+    pub value_ptr: *const u8,
+    pub value_len: usize,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct ServerEvent {
+    pub kind: u8,
+    pub address: Address,
+    pub attr: u16,
+}
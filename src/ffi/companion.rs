@@ -0,0 +1,11 @@
+use crate::syscalls;
+
+syscalls! {
+    pub fn companion_is_connected() -> bool;
+    // The largest packet the link can carry, or a negative error code if it is down.
+    pub fn companion_mtu() -> i32;
+    // Sends a single packet, which must not be larger than the MTU.
+    pub fn companion_send(ptr: *const u8, len: usize) -> i32;
+    // Receives a single packet. If `len` is too small, the rest of the packet is discarded.
+    pub fn companion_recv(ptr: *mut u8, len: usize) -> isize;
+}
@@ -0,0 +1,64 @@
+use crate::syscalls;
+
+use super::io::Handle;
+
+pub const OPEN_READ: u32 = 1 << 0;
+pub const OPEN_WRITE: u32 = 1 << 1;
+pub const OPEN_APPEND: u32 = 1 << 2;
+pub const OPEN_CREATE: u32 = 1 << 3;
+pub const OPEN_CREATE_NEW: u32 = 1 << 4;
+pub const OPEN_TRUNCATE: u32 = 1 << 5;
+// Reads, writes, seeks and syncs on the handle return -1 (would block) until they complete, and
+// are called again with the same arguments once the I/O wake fires.
+pub const OPEN_NONBLOCKING: u32 = 1 << 6;
+
+pub const SEEK_START: u8 = 0;
+pub const SEEK_CURRENT: u8 = 1;
+pub const SEEK_END: u8 = 2;
+
+// Paths are UTF-8 and relative to the app's own storage root, which the host doesn't let them
+// escape.
+syscalls! {
+    pub fn fs_open(path_ptr: *const u8, path_len: usize, flags: u32, handle: *mut Handle) -> i32;
+    pub fn fs_read(handle: Handle, ptr: *mut u8, len: usize) -> isize;
+    pub fn fs_write(handle: Handle, ptr: *const u8, len: usize) -> isize;
+    pub fn fs_seek(handle: Handle, offset: i64, whence: u8, pos: *mut u64) -> i32;
+    pub fn fs_truncate(handle: Handle, len: u64) -> i32;
+    pub fn fs_sync(handle: Handle) -> i32;
+    pub fn fs_file_metadata(handle: Handle, metadata: *mut Metadata) -> i32;
+    pub fn fs_close(handle: Handle);
+
+    pub fn fs_metadata(path_ptr: *const u8, path_len: usize, metadata: *mut Metadata) -> i32;
+    pub fn fs_rename(
+        from_ptr: *const u8,
+        from_len: usize,
+        to_ptr: *const u8,
+        to_len: usize,
+    ) -> i32;
+    pub fn fs_remove_file(path_ptr: *const u8, path_len: usize) -> i32;
+    pub fn fs_create_dir(path_ptr: *const u8, path_len: usize) -> i32;
+    pub fn fs_remove_dir(path_ptr: *const u8, path_len: usize) -> i32;
+
+    pub fn fs_read_dir(path_ptr: *const u8, path_len: usize, handle: *mut Handle) -> i32;
+    // Returns the length of the entry's name, or 0 at the end of the directory. Names that don't
+    // fit are truncated.
+    pub fn fs_read_dir_next(
+        handle: Handle,
+        metadata: *mut Metadata,
+        name_ptr: *mut u8,
+        name_len: usize,
+    ) -> isize;
+    pub fn fs_close_dir(handle: Handle);
+
+    // Total and free space available to the app, in bytes.
+    pub fn fs_usage(total: *mut u64, free: *mut u64) -> i32;
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Metadata {
+    pub len: u64,
+    pub is_dir: bool,
+    // Seconds since the Unix epoch, or 0 if unknown.
+    pub modified: u64,
+}
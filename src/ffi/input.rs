@@ -0,0 +1,22 @@
+use crate::syscalls;
+
+pub const BUTTON_BACK: u8 = 0;
+pub const BUTTON_UP: u8 = 1;
+pub const BUTTON_SELECT: u8 = 2;
+pub const BUTTON_DOWN: u8 = 3;
+
+syscalls! {
+    // Takes the oldest queued button event. Returns -1 if there are none.
+    pub fn input_next(event: *mut ButtonEvent) -> i32;
+    // `wake` is called whenever a button event is queued.
+    pub fn register_input_wake(wake: extern "C" fn());
+}
+
+// `timestamp` is on the same clock as `get_time`.
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct ButtonEvent {
+    pub button: u8,
+    pub pressed: bool,
+    pub timestamp: u64,
+}
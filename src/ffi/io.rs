@@ -8,4 +8,55 @@ syscalls! {
     pub fn print(ptr: *const u8, len: usize, new_line: bool);
     pub fn eprint(ptr: *const u8, len: usize, new_line: bool);
     pub fn log(level: u8, ptr: *const u8, len: usize);
+    pub fn log_record(record: *const LogRecord);
+    // Returns the length of the host-configured filter directives, which are truncated if they
+    // don't fit.
+    pub fn log_directives(ptr: *mut u8, len: usize) -> isize;
+
+    // Span IDs are never 0, which stands for no span. Timestamps are from `get_time`.
+    pub fn trace_span_new(span: *const SpanRecord);
+    pub fn trace_span_record(id: u64, fields_ptr: *const LogField, fields_len: usize);
+    pub fn trace_span_enter(id: u64, timestamp: u64);
+    pub fn trace_span_exit(id: u64, timestamp: u64);
+    pub fn trace_span_close(id: u64);
+    pub fn trace_event(span: u64, timestamp: u64, record: *const LogRecord);
+}
+
+// Strings that aren't available have a null pointer, and `line` is 0 if unknown.
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct LogRecord {
+    pub level: u8,
+    pub target_ptr: *const u8,
+    pub target_len: usize,
+    pub module_path_ptr: *const u8,
+    pub module_path_len: usize,
+    pub file_ptr: *const u8,
+    pub file_len: usize,
+    pub line: u32,
+    pub message_ptr: *const u8,
+    pub message_len: usize,
+    pub fields_ptr: *const LogField,
+    pub fields_len: usize,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct LogField {
+    pub key_ptr: *const u8,
+    pub key_len: usize,
+    pub value_ptr: *const u8,
+    pub value_len: usize,
+}
+
+// The span's metadata is in `record`, which has no message.
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct SpanRecord {
+    pub id: u64,
+    pub parent: u64,
+    pub timestamp: u64,
+    pub name_ptr: *const u8,
+    pub name_len: usize,
+    pub record: LogRecord,
 }
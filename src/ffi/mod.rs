@@ -6,6 +6,11 @@ pub mod tuple;
 pub mod widget;
 pub mod rng;
 pub mod asynch;
+pub mod ble;
+pub mod companion;
+pub mod fs;
+pub mod input;
+pub mod sensors;
 
 trait Sealed {}
 
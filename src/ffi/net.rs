@@ -1,10 +1,140 @@
+use std::net::{self, IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::syscalls;
+
 use super::io::Handle;
 
+pub const FAMILY_IPV4: u8 = 4;
+pub const FAMILY_IPV6: u8 = 6;
+
+pub const SHUTDOWN_READ: u8 = 0;
+pub const SHUTDOWN_WRITE: u8 = 1;
+pub const SHUTDOWN_BOTH: u8 = 2;
+
+pub const TRANSPORT_NONE: u8 = 0;
+pub const TRANSPORT_WIFI: u8 = 1;
+pub const TRANSPORT_BLE_TETHER: u8 = 2;
+
+// Only trust the certificates passed in `TlsConfig::roots`, not the host's built-in roots.
+pub const TLS_NO_SYSTEM_ROOTS: u32 = 1 << 0;
+
+syscalls! {
+    pub fn resolve_host(host_ptr: *const u8, host_len: usize, addr: *mut SocketAddr) -> i32;
+    pub fn net_status(status: *mut NetStatus);
+    // `wake` is called whenever anything in the network status changes.
+    pub fn register_net_change_wake(wake: extern "C" fn());
+
+    pub fn tcp_connect(addr: *const SocketAddr, handle: *mut Handle) -> i32;
+    pub fn tcp_connect_status(handle: Handle) -> i32;
+    pub fn tcp_read(handle: Handle, ptr: *mut u8, len: usize) -> isize;
+    pub fn tcp_write(handle: Handle, ptr: *const u8, len: usize) -> isize;
+    pub fn tcp_shutdown(handle: Handle, how: u8) -> i32;
+    pub fn tcp_peer_addr(handle: Handle, addr: *mut SocketAddr) -> i32;
+    pub fn tcp_local_addr(handle: Handle, addr: *mut SocketAddr) -> i32;
+    pub fn tcp_close(handle: Handle);
+
+    pub fn tcp_bind(addr: *const SocketAddr, handle: *mut Handle) -> i32;
+    pub fn tcp_accept(handle: Handle, stream: *mut Handle, addr: *mut SocketAddr) -> i32;
+    pub fn tcp_listener_local_addr(handle: Handle, addr: *mut SocketAddr) -> i32;
+    pub fn tcp_listener_close(handle: Handle);
+
+    pub fn udp_bind(addr: *const SocketAddr, handle: *mut Handle) -> i32;
+    pub fn udp_send_to(handle: Handle, ptr: *const u8, len: usize, addr: *const SocketAddr)
+        -> isize;
+    pub fn udp_recv_from(handle: Handle, ptr: *mut u8, len: usize, addr: *mut SocketAddr)
+        -> isize;
+    pub fn udp_local_addr(handle: Handle, addr: *mut SocketAddr) -> i32;
+    pub fn udp_join_multicast(handle: Handle, group: *const SocketAddr) -> i32;
+    pub fn udp_leave_multicast(handle: Handle, group: *const SocketAddr) -> i32;
+    pub fn udp_close(handle: Handle);
+
+    // The host takes over reading and writing the TCP stream until the session is closed.
+    pub fn tls_connect(stream: Handle, config: *const TlsConfig, handle: *mut Handle) -> i32;
+    pub fn tls_handshake_status(handle: Handle) -> i32;
+    pub fn tls_read(handle: Handle, ptr: *mut u8, len: usize) -> isize;
+    pub fn tls_write(handle: Handle, ptr: *const u8, len: usize) -> isize;
+    pub fn tls_close_notify(handle: Handle) -> i32;
+    // Copies as much of the DER-encoded leaf certificate as fits and returns its full length.
+    pub fn tls_peer_certificate(handle: Handle, ptr: *mut u8, len: usize) -> isize;
+    pub fn tls_close(handle: Handle);
+}
+
+#[repr(C)]
+pub struct TcpStream(pub Handle);
+
+#[repr(C)]
+pub struct TcpListener(pub Handle);
+
+#[repr(C)]
+pub struct UdpSocket(pub Handle);
+
+#[repr(C)]
+pub struct TlsStream(pub Handle);
+
 #[repr(C)]
-pub struct TcpStream(Handle);
+pub struct TlsConfig {
+    pub server_name_ptr: *const u8,
+    pub server_name_len: usize,
+    // SHA-256 hashes of the DER-encoded SubjectPublicKeyInfo of acceptable certificates. If
+    // non-empty, some certificate in the chain must match one of them.
+    pub pins_ptr: *const [u8; 32],
+    pub pins_len: usize,
+    // DER-encoded certificates to trust, each prefixed with its length as a little-endian u32.
+    pub roots_ptr: *const u8,
+    pub roots_len: usize,
+    pub flags: u32,
+}
 
 #[repr(C)]
-pub struct TcpListener(Handle);
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct NetStatus {
+    pub transport: u8,
+    pub link_up: bool,
+    pub has_ip: bool,
+    // Only the IP part is meaningful.
+    pub ip: SocketAddr,
+    pub has_rssi: bool,
+    pub rssi: i8,
+}
 
 #[repr(C)]
-pub struct UdpSocket(Handle);
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct SocketAddr {
+    pub ip: [u8; 16],
+    pub port: u16,
+    pub family: u8,
+}
+
+impl From<net::SocketAddr> for SocketAddr {
+    fn from(addr: net::SocketAddr) -> Self {
+        let mut ip = [0; 16];
+
+        let family = match addr.ip() {
+            IpAddr::V4(v4) => {
+                ip[..4].copy_from_slice(&v4.octets());
+                FAMILY_IPV4
+            }
+            IpAddr::V6(v6) => {
+                ip.copy_from_slice(&v6.octets());
+                FAMILY_IPV6
+            }
+        };
+
+        Self {
+            ip,
+            port: addr.port(),
+            family,
+        }
+    }
+}
+
+impl From<SocketAddr> for net::SocketAddr {
+    fn from(addr: SocketAddr) -> Self {
+        let ip = match addr.family {
+            FAMILY_IPV6 => IpAddr::V6(Ipv6Addr::from(addr.ip)),
+            _ => IpAddr::V4(Ipv4Addr::new(addr.ip[0], addr.ip[1], addr.ip[2], addr.ip[3])),
+        };
+
+        net::SocketAddr::new(ip, addr.port)
+    }
+}
@@ -0,0 +1,31 @@
+use crate::syscalls;
+
+use super::io::Handle;
+
+syscalls! {
+    // Starts the accelerometer. The rate and range are rounded to what the hardware supports, and
+    // the values actually used are written back to `config`.
+    pub fn accel_open(config: *mut AccelConfig, handle: *mut Handle) -> i32;
+    // Takes up to `len` queued samples, returning how many were written. Returns -1 (would block)
+    // until at least `batch_len` samples are queued, so the I/O wake only fires once per batch.
+    pub fn accel_read(handle: Handle, samples: *mut AccelSample, len: usize) -> isize;
+    pub fn accel_close(handle: Handle);
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct AccelConfig {
+    pub rate_hz: u32,
+    pub range_g: u8,
+    pub batch_len: u32,
+}
+
+// Raw readings, where ±32768 is the full range. `timestamp` is on the same clock as `get_time`.
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct AccelSample {
+    pub x: i16,
+    pub y: i16,
+    pub z: i16,
+    pub timestamp: u64,
+}
@@ -0,0 +1,103 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ffi::{self, io::Handle},
+    net,
+};
+
+use super::{path_str, Metadata};
+
+// Names longer than this are truncated by the host.
+const MAX_NAME_LEN: usize = 255;
+
+pub fn read_dir(path: impl AsRef<Path>) -> io::Result<ReadDir> {
+    let path = path.as_ref();
+    let path_str = path_str(path)?;
+    let mut handle = 0;
+
+    net::cvt(
+        unsafe { ffi::fs::fs_read_dir(path_str.as_ptr(), path_str.len(), &mut handle) } as isize,
+    )?;
+
+    Ok(ReadDir {
+        handle,
+        path: path.to_path_buf(),
+        done: false,
+    })
+}
+
+// An iterator over the entries of a directory, in no particular order.
+#[derive(Debug)]
+pub struct ReadDir {
+    handle: Handle,
+    path: PathBuf,
+    done: bool,
+}
+
+impl Iterator for ReadDir {
+    type Item = io::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut metadata = ffi::fs::Metadata::default();
+        let mut name = vec![0; MAX_NAME_LEN];
+
+        let ret = unsafe {
+            ffi::fs::fs_read_dir_next(self.handle, &mut metadata, name.as_mut_ptr(), name.len())
+        };
+
+        match net::cvt(ret) {
+            Ok(0) => {
+                self.done = true;
+                None
+            }
+            Ok(len) => {
+                name.truncate(len.min(MAX_NAME_LEN));
+                let name = String::from_utf8_lossy(&name).into_owned();
+
+                Some(Ok(DirEntry {
+                    path: self.path.join(&name),
+                    name,
+                    metadata: Metadata::from_ffi(metadata),
+                }))
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl Drop for ReadDir {
+    fn drop(&mut self) {
+        unsafe { ffi::fs::fs_close_dir(self.handle) }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct DirEntry {
+    name: String,
+    path: PathBuf,
+    metadata: Metadata,
+}
+
+impl DirEntry {
+    pub fn file_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn metadata(&self) -> Metadata {
+        self.metadata
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{future::poll_fn, AsyncRead, AsyncSeek, AsyncWrite};
+
+use crate::{
+    asynch::reactor,
+    ffi::{self, io::Handle},
+    net,
+};
+
+use super::{Metadata, OpenOptions};
+
+// A file with blocking operations, which is fine for small files like settings.
+#[derive(Debug)]
+pub struct File {
+    handle: Handle,
+}
+
+impl File {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        OpenOptions::new().with_read(true).open(path)
+    }
+
+    // Creates the file, or truncates it if it exists.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        OpenOptions::new()
+            .with_write(true)
+            .with_create(true)
+            .with_truncate(true)
+            .open(path)
+    }
+
+    pub fn options() -> OpenOptions {
+        OpenOptions::new()
+    }
+
+    pub(crate) fn from_handle(handle: Handle) -> Self {
+        Self { handle }
+    }
+
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        metadata(self.handle)
+    }
+
+    // Truncates or extends the file to `len` bytes, leaving the cursor where it was.
+    pub fn truncate(&self, len: u64) -> io::Result<()> {
+        net::cvt(unsafe { ffi::fs::fs_truncate(self.handle, len) } as isize).map(drop)
+    }
+
+    // Waits until everything written so far is on flash.
+    pub fn sync_all(&self) -> io::Result<()> {
+        net::cvt(unsafe { ffi::fs::fs_sync(self.handle) } as isize).map(drop)
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        net::cvt(unsafe { ffi::fs::fs_read(self.handle, buf.as_mut_ptr(), buf.len()) })
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        net::cvt(unsafe { ffi::fs::fs_write(self.handle, buf.as_ptr(), buf.len()) })
+    }
+
+    // Writes go straight to the host, so there's nothing to flush. Use `sync_all` to make sure
+    // they're durable.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        seek(self.handle, pos)
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        unsafe { ffi::fs::fs_close(self.handle) }
+    }
+}
+
+// A file whose operations complete asynchronously, so slow flash doesn't hold up other tasks.
+// Flushing waits until written data is durable.
+#[derive(Debug)]
+pub struct AsyncFile {
+    handle: Handle,
+}
+
+impl AsyncFile {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        OpenOptions::new().with_read(true).open_async(path)
+    }
+
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        OpenOptions::new()
+            .with_write(true)
+            .with_create(true)
+            .with_truncate(true)
+            .open_async(path)
+    }
+
+    pub(crate) fn from_handle(handle: Handle) -> Self {
+        Self { handle }
+    }
+
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        metadata(self.handle)
+    }
+
+    pub async fn truncate(&self, len: u64) -> io::Result<()> {
+        poll_fn(|cx| {
+            poll_op(cx, || unsafe {
+                ffi::fs::fs_truncate(self.handle, len) as isize
+            })
+        })
+        .await
+        .map(drop)
+    }
+
+    pub async fn sync_all(&self) -> io::Result<()> {
+        poll_fn(|cx| poll_op(cx, || unsafe { ffi::fs::fs_sync(self.handle) as isize }))
+            .await
+            .map(drop)
+    }
+}
+
+impl AsyncRead for AsyncFile {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        poll_op(cx, || unsafe {
+            ffi::fs::fs_read(self.handle, buf.as_mut_ptr(), buf.len())
+        })
+    }
+}
+
+impl AsyncWrite for AsyncFile {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        poll_op(cx, || unsafe {
+            ffi::fs::fs_write(self.handle, buf.as_ptr(), buf.len())
+        })
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        poll_op(cx, || unsafe { ffi::fs::fs_sync(self.handle) } as isize).map_ok(drop)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl AsyncSeek for AsyncFile {
+    fn poll_seek(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        pos: SeekFrom,
+    ) -> Poll<io::Result<u64>> {
+        match seek(self.handle, pos) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                reactor::register_io(cx.waker(), true, true);
+                Poll::Pending
+            }
+            res => Poll::Ready(res),
+        }
+    }
+}
+
+impl Drop for AsyncFile {
+    fn drop(&mut self) {
+        unsafe { ffi::fs::fs_close(self.handle) }
+    }
+}
+
+fn poll_op(cx: &mut Context<'_>, f: impl FnOnce() -> isize) -> Poll<io::Result<usize>> {
+    match net::cvt(f()) {
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+            reactor::register_io(cx.waker(), true, true);
+            Poll::Pending
+        }
+        res => Poll::Ready(res),
+    }
+}
+
+fn metadata(handle: Handle) -> io::Result<Metadata> {
+    let mut metadata = ffi::fs::Metadata::default();
+    net::cvt(unsafe { ffi::fs::fs_file_metadata(handle, &mut metadata) } as isize)?;
+
+    Ok(Metadata::from_ffi(metadata))
+}
+
+fn seek(handle: Handle, pos: SeekFrom) -> io::Result<u64> {
+    let (offset, whence) = match pos {
+        SeekFrom::Start(offset) => (
+            i64::try_from(offset).map_err(|_| io::ErrorKind::InvalidInput)?,
+            ffi::fs::SEEK_START,
+        ),
+        SeekFrom::Current(offset) => (offset, ffi::fs::SEEK_CURRENT),
+        SeekFrom::End(offset) => (offset, ffi::fs::SEEK_END),
+    };
+
+    let mut new_pos = 0;
+    net::cvt(unsafe { ffi::fs::fs_seek(handle, offset, whence, &mut new_pos) } as isize)?;
+
+    Ok(new_pos)
+}
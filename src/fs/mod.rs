@@ -0,0 +1,251 @@
+use std::{
+    io::{self, Read, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{ffi, net};
+
+// Each app gets its own sandboxed file system on the device's flash. Paths are relative to the
+// app's root, and `..` can't be used to leave it.
+mod dir;
+mod file;
+
+pub use dir::{read_dir, DirEntry, ReadDir};
+pub use file::{AsyncFile, File};
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Metadata {
+    len: u64,
+    is_dir: bool,
+    modified: Option<SystemTime>,
+}
+
+impl Metadata {
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        !self.is_dir
+    }
+
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    fn from_ffi(metadata: ffi::fs::Metadata) -> Self {
+        Self {
+            len: metadata.len,
+            is_dir: metadata.is_dir,
+            modified: match metadata.modified {
+                0 => None,
+                secs => UNIX_EPOCH.checked_add(Duration::from_secs(secs)),
+            },
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Usage {
+    pub total: u64,
+    pub free: u64,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct OpenOptions {
+    flags: u32,
+}
+
+impl OpenOptions {
+    pub const fn new() -> Self {
+        Self { flags: 0 }
+    }
+
+    pub const fn with_read(self, read: bool) -> Self {
+        self.with_flag(ffi::fs::OPEN_READ, read)
+    }
+
+    pub const fn with_write(self, write: bool) -> Self {
+        self.with_flag(ffi::fs::OPEN_WRITE, write)
+    }
+
+    pub const fn with_append(self, append: bool) -> Self {
+        self.with_flag(ffi::fs::OPEN_APPEND, append)
+    }
+
+    pub const fn with_create(self, create: bool) -> Self {
+        self.with_flag(ffi::fs::OPEN_CREATE, create)
+    }
+
+    // Fails with `AlreadyExists` if the file is already there.
+    pub const fn with_create_new(self, create_new: bool) -> Self {
+        self.with_flag(ffi::fs::OPEN_CREATE_NEW, create_new)
+    }
+
+    pub const fn with_truncate(self, truncate: bool) -> Self {
+        self.with_flag(ffi::fs::OPEN_TRUNCATE, truncate)
+    }
+
+    const fn with_flag(mut self, flag: u32, set: bool) -> Self {
+        match set {
+            true => self.flags |= flag,
+            false => self.flags &= !flag,
+        }
+        self
+    }
+
+    pub fn open(&self, path: impl AsRef<Path>) -> io::Result<File> {
+        open(path.as_ref(), self.flags).map(File::from_handle)
+    }
+
+    pub fn open_async(&self, path: impl AsRef<Path>) -> io::Result<AsyncFile> {
+        open(path.as_ref(), self.flags | ffi::fs::OPEN_NONBLOCKING).map(AsyncFile::from_handle)
+    }
+}
+
+fn open(path: &Path, flags: u32) -> io::Result<ffi::io::Handle> {
+    let path = path_str(path)?;
+    let mut handle = 0;
+
+    net::cvt(unsafe { ffi::fs::fs_open(path.as_ptr(), path.len(), flags, &mut handle) } as isize)?;
+
+    Ok(handle)
+}
+
+pub fn metadata(path: impl AsRef<Path>) -> io::Result<Metadata> {
+    let path = path_str(path.as_ref())?;
+    let mut metadata = ffi::fs::Metadata::default();
+
+    net::cvt(unsafe { ffi::fs::fs_metadata(path.as_ptr(), path.len(), &mut metadata) } as isize)?;
+
+    Ok(Metadata::from_ffi(metadata))
+}
+
+pub fn exists(path: impl AsRef<Path>) -> io::Result<bool> {
+    match metadata(path) {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let mut buf = Vec::with_capacity(file.metadata()?.len() as usize);
+    file.read_to_end(&mut buf)?;
+
+    Ok(buf)
+}
+
+pub fn read_to_string(path: impl AsRef<Path>) -> io::Result<String> {
+    String::from_utf8(read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    File::create(path)?.write_all(contents.as_ref())
+}
+
+pub async fn read_async(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+    let mut file = OpenOptions::new().with_read(true).open_async(path)?;
+    let mut buf = Vec::with_capacity(file.metadata()?.len() as usize);
+    file.read_to_end(&mut buf).await?;
+
+    Ok(buf)
+}
+
+pub async fn write_async(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .with_write(true)
+        .with_create(true)
+        .with_truncate(true)
+        .open_async(path)?;
+
+    file.write_all(contents.as_ref()).await?;
+    file.flush().await
+}
+
+// Replaces `to` if it exists. Renaming within the same directory is atomic.
+pub fn rename(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+    let from = path_str(from.as_ref())?;
+    let to = path_str(to.as_ref())?;
+
+    net::cvt(
+        unsafe { ffi::fs::fs_rename(from.as_ptr(), from.len(), to.as_ptr(), to.len()) } as isize,
+    )
+    .map(drop)
+}
+
+pub fn remove_file(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path_str(path.as_ref())?;
+
+    net::cvt(unsafe { ffi::fs::fs_remove_file(path.as_ptr(), path.len()) } as isize).map(drop)
+}
+
+pub fn create_dir(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path_str(path.as_ref())?;
+
+    net::cvt(unsafe { ffi::fs::fs_create_dir(path.as_ptr(), path.len()) } as isize).map(drop)
+}
+
+pub fn create_dir_all(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+
+    if path.as_os_str().is_empty() || exists(path)? {
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
+    }
+
+    match create_dir(path) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => Err(e),
+        _ => Ok(()),
+    }
+}
+
+// Removes an empty directory.
+pub fn remove_dir(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path_str(path.as_ref())?;
+
+    net::cvt(unsafe { ffi::fs::fs_remove_dir(path.as_ptr(), path.len()) } as isize).map(drop)
+}
+
+pub fn remove_dir_all(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+
+    // Collect first so the directory isn't modified while it's being read.
+    let entries = read_dir(path)?.collect::<io::Result<Vec<_>>>()?;
+
+    for entry in entries {
+        match entry.metadata().is_dir() {
+            true => remove_dir_all(entry.path())?,
+            false => remove_file(entry.path())?,
+        }
+    }
+
+    remove_dir(path)
+}
+
+pub fn usage() -> io::Result<Usage> {
+    let mut usage = Usage::default();
+    net::cvt(unsafe { ffi::fs::fs_usage(&mut usage.total, &mut usage.free) } as isize)?;
+
+    Ok(usage)
+}
+
+fn path_str(path: &Path) -> io::Result<&str> {
+    path.to_str()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path is not valid UTF-8"))
+}
@@ -0,0 +1,259 @@
+use futures::{AsyncRead, AsyncReadExt};
+
+use super::{Error, Headers};
+
+const READ_CHUNK: usize = 512;
+const MAX_LINE: usize = 1024;
+
+// A minimal buffered reader over a connection. Everything read past the end of the message head
+// is kept around so it can be handed to the body decoder.
+pub(crate) struct Reader<'a, S> {
+    stream: &'a mut S,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<'a, S: AsyncRead + Unpin> Reader<'a, S> {
+    pub(crate) fn new(stream: &'a mut S) -> Self {
+        Self {
+            stream,
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut S {
+        self.stream
+    }
+
+    // Consumes the reader, returning any bytes that were read but not consumed.
+    pub(crate) fn into_buffered(mut self) -> Vec<u8> {
+        self.buf.drain(..self.pos);
+        self.buf
+    }
+
+    // Returns the number of buffered bytes which haven't been consumed yet.
+    pub(crate) fn buffered(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    // Reads until the blank line terminating a message head, returning the head including the
+    // terminator. Returns `Ok(None)` if the connection was closed before any bytes were read.
+    pub(crate) async fn read_head(&mut self, limit: usize) -> Result<Option<Vec<u8>>, Error> {
+        loop {
+            let pending = &self.buf[self.pos..];
+
+            if let Some(idx) = pending.windows(4).position(|w| w == b"\r\n\r\n") {
+                let head = pending[..idx + 4].to_vec();
+                self.pos += idx + 4;
+
+                return Ok(Some(head));
+            }
+
+            if self.buffered() > limit {
+                return Err(Error::HeadersTooLarge { limit });
+            }
+
+            if self.fill().await? == 0 {
+                return match self.buffered() {
+                    0 => Ok(None),
+                    _ => Err(Error::UnexpectedEof),
+                };
+            }
+        }
+    }
+
+    pub(crate) async fn read_body(
+        &mut self,
+        headers: &Headers,
+        until_close: bool,
+        limit: usize,
+    ) -> Result<Vec<u8>, Error> {
+        if headers.has_token("Transfer-Encoding", "chunked") {
+            self.read_chunked(limit).await
+        } else if let Some(len) = headers.content_length()? {
+            if len > limit {
+                return Err(Error::BodyTooLarge { limit });
+            }
+
+            self.read_exact(len).await
+        } else if until_close {
+            self.read_to_end(limit).await
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    async fn read_chunked(&mut self, limit: usize) -> Result<Vec<u8>, Error> {
+        let mut body = Vec::new();
+
+        loop {
+            let line = self.read_line().await?;
+            let size = line.split(|&b| b == b';').next().unwrap_or_default();
+            let size = std::str::from_utf8(size).map_err(|_| Error::InvalidChunk)?;
+            let size = usize::from_str_radix(size.trim(), 16).map_err(|_| Error::InvalidChunk)?;
+
+            if size == 0 {
+                break;
+            }
+
+            if body.len().saturating_add(size) > limit {
+                return Err(Error::BodyTooLarge { limit });
+            }
+
+            body.extend_from_slice(&self.read_exact(size).await?);
+
+            if !self.read_line().await?.is_empty() {
+                return Err(Error::InvalidChunk);
+            }
+        }
+
+        // Trailers aren't exposed, but they still have to be consumed to keep the connection in a
+        // usable state.
+        while !self.read_line().await?.is_empty() {}
+
+        Ok(body)
+    }
+
+    async fn read_to_end(&mut self, limit: usize) -> Result<Vec<u8>, Error> {
+        while self.fill().await? != 0 {
+            if self.buffered() > limit {
+                return Err(Error::BodyTooLarge { limit });
+            }
+        }
+
+        let body = self.buf[self.pos..].to_vec();
+        self.pos = self.buf.len();
+
+        Ok(body)
+    }
+
+    pub(crate) async fn read_exact(&mut self, len: usize) -> Result<Vec<u8>, Error> {
+        while self.buffered() < len {
+            if self.fill().await? == 0 {
+                return Err(Error::UnexpectedEof);
+            }
+        }
+
+        let data = self.buf[self.pos..self.pos + len].to_vec();
+        self.pos += len;
+
+        Ok(data)
+    }
+
+    // Reads a CRLF-terminated line, returning it without the terminator.
+    async fn read_line(&mut self) -> Result<Vec<u8>, Error> {
+        loop {
+            if let Some(idx) = self.buf[self.pos..].windows(2).position(|w| w == b"\r\n") {
+                let line = self.buf[self.pos..self.pos + idx].to_vec();
+                self.pos += idx + 2;
+
+                return Ok(line);
+            }
+
+            if self.buffered() > MAX_LINE {
+                return Err(Error::InvalidChunk);
+            }
+
+            if self.fill().await? == 0 {
+                return Err(Error::UnexpectedEof);
+            }
+        }
+    }
+
+    async fn fill(&mut self) -> Result<usize, Error> {
+        // Drop the consumed prefix before growing the buffer so it stays bounded by what is
+        // actually outstanding.
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+
+        // Read into a chunk of its own so a read cancelled by a timeout leaves the buffer as is.
+        let mut chunk = [0; READ_CHUNK];
+        let n = self.stream.read(&mut chunk).await?;
+        self.buf.extend_from_slice(&chunk[..n]);
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, io::Cursor};
+
+    use super::*;
+
+    fn chunked() -> Headers {
+        let mut headers = Headers::new();
+        headers.insert("Transfer-Encoding", "gzip, chunked");
+        headers
+    }
+
+    fn read_chunked(input: &[u8], limit: usize) -> (Result<Vec<u8>, Error>, Vec<u8>) {
+        let mut stream = Cursor::new(input.to_vec());
+        let mut reader = Reader::new(&mut stream);
+        let body = block_on(reader.read_body(&chunked(), false, limit));
+
+        (body, reader.into_buffered())
+    }
+
+    #[test]
+    fn chunked_body() {
+        let input = b"5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nX-Trailer: 1\r\n\r\nnext";
+        let (body, rest) = read_chunked(input, 1024);
+
+        assert_eq!(body.unwrap(), b"hello, world");
+        // Whatever follows belongs to the next message.
+        assert_eq!(rest, b"next");
+    }
+
+    #[test]
+    fn chunked_body_across_reads() {
+        // Bigger than a single fill, so chunks and their sizes straddle reads.
+        let data = (0..2000).map(|i| i as u8).collect::<Vec<_>>();
+        let mut input = Vec::new();
+
+        for chunk in data.chunks(300) {
+            input.extend_from_slice(format!("{:X}\r\n", chunk.len()).as_bytes());
+            input.extend_from_slice(chunk);
+            input.extend_from_slice(b"\r\n");
+        }
+        input.extend_from_slice(b"0\r\n\r\n");
+
+        assert_eq!(read_chunked(&input, 4096).0.unwrap(), data);
+    }
+
+    #[test]
+    fn chunked_body_invalid() {
+        let cases: &[&[u8]] = &[b"z\r\nhello\r\n0\r\n\r\n", b"5\r\nhelloX\r\n0\r\n\r\n"];
+
+        for input in cases {
+            assert!(matches!(
+                read_chunked(input, 1024).0,
+                Err(Error::InvalidChunk)
+            ));
+        }
+
+        assert!(matches!(
+            read_chunked(b"5\r\nhel", 1024).0,
+            Err(Error::UnexpectedEof)
+        ));
+        assert!(matches!(
+            read_chunked(b"5\r\nhello\r\n0\r\n\r\n", 4).0,
+            Err(Error::BodyTooLarge { limit: 4 })
+        ));
+    }
+
+    #[test]
+    fn head_then_body() {
+        let mut stream = Cursor::new(b"HTTP/1.1 200 OK\r\nA: b\r\n\r\nbody".to_vec());
+        let mut reader = Reader::new(&mut stream);
+
+        let head = block_on(reader.read_head(1024)).unwrap().unwrap();
+        assert_eq!(head, b"HTTP/1.1 200 OK\r\nA: b\r\n\r\n");
+
+        let body = block_on(reader.read_body(&Headers::new(), true, 1024)).unwrap();
+        assert_eq!(body, b"body");
+    }
+}
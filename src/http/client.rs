@@ -0,0 +1,511 @@
+use std::{
+    io,
+    pin::Pin,
+    str::Utf8Error,
+    task::{Context, Poll},
+};
+
+use futures::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use super::{
+    body::Reader, Error, Headers, Method, Url, DEFAULT_MAX_BODY_SIZE, DEFAULT_MAX_HEADER_SIZE,
+    MAX_HEADERS,
+};
+use crate::net::{TcpStream, TlsConnector, TlsStream};
+
+const DEFAULT_MAX_REDIRECTS: usize = 5;
+const MAX_IDLE_CONNECTIONS: usize = 2;
+const USER_AGENT: &str = concat!("xenon/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Request {
+    method: Method,
+    url: Url,
+    headers: Headers,
+    body: Vec<u8>,
+}
+
+impl Request {
+    pub fn new(method: Method, url: &str) -> Result<Self, Error> {
+        Ok(Self {
+            method,
+            url: Url::parse(url)?,
+            headers: Headers::new(),
+            body: Vec::new(),
+        })
+    }
+
+    pub fn get(url: &str) -> Result<Self, Error> {
+        Self::new(Method::Get, url)
+    }
+
+    pub fn head(url: &str) -> Result<Self, Error> {
+        Self::new(Method::Head, url)
+    }
+
+    pub fn post(url: &str) -> Result<Self, Error> {
+        Self::new(Method::Post, url)
+    }
+
+    pub fn put(url: &str) -> Result<Self, Error> {
+        Self::new(Method::Put, url)
+    }
+
+    pub fn delete(url: &str) -> Result<Self, Error> {
+        Self::new(Method::Delete, url)
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        &mut self.headers
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    fn encode_head(&self, keep_alive: bool) -> Result<Vec<u8>, Error> {
+        self.headers.validate()?;
+
+        let mut head = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\n",
+            self.method,
+            self.url.path(),
+            self.url.authority()
+        );
+
+        for (name, value) in self.headers.iter() {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+
+        if !self.headers.contains("User-Agent") {
+            head.push_str(&format!("User-Agent: {USER_AGENT}\r\n"));
+        }
+
+        let sends_body = !self.body.is_empty()
+            || matches!(self.method, Method::Post | Method::Put | Method::Patch);
+        if sends_body && !self.headers.contains("Content-Length") {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+
+        if !keep_alive {
+            head.push_str("Connection: close\r\n");
+        }
+
+        head.push_str("\r\n");
+        Ok(head.into_bytes())
+    }
+
+    fn redirect(mut self, status: u16, url: Url) -> Self {
+        // 303 always switches to GET, and so do 301 and 302 for POST, matching what browsers do.
+        // 307 and 308 must repeat the request as-is.
+        let to_get = match status {
+            303 => self.method != Method::Head,
+            301 | 302 => self.method == Method::Post,
+            _ => false,
+        };
+
+        if to_get {
+            self.method = Method::Get;
+            self.body.clear();
+            self.headers.remove("Content-Length");
+            self.headers.remove("Content-Type");
+        }
+
+        if url.host() != self.url.host()
+            || url.port() != self.url.port()
+            || url.is_secure() != self.url.is_secure()
+        {
+            self.headers.remove("Authorization");
+            self.headers.remove("Cookie");
+        }
+
+        self.url = url;
+        self
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Response {
+    status: u16,
+    reason: String,
+    headers: Headers,
+    body: Vec<u8>,
+    url: Url,
+}
+
+impl Response {
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    // The URL the response was received from, after following redirects.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn text(&self) -> Result<&str, Utf8Error> {
+        std::str::from_utf8(&self.body)
+    }
+
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+}
+
+enum Stream {
+    Plain(TcpStream),
+    Tls(TlsStream),
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            Self::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            Self::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_close(cx),
+            Self::Tls(stream) => Pin::new(stream).poll_close(cx),
+        }
+    }
+}
+
+struct Connection {
+    secure: bool,
+    host: String,
+    port: u16,
+    stream: Stream,
+}
+
+pub struct Client {
+    max_header_size: usize,
+    max_body_size: usize,
+    max_redirects: usize,
+    keep_alive: bool,
+    tls: TlsConnector,
+    idle: Vec<Connection>,
+}
+
+impl Client {
+    pub const fn new() -> Self {
+        Self {
+            max_header_size: DEFAULT_MAX_HEADER_SIZE,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            keep_alive: true,
+            tls: TlsConnector::new(),
+            idle: Vec::new(),
+        }
+    }
+
+    pub fn with_max_header_size(mut self, max_header_size: usize) -> Self {
+        self.max_header_size = max_header_size;
+        self
+    }
+
+    pub fn with_max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    pub fn with_max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    pub fn with_keep_alive(mut self, keep_alive: bool) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    // The TLS settings used for `https` URLs, e.g. to pin the server's certificate.
+    pub fn with_tls_connector(mut self, tls: TlsConnector) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    pub async fn get(&mut self, url: &str) -> Result<Response, Error> {
+        self.send(Request::get(url)?).await
+    }
+
+    pub async fn post(
+        &mut self,
+        url: &str,
+        content_type: &str,
+        body: impl Into<Vec<u8>>,
+    ) -> Result<Response, Error> {
+        let request = Request::post(url)?
+            .with_header("Content-Type", content_type)
+            .with_body(body);
+
+        self.send(request).await
+    }
+
+    pub async fn send(&mut self, mut request: Request) -> Result<Response, Error> {
+        for _ in 0..=self.max_redirects {
+            let response = self.send_once(&request).await?;
+
+            let location = match response.status {
+                301 | 302 | 303 | 307 | 308 => response.headers.get("Location"),
+                _ => None,
+            };
+
+            let Some(location) = location else {
+                return Ok(response);
+            };
+
+            let url = request.url.join(location)?;
+            request = request.redirect(response.status, url);
+        }
+
+        Err(Error::TooManyRedirects)
+    }
+
+    // Closes all idle keep-alive connections.
+    pub fn close_idle(&mut self) {
+        self.idle.clear();
+    }
+
+    async fn send_once(&mut self, request: &Request) -> Result<Response, Error> {
+        let secure = request.url.is_secure();
+        let host = request.url.host();
+        let port = request.url.port();
+
+        if let Some(idx) = self
+            .idle
+            .iter()
+            .position(|c| c.secure == secure && c.host == host && c.port == port)
+        {
+            let mut conn = self.idle.swap_remove(idx);
+
+            match self.exchange(&mut conn.stream, request).await {
+                Ok((response, reusable)) => {
+                    self.release(conn, reusable);
+                    return Ok(response);
+                }
+                // The server may have dropped the idle connection in the meantime, so try again
+                // on a fresh one. The request may have been handled before the connection broke
+                // though, so only do so if handling it twice is harmless.
+                Err(Error::Io(_) | Error::UnexpectedEof) if request.method.is_idempotent() => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let stream = TcpStream::connect_host(host, port).await?;
+        let stream = match secure {
+            true => Stream::Tls(self.tls.connect(host, stream).await?),
+            false => Stream::Plain(stream),
+        };
+
+        let mut conn = Connection {
+            secure,
+            host: host.to_owned(),
+            port,
+            stream,
+        };
+
+        let (response, reusable) = self.exchange(&mut conn.stream, request).await?;
+        self.release(conn, reusable);
+
+        Ok(response)
+    }
+
+    fn release(&mut self, conn: Connection, reusable: bool) {
+        if reusable && self.keep_alive {
+            if self.idle.len() >= MAX_IDLE_CONNECTIONS {
+                self.idle.remove(0);
+            }
+
+            self.idle.push(conn);
+        }
+    }
+
+    // Performs a single request/response exchange over `stream`, returning the response and
+    // whether the connection can be reused for another request.
+    pub(crate) async fn exchange<S>(
+        &self,
+        stream: &mut S,
+        request: &Request,
+    ) -> Result<(Response, bool), Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let head = request.encode_head(self.keep_alive)?;
+
+        stream.write_all(&head).await?;
+        stream.write_all(&request.body).await?;
+        stream.flush().await?;
+
+        let mut reader = Reader::new(stream);
+
+        loop {
+            let head = reader
+                .read_head(self.max_header_size)
+                .await?
+                .ok_or(Error::UnexpectedEof)?;
+
+            let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+            let mut parsed = httparse::Response::new(&mut headers);
+
+            if parsed.parse(&head)?.is_partial() {
+                return Err(Error::UnexpectedEof);
+            }
+
+            let status = parsed.code.unwrap_or_default();
+            let version = parsed.version.unwrap_or_default();
+
+            // Interim responses (e.g. 100 Continue) are followed by the real one.
+            if (100..200).contains(&status) {
+                continue;
+            }
+
+            let reason = parsed.reason.unwrap_or_default().to_owned();
+            let headers = Headers::from_parsed(parsed.headers)?;
+
+            let has_body = request.method != Method::Head && status != 204 && status != 304;
+            let framed = headers.has_token("Transfer-Encoding", "chunked")
+                || headers.content_length()?.is_some();
+
+            let body = if has_body {
+                reader
+                    .read_body(&headers, !framed, self.max_body_size)
+                    .await?
+            } else {
+                Vec::new()
+            };
+
+            let persistent = match version {
+                0 => headers.has_token("Connection", "keep-alive"),
+                _ => !headers.has_token("Connection", "close"),
+            };
+            let reusable =
+                persistent && (framed || !has_body) && reader.buffered() == 0 && self.keep_alive;
+
+            let response = Response {
+                status,
+                reason,
+                headers,
+                body,
+                url: request.url.clone(),
+            };
+
+            return Ok((response, reusable));
+        }
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn head() {
+        let request = Request::post("http://example.com:8080/a?b")
+            .unwrap()
+            .with_header("X-Token", "abc")
+            .with_body("hi");
+
+        let head = String::from_utf8(request.encode_head(false).unwrap()).unwrap();
+        assert_eq!(
+            head,
+            format!(
+                "POST /a?b HTTP/1.1\r\nHost: example.com:8080\r\nX-Token: abc\r\n\
+                 User-Agent: {USER_AGENT}\r\nContent-Length: 2\r\nConnection: close\r\n\r\n"
+            )
+        );
+    }
+
+    #[test]
+    fn header_injection() {
+        let headers = [
+            ("X-Token", "a\r\nEvil: 1"),
+            ("X-Token", "a\nb"),
+            ("X-Token", "a\0"),
+            ("Bad Name", "a"),
+            ("Evil: 1\r\nX-Token", "a"),
+            ("", "a"),
+        ];
+
+        for (name, value) in headers {
+            let request = Request::get("http://example.com")
+                .unwrap()
+                .with_header(name, value);
+
+            assert!(matches!(
+                request.encode_head(true),
+                Err(Error::InvalidHeader)
+            ));
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::{fmt, io, str::FromStr};
+
+use thiserror::Error;
+
+pub(crate) mod body;
+pub mod client;
+pub mod server;
+mod url;
+
+pub use client::{Client, Request, Response};
+pub use url::Url;
+
+pub const DEFAULT_MAX_HEADER_SIZE: usize = 8 * 1024;
+pub const DEFAULT_MAX_BODY_SIZE: usize = 64 * 1024;
+pub(crate) const MAX_HEADERS: usize = 32;
+
+pub fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        415 => "Unsupported Media Type",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Patch,
+    Options,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Get => "GET",
+            Self::Head => "HEAD",
+            Self::Post => "POST",
+            Self::Put => "PUT",
+            Self::Delete => "DELETE",
+            Self::Patch => "PATCH",
+            Self::Options => "OPTIONS",
+        }
+    }
+
+    // Whether sending the request twice has the same effect as sending it once (RFC 9110, 9.2.2).
+    pub fn is_idempotent(&self) -> bool {
+        !matches!(self, Self::Post | Self::Patch)
+    }
+}
+
+impl FromStr for Method {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "GET" => Ok(Self::Get),
+            "HEAD" => Ok(Self::Head),
+            "POST" => Ok(Self::Post),
+            "PUT" => Ok(Self::Put),
+            "DELETE" => Ok(Self::Delete),
+            "PATCH" => Ok(Self::Patch),
+            "OPTIONS" => Ok(Self::Options),
+            _ => Err(Error::UnsupportedMethod),
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Header names are compared case-insensitively, but kept in the order and case they were added
+// in. A Vec is smaller and faster than a map for the handful of headers a request carries.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Headers(Vec<(String, String)>);
+
+impl Headers {
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.0
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        self.remove(&name);
+        self.0.push((name, value.into()));
+    }
+
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.0.push((name.into(), value.into()));
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.0.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // Returns true if the comma-separated header `name` contains `token`, e.g. `Connection: close`.
+    pub(crate) fn has_token(&self, name: &str, token: &str) -> bool {
+        self.get_all(name)
+            .flat_map(|v| v.split(','))
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    }
+
+    // Checks that the headers can be written out as they are. Names must be tokens, and values
+    // can't contain line breaks, which would let them add headers of their own or end the head.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        let valid = self.0.iter().all(|(name, value)| {
+            is_token(name) && !value.bytes().any(|b| matches!(b, b'\r' | b'\n' | 0))
+        });
+
+        match valid {
+            true => Ok(()),
+            false => Err(Error::InvalidHeader),
+        }
+    }
+
+    pub(crate) fn content_length(&self) -> Result<Option<usize>, Error> {
+        match self.get("Content-Length") {
+            Some(len) => len
+                .trim()
+                .parse()
+                .map(Some)
+                .map_err(|_| Error::InvalidContentLength),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn from_parsed(headers: &[httparse::Header<'_>]) -> Result<Self, Error> {
+        headers
+            .iter()
+            .map(|h| {
+                let value = std::str::from_utf8(h.value).map_err(|_| Error::InvalidHeader)?;
+                Ok((h.name.to_owned(), value.to_owned()))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+// The characters allowed in header names (RFC 9110).
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid URL")]
+    InvalidUrl,
+    #[error("unsupported URL scheme")]
+    UnsupportedScheme,
+    #[error("unsupported HTTP method")]
+    UnsupportedMethod,
+    #[error("malformed HTTP message: {0}")]
+    Parse(#[from] httparse::Error),
+    #[error("invalid header name or value")]
+    InvalidHeader,
+    #[error("invalid Content-Length")]
+    InvalidContentLength,
+    #[error("invalid chunk encoding")]
+    InvalidChunk,
+    #[error("headers exceed {limit} bytes")]
+    HeadersTooLarge { limit: usize },
+    #[error("body exceeds {limit} bytes")]
+    BodyTooLarge { limit: usize },
+    #[error("too many redirects")]
+    TooManyRedirects,
+    #[error("connection closed unexpectedly")]
+    UnexpectedEof,
+}
@@ -0,0 +1,654 @@
+use std::{future::Future, io, str::Utf8Error};
+
+use futures::{
+    future::BoxFuture, stream::FuturesUnordered, AsyncRead, AsyncWrite, AsyncWriteExt, FutureExt,
+    StreamExt,
+};
+
+use super::{
+    body::Reader, reason_phrase, Error, Headers, Method, DEFAULT_MAX_BODY_SIZE,
+    DEFAULT_MAX_HEADER_SIZE, MAX_HEADERS,
+};
+use crate::{
+    net::{SocketAddr, TcpListener, TcpStream},
+    time::{self, Duration},
+};
+
+const DEFAULT_MAX_CONNECTIONS: usize = 4;
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Request {
+    method: Method,
+    path: String,
+    query: Option<String>,
+    headers: Headers,
+    body: Vec<u8>,
+    params: Vec<(String, String)>,
+    peer_addr: SocketAddr,
+}
+
+impl Request {
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    // Looks up a raw (not percent-decoded) query parameter.
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .as_deref()?
+            .split('&')
+            .filter_map(|pair| pair.split_once('=').or(Some((pair, ""))))
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v)
+    }
+
+    // Looks up a `:name` path parameter of the matched route. The remainder matched by a trailing
+    // `*` is available as `"*"`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn text(&self) -> Result<&str, Utf8Error> {
+        std::str::from_utf8(&self.body)
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Response {
+    status: u16,
+    headers: Headers,
+    body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Headers::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn ok() -> Self {
+        Self::new(200)
+    }
+
+    pub fn not_found() -> Self {
+        Self::new(404).with_text(reason_phrase(404))
+    }
+
+    pub fn text(body: impl Into<String>) -> Self {
+        Self::ok().with_text(body)
+    }
+
+    pub fn html(body: impl Into<String>) -> Self {
+        Self::ok()
+            .with_header("Content-Type", "text/html; charset=utf-8")
+            .with_body(body.into())
+    }
+
+    pub fn json(body: impl Into<String>) -> Self {
+        Self::ok()
+            .with_header("Content-Type", "application/json")
+            .with_body(body.into())
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    pub fn with_text(self, body: impl Into<String>) -> Self {
+        self.with_header("Content-Type", "text/plain; charset=utf-8")
+            .with_body(body.into())
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    fn encode_head(&self, keep_alive: bool) -> Vec<u8> {
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\n",
+            self.status,
+            reason_phrase(self.status)
+        );
+
+        for (name, value) in self.headers.iter() {
+            if !name.eq_ignore_ascii_case("Content-Length") {
+                head.push_str(&format!("{name}: {value}\r\n"));
+            }
+        }
+
+        head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+
+        if !keep_alive {
+            head.push_str("Connection: close\r\n");
+        }
+
+        head.push_str("\r\n");
+        head.into_bytes()
+    }
+}
+
+pub trait Handler: Send + Sync + 'static {
+    fn call(&self, request: Request) -> BoxFuture<'static, Response>;
+}
+
+impl<F, Fut> Handler for F
+where
+    F: Fn(Request) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Response> + Send + 'static,
+{
+    fn call(&self, request: Request) -> BoxFuture<'static, Response> {
+        self(request).boxed()
+    }
+}
+
+enum Segment {
+    Literal(String),
+    Param(String),
+    Wildcard,
+}
+
+struct Route {
+    method: Method,
+    segments: Vec<Segment>,
+    handler: Box<dyn Handler>,
+}
+
+impl Route {
+    fn matches(&self, path: &str) -> Option<Vec<(String, String)>> {
+        let mut parts = path.split('/').filter(|s| !s.is_empty());
+        let mut params = Vec::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Wildcard => {
+                    let rest = parts.collect::<Vec<_>>().join("/");
+                    params.push(("*".to_owned(), rest));
+
+                    return Some(params);
+                }
+                Segment::Literal(literal) => {
+                    if parts.next()? != literal {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => params.push((name.clone(), parts.next()?.to_owned())),
+            }
+        }
+
+        parts.next().is_none().then_some(params)
+    }
+}
+
+pub struct Server {
+    listener: TcpListener,
+    routes: Vec<Route>,
+    max_header_size: usize,
+    max_body_size: usize,
+    max_connections: usize,
+    read_timeout: Duration,
+}
+
+impl Server {
+    pub fn new(listener: TcpListener) -> Self {
+        Self {
+            listener,
+            routes: Vec::new(),
+            max_header_size: DEFAULT_MAX_HEADER_SIZE,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+        }
+    }
+
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        TcpListener::bind(addr).map(Self::new)
+    }
+
+    // Adds a route. Paths are matched segment by segment, where `:name` matches any single segment
+    // and a trailing `*` matches everything after it. Routes are tried in the order they were added.
+    pub fn route(mut self, method: Method, path: &str, handler: impl Handler) -> Self {
+        let segments = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| match (s, s.strip_prefix(':')) {
+                ("*", _) => Segment::Wildcard,
+                (_, Some(name)) => Segment::Param(name.to_owned()),
+                (s, None) => Segment::Literal(s.to_owned()),
+            })
+            .collect();
+
+        self.routes.push(Route {
+            method,
+            segments,
+            handler: Box::new(handler),
+        });
+
+        self
+    }
+
+    pub fn get(self, path: &str, handler: impl Handler) -> Self {
+        self.route(Method::Get, path, handler)
+    }
+
+    pub fn post(self, path: &str, handler: impl Handler) -> Self {
+        self.route(Method::Post, path, handler)
+    }
+
+    pub fn put(self, path: &str, handler: impl Handler) -> Self {
+        self.route(Method::Put, path, handler)
+    }
+
+    pub fn delete(self, path: &str, handler: impl Handler) -> Self {
+        self.route(Method::Delete, path, handler)
+    }
+
+    pub fn with_max_header_size(mut self, max_header_size: usize) -> Self {
+        self.max_header_size = max_header_size;
+        self
+    }
+
+    pub fn with_max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections.max(1);
+        self
+    }
+
+    // Sets how long a connection may take to send the head or the body of a request, which
+    // includes the wait for the next request on a kept alive connection.
+    pub fn with_read_timeout(mut self, read_timeout: Duration) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Accepts and serves connections until accepting fails. At most `max_connections` are served
+    // at once, further clients wait in the host's accept backlog.
+    pub async fn run(&self) -> io::Result<()> {
+        let mut connections = FuturesUnordered::new();
+
+        loop {
+            if connections.len() >= self.max_connections {
+                connections.next().await;
+                continue;
+            }
+
+            futures::select! {
+                res = self.listener.accept().fuse() => {
+                    let (stream, peer_addr) = res?;
+                    connections.push(self.serve_connection(stream, peer_addr));
+                }
+                _ = connections.select_next_some() => {}
+            }
+        }
+    }
+
+    async fn serve_connection(&self, mut stream: TcpStream, peer_addr: SocketAddr) {
+        if let Err(e) = self.serve(&mut stream, peer_addr).await {
+            log::debug!("HTTP connection from {peer_addr} failed: {e}");
+        }
+    }
+
+    // Serves requests from a single connection until either side closes it.
+    pub(crate) async fn serve<S>(&self, stream: &mut S, peer_addr: SocketAddr) -> Result<(), Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut reader = Reader::new(stream);
+
+        loop {
+            let head = reader.read_head(self.max_header_size);
+            let head = match time::timeout(self.read_timeout, head).await {
+                Ok(Ok(Some(head))) => head,
+                Ok(Ok(None)) => return Ok(()),
+                Ok(Err(Error::HeadersTooLarge { .. })) => {
+                    return Self::reject(reader.get_mut(), 431).await;
+                }
+                Ok(Err(e)) => return Err(e),
+                // An idle connection is closed quietly, a client that stalled halfway through a
+                // request is told why.
+                Err(_) if reader.buffered() == 0 => return Ok(()),
+                Err(_) => return Self::reject(reader.get_mut(), 408).await,
+            };
+
+            let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+            let mut parsed = httparse::Request::new(&mut headers);
+
+            match parsed.parse(&head) {
+                Ok(status) if status.is_complete() => {}
+                Err(httparse::Error::TooManyHeaders) => {
+                    return Self::reject(reader.get_mut(), 431).await;
+                }
+                _ => return Self::reject(reader.get_mut(), 400).await,
+            }
+
+            let Ok(method) = parsed.method.unwrap_or_default().parse::<Method>() else {
+                return Self::reject(reader.get_mut(), 501).await;
+            };
+            let Ok(headers) = Headers::from_parsed(parsed.headers) else {
+                return Self::reject(reader.get_mut(), 400).await;
+            };
+
+            let target = parsed.path.unwrap_or("/");
+            let (path, query) = match target.split_once('?') {
+                Some((path, query)) => (path.to_owned(), Some(query.to_owned())),
+                None => (target.to_owned(), None),
+            };
+
+            let keep_alive = match parsed.version.unwrap_or_default() {
+                0 => headers.has_token("Connection", "keep-alive"),
+                _ => !headers.has_token("Connection", "close"),
+            };
+
+            if headers.has_token("Expect", "100-continue") {
+                reader
+                    .get_mut()
+                    .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                    .await?;
+            }
+
+            let body = reader.read_body(&headers, false, self.max_body_size);
+            let body = match time::timeout(self.read_timeout, body).await {
+                Ok(Ok(body)) => body,
+                Ok(Err(Error::BodyTooLarge { .. })) => {
+                    return Self::reject(reader.get_mut(), 413).await;
+                }
+                Ok(Err(Error::InvalidChunk | Error::InvalidContentLength)) => {
+                    return Self::reject(reader.get_mut(), 400).await;
+                }
+                Ok(Err(e)) => return Err(e),
+                Err(_) => return Self::reject(reader.get_mut(), 408).await,
+            };
+
+            let request = Request {
+                method,
+                path,
+                query,
+                headers,
+                body,
+                params: Vec::new(),
+                peer_addr,
+            };
+
+            let mut response = self.dispatch(request).await;
+            // A CR or LF in a handler's header would let it smuggle extra headers or a second
+            // response onto the connection.
+            if response.headers.validate().is_err() {
+                log::warn!("HTTP handler returned an invalid header, answering 500 instead");
+                response = Response::new(500).with_text(reason_phrase(500));
+            }
+            let stream = reader.get_mut();
+
+            stream.write_all(&response.encode_head(keep_alive)).await?;
+            if method != Method::Head {
+                stream.write_all(&response.body).await?;
+            }
+            stream.flush().await?;
+
+            if !keep_alive {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn dispatch(&self, mut request: Request) -> Response {
+        let mut path_matched = false;
+
+        for route in &self.routes {
+            let Some(params) = route.matches(&request.path) else {
+                continue;
+            };
+
+            // HEAD is answered by GET handlers, the body is dropped when the response is written.
+            if route.method == request.method
+                || (route.method == Method::Get && request.method == Method::Head)
+            {
+                request.params = params;
+                return route.handler.call(request).await;
+            }
+
+            path_matched = true;
+        }
+
+        if path_matched {
+            Response::new(405).with_text(reason_phrase(405))
+        } else {
+            Response::not_found()
+        }
+    }
+
+    async fn reject<S>(stream: &mut S, status: u16) -> Result<(), Error>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        let response = Response::new(status).with_text(reason_phrase(status));
+
+        stream.write_all(&response.encode_head(false)).await?;
+        stream.write_all(&response.body).await?;
+        stream.flush().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::net::Ipv4Addr;
+
+    // A client which sends `input` and then either closes the connection or goes quiet.
+    struct Client {
+        input: Vec<u8>,
+        pos: usize,
+        hang: bool,
+        output: Vec<u8>,
+    }
+
+    impl AsyncRead for Client {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            let rest = &self.input[self.pos..];
+            if rest.is_empty() && self.hang {
+                return Poll::Pending;
+            }
+
+            let n = rest.len().min(buf.len());
+            buf[..n].copy_from_slice(&rest[..n]);
+            self.pos += n;
+
+            Poll::Ready(Ok(n))
+        }
+    }
+
+    impl AsyncWrite for Client {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.output.extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn server() -> Server {
+        Server::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 80))
+            .unwrap()
+            .get("/hello/:name", |request: Request| async move {
+                Response::text(format!("hello {}", request.param("name").unwrap()))
+            })
+            .post("/echo", |request: Request| async move {
+                Response::ok().with_body(request.body().to_vec())
+            })
+            .get("/evil", |_| async {
+                Response::ok().with_header("X-A", "b\r\nX-B: c")
+            })
+            .with_read_timeout(Duration::from_millis(50))
+    }
+
+    fn serve(server: &Server, input: &str, hang: bool) -> String {
+        let mut client = Client {
+            input: input.as_bytes().to_vec(),
+            pos: 0,
+            hang,
+            output: Vec::new(),
+        };
+        let peer_addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 1234);
+
+        block_on(server.serve(&mut client, peer_addr)).unwrap();
+        String::from_utf8(client.output).unwrap()
+    }
+
+    fn params(server: &Server, route: usize, path: &str) -> Option<Vec<(String, String)>> {
+        server.routes[route].matches(path)
+    }
+
+    #[test]
+    fn route_matches() {
+        let server = Server::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 80))
+            .unwrap()
+            .get("/", |_| async { Response::ok() })
+            .get("/a/:id/b", |_| async { Response::ok() })
+            .get("/files/*", |_| async { Response::ok() });
+
+        assert_eq!(params(&server, 0, "/"), Some(vec![]));
+        assert_eq!(params(&server, 0, "/a"), None);
+
+        assert_eq!(
+            params(&server, 1, "/a/42/b/"),
+            Some(vec![("id".to_owned(), "42".to_owned())])
+        );
+        assert_eq!(params(&server, 1, "/a/42"), None);
+        assert_eq!(params(&server, 1, "/a/42/b/c"), None);
+        assert_eq!(params(&server, 1, "/x/42/b"), None);
+
+        assert_eq!(
+            params(&server, 2, "/files/a/b.txt"),
+            Some(vec![("*".to_owned(), "a/b.txt".to_owned())])
+        );
+        assert_eq!(
+            params(&server, 2, "/files"),
+            Some(vec![("*".to_owned(), String::new())])
+        );
+    }
+
+    #[test]
+    fn keep_alive() {
+        let output = serve(
+            &server(),
+            "GET /hello/bob HTTP/1.1\r\nHost: a\r\n\r\n\
+             POST /echo HTTP/1.1\r\nContent-Length: 2\r\nConnection: close\r\n\r\nhi",
+            false,
+        );
+
+        assert_eq!(
+            output,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\n\
+             Content-Length: 9\r\n\r\nhello bob\
+             HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nhi"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let server = server();
+
+        let output = serve(&server, "GET /nope HTTP/1.1\r\n\r\n", false);
+        assert!(output.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        let output = serve(&server, "POST /hello/bob HTTP/1.1\r\n\r\n", false);
+        assert!(output.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+
+        let output = serve(&server, "GET / HTTP/1.1\r\nBad Header\r\n\r\n", false);
+        assert!(output.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+        let output = serve(&server, "GET /evil HTTP/1.1\r\n\r\n", false);
+        assert!(output.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(!output.contains("X-B"));
+    }
+
+    #[test]
+    fn read_timeout() {
+        let server = server();
+
+        // An idle keep-alive connection is closed without a response.
+        let output = serve(&server, "GET /hello/bob HTTP/1.1\r\n\r\n", true);
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(!output.contains("408"));
+
+        let output = serve(&server, "GET /hello/bob HTTP/1.1\r\nHost:", true);
+        assert!(output.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+
+        let output = serve(
+            &server,
+            "POST /echo HTTP/1.1\r\nContent-Length: 5\r\n\r\nhi",
+            true,
+        );
+        assert!(output.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+    }
+}
@@ -0,0 +1,244 @@
+use std::{fmt, str::FromStr};
+
+use super::Error;
+
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Url {
+    secure: bool,
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Url {
+    pub fn parse(url: &str) -> Result<Self, Error> {
+        // These would end up on the request line as they are.
+        if url.bytes().any(|b| b.is_ascii_control() || b == b' ') {
+            return Err(Error::InvalidUrl);
+        }
+
+        let (scheme, rest) = url.split_once("://").ok_or(Error::InvalidUrl)?;
+
+        let secure = match scheme {
+            s if s.eq_ignore_ascii_case("http") => false,
+            s if s.eq_ignore_ascii_case("https") => true,
+            _ => return Err(Error::UnsupportedScheme),
+        };
+        let default_port = default_port(secure);
+
+        let (authority, path) = match rest.find(['/', '?', '#']) {
+            Some(idx) => rest.split_at(idx),
+            None => (rest, "/"),
+        };
+
+        // Credentials in the authority aren't supported, and fragments are never sent.
+        if authority.contains('@') {
+            return Err(Error::InvalidUrl);
+        }
+        let path = path.split('#').next().unwrap_or_default();
+
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => {
+                (host, port.parse().map_err(|_| Error::InvalidUrl)?)
+            }
+            _ => (authority, default_port),
+        };
+
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(Error::InvalidUrl);
+        }
+
+        let path = match path {
+            "" => "/".to_owned(),
+            p if p.starts_with('?') => format!("/{p}"),
+            p => p.to_owned(),
+        };
+
+        Ok(Self {
+            secure,
+            host: host.to_owned(),
+            port,
+            path,
+        })
+    }
+
+    pub fn scheme(&self) -> &'static str {
+        match self.secure {
+            true => "https",
+            false => "http",
+        }
+    }
+
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    // The path and query, which is what goes on the request line.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // Resolves a `Location` header value against this URL.
+    pub fn join(&self, location: &str) -> Result<Self, Error> {
+        if location.contains("://") {
+            return Self::parse(location);
+        }
+
+        if let Some(rest) = location.strip_prefix("//") {
+            return Self::parse(&format!("{}://{rest}", self.scheme()));
+        }
+
+        let path = if location.starts_with('/') {
+            location.to_owned()
+        } else {
+            let base = self.path.split('?').next().unwrap_or_default();
+            let dir = &base[..base.rfind('/').map_or(0, |idx| idx + 1)];
+
+            format!("{dir}{location}")
+        };
+
+        Ok(Self {
+            secure: self.secure,
+            host: self.host.clone(),
+            port: self.port,
+            path,
+        })
+    }
+
+    // The value of the `Host` header, which omits the port if it's the default.
+    pub(crate) fn authority(&self) -> String {
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+
+        match self.port {
+            port if port == default_port(self.secure) => host,
+            port => format!("{host}:{port}"),
+        }
+    }
+}
+
+impl FromStr for Url {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}{}", self.scheme(), self.authority(), self.path)
+    }
+}
+
+fn default_port(secure: bool) -> u16 {
+    match secure {
+        true => 443,
+        false => 80,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let u = url("HTTPS://example.com:8443/a/b?c=d#frag");
+        assert!(u.is_secure());
+        assert_eq!(u.host(), "example.com");
+        assert_eq!(u.port(), 8443);
+        assert_eq!(u.path(), "/a/b?c=d");
+
+        let u = url("http://example.com");
+        assert_eq!((u.port(), u.path()), (80, "/"));
+
+        let u = url("http://example.com?q");
+        assert_eq!(u.path(), "/?q");
+
+        let u = url("http://[::1]:8080/x");
+        assert_eq!((u.host(), u.port()), ("::1", 8080));
+
+        let u = url("https://[fe80::1]");
+        assert_eq!((u.host(), u.port()), ("fe80::1", 443));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(matches!(Url::parse("example.com"), Err(Error::InvalidUrl)));
+        assert!(matches!(
+            Url::parse("ftp://example.com"),
+            Err(Error::UnsupportedScheme)
+        ));
+        assert!(matches!(Url::parse("http://"), Err(Error::InvalidUrl)));
+        assert!(matches!(
+            Url::parse("http://user@host/"),
+            Err(Error::InvalidUrl)
+        ));
+        assert!(matches!(
+            Url::parse("http://host:port/"),
+            Err(Error::InvalidUrl)
+        ));
+        assert!(matches!(
+            Url::parse("http://host:70000/"),
+            Err(Error::InvalidUrl)
+        ));
+        assert!(matches!(
+            Url::parse("http://host/a b"),
+            Err(Error::InvalidUrl)
+        ));
+        assert!(matches!(
+            Url::parse("http://host/\r\nX: y"),
+            Err(Error::InvalidUrl)
+        ));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            url("http://example.com:80/a").to_string(),
+            "http://example.com/a"
+        );
+        assert_eq!(
+            url("https://example.com:80/a").to_string(),
+            "https://example.com:80/a"
+        );
+        assert_eq!(url("http://[::1]:8080").to_string(), "http://[::1]:8080/");
+    }
+
+    #[test]
+    fn join() {
+        let base = url("https://example.com:8443/a/b?c=d");
+
+        assert_eq!(
+            base.join("/x?y").unwrap(),
+            url("https://example.com:8443/x?y")
+        );
+        assert_eq!(base.join("x").unwrap(), url("https://example.com:8443/a/x"));
+        assert_eq!(
+            base.join("//other.org/z").unwrap(),
+            url("https://other.org/z")
+        );
+        assert_eq!(
+            base.join("http://other.org").unwrap(),
+            url("http://other.org/")
+        );
+        assert_eq!(url("http://h").join("x").unwrap(), url("http://h/x"));
+    }
+}
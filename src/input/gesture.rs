@@ -0,0 +1,532 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{Stream, StreamExt};
+
+use crate::time::{Duration, Instant, Timer};
+
+use super::{Action, Button, ButtonEvent};
+
+const DEFAULT_LONG_PRESS: Duration = Duration::from_millis(500);
+const DEFAULT_CLICK_WINDOW: Duration = Duration::from_millis(250);
+const DEFAULT_CHORD_WINDOW: Duration = Duration::from_millis(80);
+const DEFAULT_MAX_CLICKS: u8 = 3;
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Gesture {
+    // `count` quick presses and releases of the same button, e.g. 2 for a double click. This is
+    // reported once the click window has passed without another one, or right away at the
+    // maximum count.
+    Click { button: Button, count: u8 },
+    // The button has been held for the long press time.
+    LongPress(Button),
+    // Sent while auto-repeat is on and the button is held, counting up from 1.
+    Repeat { button: Button, count: u32 },
+    // The button was released after a long press or repeats.
+    LongRelease(Button),
+    // Buttons pressed together within the chord window, in the order they were pressed. They
+    // don't produce any other gestures until they're released.
+    Chord(Vec<Button>),
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Held {
+    button: Button,
+    pressed_at: Instant,
+    long_sent: bool,
+    repeats: u32,
+    in_chord: bool,
+}
+
+#[derive(Clone, Debug)]
+struct ChordState {
+    buttons: Vec<Button>,
+    deadline: Instant,
+    sent: bool,
+}
+
+// Turns a stream of button events into gestures.
+//
+// Clicks are only reported after the click window, so a single click is delayed by that much
+// when double clicks are possible. Use `with_max_clicks(1)` to report them as soon as the button
+// is released.
+#[derive(Debug)]
+pub struct Gestures<S> {
+    events: S,
+    done: bool,
+    long_press: Duration,
+    repeat: Option<(Duration, Duration)>,
+    click_window: Duration,
+    chord_window: Duration,
+    max_clicks: u8,
+    held: Vec<Held>,
+    chord: Option<ChordState>,
+    // Button, clicks so far and when the click window closes.
+    clicks: Option<(Button, u8, Instant)>,
+    queue: VecDeque<Gesture>,
+    timer: Option<Timer>,
+}
+
+impl<S: Stream<Item = ButtonEvent> + Unpin> Gestures<S> {
+    pub fn new(events: S) -> Self {
+        Self {
+            events,
+            done: false,
+            long_press: DEFAULT_LONG_PRESS,
+            repeat: None,
+            click_window: DEFAULT_CLICK_WINDOW,
+            chord_window: DEFAULT_CHORD_WINDOW,
+            max_clicks: DEFAULT_MAX_CLICKS,
+            held: Vec::new(),
+            chord: None,
+            clicks: None,
+            queue: VecDeque::new(),
+            timer: None,
+        }
+    }
+
+    pub fn with_long_press(mut self, duration: Duration) -> Self {
+        self.long_press = duration;
+        self
+    }
+
+    // Sends `Repeat` after a button has been held for `delay`, and then every `interval`.
+    pub fn with_repeat(mut self, delay: Duration, interval: Duration) -> Self {
+        self.repeat = Some((delay, interval.max(Duration::from_millis(1))));
+        self
+    }
+
+    // How long after a release another press still counts towards the same click.
+    pub fn with_click_window(mut self, window: Duration) -> Self {
+        self.click_window = window;
+        self
+    }
+
+    // How close together presses have to be to count as a chord.
+    pub fn with_chord_window(mut self, window: Duration) -> Self {
+        self.chord_window = window;
+        self
+    }
+
+    pub fn with_max_clicks(mut self, max_clicks: u8) -> Self {
+        self.max_clicks = max_clicks.max(1);
+        self
+    }
+
+    fn handle(&mut self, event: ButtonEvent) {
+        match event.action {
+            Action::Press => self.press(event.button, event.timestamp),
+            Action::Release => self.release(event.button, event.timestamp),
+        }
+    }
+
+    fn press(&mut self, button: Button, timestamp: Instant) {
+        // The host shouldn't repeat presses, but there's nothing sensible to do with one.
+        if self.held.iter().any(|h| h.button == button) {
+            return;
+        }
+
+        if self.clicks.is_some_and(|(b, ..)| b != button) {
+            self.flush_clicks();
+        }
+
+        match &mut self.chord {
+            Some(chord) if !chord.sent && timestamp <= chord.deadline => chord.buttons.push(button),
+            _ if self.held.is_empty() => {
+                self.chord = Some(ChordState {
+                    buttons: vec![button],
+                    deadline: timestamp + self.chord_window,
+                    sent: false,
+                });
+            }
+            _ => {}
+        }
+
+        self.held.push(Held {
+            button,
+            pressed_at: timestamp,
+            long_sent: false,
+            repeats: 0,
+            in_chord: false,
+        });
+    }
+
+    fn release(&mut self, button: Button, timestamp: Instant) {
+        if self
+            .chord
+            .as_ref()
+            .is_some_and(|c| !c.sent && c.buttons.contains(&button))
+        {
+            self.resolve_chord();
+        }
+
+        let Some(index) = self.held.iter().position(|h| h.button == button) else {
+            return;
+        };
+        let held = self.held.remove(index);
+
+        if self.held.is_empty() {
+            self.chord = None;
+        }
+
+        if held.in_chord {
+            return;
+        }
+
+        if held.long_sent || held.repeats > 0 {
+            self.queue.push_back(Gesture::LongRelease(button));
+            return;
+        }
+
+        let count = match self.clicks {
+            Some((b, count, _)) if b == button => count + 1,
+            _ => {
+                self.flush_clicks();
+                1
+            }
+        };
+
+        self.clicks = Some((button, count, timestamp + self.click_window));
+        if count >= self.max_clicks {
+            self.flush_clicks();
+        }
+    }
+
+    // Fires anything that's due at `now`.
+    fn tick(&mut self, now: Instant) {
+        if self
+            .chord
+            .as_ref()
+            .is_some_and(|c| !c.sent && now >= c.deadline)
+        {
+            self.resolve_chord();
+        }
+
+        for held in self.held.iter_mut().filter(|h| !h.in_chord) {
+            if !held.long_sent && now >= held.pressed_at + self.long_press {
+                held.long_sent = true;
+                self.queue.push_back(Gesture::LongPress(held.button));
+            }
+
+            if let Some((delay, interval)) = self.repeat {
+                while now >= held.pressed_at + delay + interval * held.repeats {
+                    held.repeats += 1;
+                    self.queue.push_back(Gesture::Repeat {
+                        button: held.button,
+                        count: held.repeats,
+                    });
+                }
+            }
+        }
+
+        if self.clicks.is_some_and(|(.., deadline)| now >= deadline) {
+            self.flush_clicks();
+        }
+    }
+
+    fn resolve_chord(&mut self) {
+        let Some(chord) = &mut self.chord else {
+            return;
+        };
+
+        if chord.buttons.len() < 2 {
+            self.chord = None;
+            return;
+        }
+
+        chord.sent = true;
+        for held in &mut self.held {
+            held.in_chord |= chord.buttons.contains(&held.button);
+        }
+
+        self.queue.push_back(Gesture::Chord(chord.buttons.clone()));
+    }
+
+    fn flush_clicks(&mut self) {
+        if let Some((button, count, _)) = self.clicks.take() {
+            self.queue.push_back(Gesture::Click { button, count });
+        }
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        let chord = self.chord.as_ref().filter(|c| !c.sent).map(|c| c.deadline);
+        let clicks = self.clicks.map(|(.., deadline)| deadline);
+
+        let held = self.held.iter().filter(|h| !h.in_chord).flat_map(|h| {
+            let long_press = (!h.long_sent).then(|| h.pressed_at + self.long_press);
+            let repeat = self
+                .repeat
+                .map(|(delay, interval)| h.pressed_at + delay + interval * h.repeats);
+
+            long_press.into_iter().chain(repeat)
+        });
+
+        chord.into_iter().chain(clicks).chain(held).min()
+    }
+}
+
+impl<S: Stream<Item = ButtonEvent> + Unpin> Stream for Gestures<S> {
+    type Item = Gesture;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(gesture) = self.queue.pop_front() {
+                return Poll::Ready(Some(gesture));
+            }
+
+            if self.done {
+                return Poll::Ready(None);
+            }
+
+            match self.events.poll_next_unpin(cx) {
+                Poll::Ready(Some(event)) => {
+                    // Events can be read late, so fire whatever was due before this one happened
+                    // rather than judging it by the current time.
+                    self.tick(event.timestamp);
+                    self.handle(event);
+                    continue;
+                }
+                Poll::Ready(None) => {
+                    // Nothing else can happen, so report what's pending and finish.
+                    self.done = true;
+                    self.resolve_chord();
+                    self.flush_clicks();
+                    continue;
+                }
+                Poll::Pending => {}
+            }
+
+            self.tick(Instant::now());
+            if !self.queue.is_empty() {
+                continue;
+            }
+
+            let Some(deadline) = self.next_deadline() else {
+                self.timer = None;
+                return Poll::Pending;
+            };
+
+            // Keep the timer between polls, so its wakeup isn't cancelled.
+            let timer = match &mut self.timer {
+                Some(timer) if timer.deadline() == deadline => timer,
+                timer => timer.insert(Timer::at(deadline)),
+            };
+
+            if Pin::new(timer).poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.timer = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, stream};
+
+    use super::*;
+
+    type Events = stream::Iter<std::vec::IntoIter<ButtonEvent>>;
+
+    // Button events at made up times. The stream is always ready, so gestures are judged by the
+    // event timestamps alone.
+    struct Trace {
+        events: Vec<ButtonEvent>,
+        ms: u64,
+    }
+
+    impl Trace {
+        fn new() -> Self {
+            Self {
+                events: Vec::new(),
+                ms: 1000,
+            }
+        }
+
+        fn push(mut self, button: Button, action: Action) -> Self {
+            self.events.push(ButtonEvent {
+                button,
+                action,
+                timestamp: Instant::from_micros(self.ms * 1000),
+            });
+            self
+        }
+
+        fn press(self, button: Button) -> Self {
+            self.push(button, Action::Press)
+        }
+
+        fn release(self, button: Button) -> Self {
+            self.push(button, Action::Release)
+        }
+
+        fn wait(mut self, ms: u64) -> Self {
+            self.ms += ms;
+            self
+        }
+
+        fn click(self, button: Button) -> Self {
+            self.press(button).wait(50).release(button)
+        }
+
+        fn gestures_with(
+            &self,
+            f: impl FnOnce(Gestures<Events>) -> Gestures<Events>,
+        ) -> Vec<Gesture> {
+            let gestures = f(Gestures::new(stream::iter(self.events.clone())));
+            block_on(gestures.collect())
+        }
+
+        fn gestures(&self) -> Vec<Gesture> {
+            self.gestures_with(|g| g)
+        }
+    }
+
+    fn click(button: Button, count: u8) -> Gesture {
+        Gesture::Click { button, count }
+    }
+
+    fn repeat(button: Button, count: u32) -> Gesture {
+        Gesture::Repeat { button, count }
+    }
+
+    #[test]
+    fn clicks() {
+        let trace = Trace::new()
+            .click(Button::Select)
+            .wait(300)
+            .click(Button::Select)
+            .wait(100)
+            .click(Button::Select)
+            .wait(100)
+            .click(Button::Up)
+            .wait(100)
+            .click(Button::Up)
+            .wait(100)
+            .click(Button::Up);
+
+        assert_eq!(
+            trace.gestures(),
+            [
+                click(Button::Select, 1),
+                click(Button::Select, 2),
+                click(Button::Up, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn max_clicks() {
+        // The third click is reported at once, so the fourth starts over.
+        let trace = (0..4).fold(Trace::new(), |trace, _| trace.click(Button::Down).wait(100));
+        assert_eq!(
+            trace.gestures(),
+            [click(Button::Down, 3), click(Button::Down, 1)]
+        );
+
+        let trace = Trace::new()
+            .click(Button::Down)
+            .wait(100)
+            .click(Button::Down);
+        assert_eq!(
+            trace.gestures_with(|g| g.with_max_clicks(1)),
+            [click(Button::Down, 1), click(Button::Down, 1)]
+        );
+    }
+
+    #[test]
+    fn long_press() {
+        let trace = Trace::new()
+            .press(Button::Back)
+            .wait(400)
+            .release(Button::Back)
+            .wait(1000)
+            .press(Button::Back)
+            .wait(600)
+            .release(Button::Back);
+
+        assert_eq!(
+            trace.gestures(),
+            [
+                click(Button::Back, 1),
+                Gesture::LongPress(Button::Back),
+                Gesture::LongRelease(Button::Back)
+            ]
+        );
+    }
+
+    #[test]
+    fn repeats() {
+        let trace = Trace::new().press(Button::Up).wait(650).release(Button::Up);
+
+        assert_eq!(
+            trace.gestures_with(|g| g
+                .with_long_press(Duration::from_secs(1))
+                .with_repeat(Duration::from_millis(300), Duration::from_millis(100))),
+            [
+                repeat(Button::Up, 1),
+                repeat(Button::Up, 2),
+                repeat(Button::Up, 3),
+                repeat(Button::Up, 4),
+                Gesture::LongRelease(Button::Up)
+            ]
+        );
+    }
+
+    #[test]
+    fn chords() {
+        let trace = Trace::new()
+            .press(Button::Up)
+            .wait(30)
+            .press(Button::Down)
+            .wait(600)
+            .release(Button::Up)
+            .wait(20)
+            .release(Button::Down)
+            .wait(100)
+            .click(Button::Select);
+
+        assert_eq!(
+            trace.gestures(),
+            [
+                Gesture::Chord(vec![Button::Up, Button::Down]),
+                click(Button::Select, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn outside_chord_window() {
+        let trace = Trace::new()
+            .press(Button::Up)
+            .wait(200)
+            .click(Button::Down)
+            .wait(10)
+            .release(Button::Up);
+
+        assert_eq!(
+            trace.gestures(),
+            [click(Button::Down, 1), click(Button::Up, 1)]
+        );
+    }
+    #[test]
+    fn long_press_while_held() {
+        // Nothing else happens after the press, so only the timer can wake the stream up.
+        let press = ButtonEvent {
+            button: Button::Select,
+            action: Action::Press,
+            timestamp: Instant::now(),
+        };
+        let events = stream::iter([press]).chain(stream::pending());
+        let mut gestures = Gestures::new(events).with_long_press(Duration::from_millis(50));
+
+        assert_eq!(
+            block_on(gestures.next()),
+            Some(Gesture::LongPress(Button::Select))
+        );
+    }
+}
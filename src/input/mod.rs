@@ -0,0 +1,94 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+
+use crate::{asynch::reactor, ffi, time::Instant};
+
+mod gesture;
+
+pub use gesture::{Gesture, Gestures};
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Button {
+    Back,
+    Up,
+    Select,
+    Down,
+    // A button this crate doesn't know about, by the host's number for it.
+    Other(u8),
+}
+
+impl Button {
+    fn from_ffi(button: u8) -> Self {
+        match button {
+            ffi::input::BUTTON_BACK => Self::Back,
+            ffi::input::BUTTON_UP => Self::Up,
+            ffi::input::BUTTON_SELECT => Self::Select,
+            ffi::input::BUTTON_DOWN => Self::Down,
+            other => Self::Other(other),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Action {
+    Press,
+    Release,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct ButtonEvent {
+    pub button: Button,
+    pub action: Action,
+    // When the host saw the event, which may be a little before it's received.
+    pub timestamp: Instant,
+}
+
+impl ButtonEvent {
+    fn from_ffi(event: ffi::input::ButtonEvent) -> Self {
+        Self {
+            button: Button::from_ffi(event.button),
+            action: match event.pressed {
+                true => Action::Press,
+                false => Action::Release,
+            },
+            timestamp: Instant::from_micros(event.timestamp),
+        }
+    }
+}
+
+// Button events in the order they happened. The host keeps a single queue, so every event goes
+// to whichever stream polls first, and an app should only read from one at a time.
+pub fn events() -> Events {
+    Events { _private: () }
+}
+
+#[derive(Debug)]
+pub struct Events {
+    _private: (),
+}
+
+impl Events {
+    pub fn gestures(self) -> Gestures<Self> {
+        Gestures::new(self)
+    }
+}
+
+impl Stream for Events {
+    type Item = ButtonEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut event = ffi::input::ButtonEvent::default();
+
+        match unsafe { ffi::input::input_next(&mut event) } {
+            0 => Poll::Ready(Some(ButtonEvent::from_ffi(event))),
+            _ => {
+                reactor::register_input(cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use std::io;
+
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024;
+
+const LENGTH_PREFIX: usize = 4;
+const READ_CHUNK: usize = 512;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("serialization error: {0}")]
+    Serialization(#[from] postcard::Error),
+    #[error("frame of {len} bytes exceeds the limit of {limit} bytes")]
+    FrameTooLarge { len: usize, limit: usize },
+    #[error("stream ended in the middle of a frame")]
+    UnexpectedEof,
+}
+
+// Sends and receives messages over a byte stream. Every frame is a big-endian u32 length followed
+// by that many bytes, which `send` and `recv` fill with postcard-encoded values.
+pub struct Framed<S> {
+    stream: S,
+    read_buf: Vec<u8>,
+    max_frame_size: usize,
+}
+
+impl<S> Framed<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            read_buf: Vec::new(),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+
+    // Frames larger than this are rejected in both directions. A peer announcing a larger frame
+    // is treated as an error rather than buffered, since the stream can't be resynchronized.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    // Returns the stream along with any bytes that were read from it but not yet returned.
+    pub fn into_parts(self) -> (S, Vec<u8>) {
+        (self.stream, self.read_buf)
+    }
+
+    pub async fn send<T: Serialize + ?Sized>(&mut self, item: &T) -> Result<(), Error> {
+        let frame = postcard::to_allocvec(item)?;
+        self.send_frame(&frame).await
+    }
+
+    // Receives the next value, or `None` if the stream ended cleanly between frames.
+    pub async fn recv<T: DeserializeOwned>(&mut self) -> Result<Option<T>, Error> {
+        match self.recv_frame().await? {
+            Some(frame) => Ok(Some(postcard::from_bytes(&frame)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn send_frame(&mut self, frame: &[u8]) -> Result<(), Error> {
+        if frame.len() > self.max_frame_size {
+            return Err(Error::FrameTooLarge {
+                len: frame.len(),
+                limit: self.max_frame_size,
+            });
+        }
+
+        let mut buf = Vec::with_capacity(LENGTH_PREFIX + frame.len());
+        buf.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        buf.extend_from_slice(frame);
+
+        self.stream.write_all(&buf).await?;
+        self.stream.flush().await?;
+
+        Ok(())
+    }
+
+    // Partially received frames are kept in the read buffer, so this can be safely cancelled,
+    // e.g. when used in `select!`.
+    pub async fn recv_frame(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        loop {
+            if let Some(prefix) = self.read_buf.first_chunk::<LENGTH_PREFIX>() {
+                let len = u32::from_be_bytes(*prefix) as usize;
+
+                if len > self.max_frame_size {
+                    return Err(Error::FrameTooLarge {
+                        len,
+                        limit: self.max_frame_size,
+                    });
+                }
+
+                if self.read_buf.len() >= LENGTH_PREFIX + len {
+                    let frame = self.read_buf[LENGTH_PREFIX..LENGTH_PREFIX + len].to_vec();
+                    self.read_buf.drain(..LENGTH_PREFIX + len);
+
+                    return Ok(Some(frame));
+                }
+            }
+
+            let mut chunk = [0; READ_CHUNK];
+            let n = self.stream.read(&mut chunk).await?;
+
+            if n == 0 {
+                return match self.read_buf.is_empty() {
+                    true => Ok(None),
+                    false => Err(Error::UnexpectedEof),
+                };
+            }
+
+            self.read_buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    pub async fn close(&mut self) -> Result<(), Error> {
+        self.stream.close().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use futures::{executor::block_on, io::Cursor};
+
+    use super::*;
+
+    // Hands out the input `step` bytes at a time and discards anything written.
+    struct Trickle {
+        input: Vec<u8>,
+        pos: usize,
+        step: usize,
+    }
+
+    impl AsyncRead for Trickle {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            let n = (self.input.len() - self.pos).min(self.step).min(buf.len());
+            buf[..n].copy_from_slice(&self.input[self.pos..self.pos + n]);
+            self.pos += n;
+
+            Poll::Ready(Ok(n))
+        }
+    }
+
+    impl AsyncWrite for Trickle {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn trickle(input: Vec<u8>, step: usize) -> Framed<Trickle> {
+        Framed::new(Trickle {
+            input,
+            pos: 0,
+            step,
+        })
+    }
+
+    fn encode(items: &[(u32, String)]) -> Vec<u8> {
+        let mut framed = Framed::new(Cursor::new(Vec::new()));
+        for item in items {
+            block_on(framed.send(item)).unwrap();
+        }
+
+        framed.into_parts().0.into_inner()
+    }
+
+    #[test]
+    fn round_trip() {
+        let items = [
+            (1, "one".to_owned()),
+            (0, String::new()),
+            (u32::MAX, "x".repeat(2000)),
+        ];
+        let bytes = encode(&items);
+        assert_eq!(bytes[..LENGTH_PREFIX], [0, 0, 0, 5]);
+
+        // Splits the length prefixes and payloads across reads in different places.
+        for step in [1, 2, 3, 5, READ_CHUNK, bytes.len()] {
+            let mut framed = trickle(bytes.clone(), step);
+
+            for item in &items {
+                assert_eq!(
+                    block_on(framed.recv::<(u32, String)>()).unwrap().as_ref(),
+                    Some(item)
+                );
+            }
+            assert!(block_on(framed.recv::<(u32, String)>()).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn leftover_bytes() {
+        let mut bytes = encode(&[(7, "seven".to_owned())]);
+        bytes.extend_from_slice(b"raw");
+
+        let mut framed = trickle(bytes, READ_CHUNK);
+        assert_eq!(
+            block_on(framed.recv::<(u32, String)>()).unwrap(),
+            Some((7, "seven".to_owned()))
+        );
+        assert_eq!(framed.into_parts().1, b"raw");
+    }
+
+    #[test]
+    fn frame_too_large() {
+        let mut framed = Framed::new(Cursor::new(Vec::new())).with_max_frame_size(4);
+        assert!(matches!(
+            block_on(framed.send_frame(b"12345")),
+            Err(Error::FrameTooLarge { len: 5, limit: 4 })
+        ));
+        assert!(framed.get_ref().get_ref().is_empty());
+
+        // Rejected as soon as the length arrives, without waiting for the payload.
+        let mut framed = trickle(vec![0, 0, 0, 5, 1], 1).with_max_frame_size(4);
+        assert!(matches!(
+            block_on(framed.recv_frame()),
+            Err(Error::FrameTooLarge { len: 5, limit: 4 })
+        ));
+        assert_eq!(framed.get_ref().pos, LENGTH_PREFIX);
+
+        let mut framed = trickle(vec![0xff; 8], 8);
+        assert!(matches!(
+            block_on(framed.recv_frame()),
+            Err(Error::FrameTooLarge {
+                len: 0xffff_ffff,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn truncated() {
+        for len in [2, LENGTH_PREFIX + 1] {
+            let mut bytes = encode(&[(7, "seven".to_owned())]);
+            bytes.truncate(len);
+
+            let mut framed = trickle(bytes, 1);
+            assert!(matches!(
+                block_on(framed.recv_frame()),
+                Err(Error::UnexpectedEof)
+            ));
+        }
+    }
+}
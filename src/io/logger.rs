@@ -0,0 +1,258 @@
+use std::ptr;
+
+use log::{kv, Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+use crate::{ffi, net};
+
+const LOG_LEVEL_ERROR: u8 = 1;
+const LOG_LEVEL_WARN: u8 = 2;
+const LOG_LEVEL_INFO: u8 = 3;
+const LOG_LEVEL_DEBUG: u8 = 4;
+const LOG_LEVEL_TRACE: u8 = 5;
+
+// Configures a `Logger`. Filters are chosen by the longest target prefix that matches a record's
+// target on a `::` boundary, falling back to the default level.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Builder {
+    level: LevelFilter,
+    filters: Vec<(String, LevelFilter)>,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self {
+            level: LevelFilter::Info,
+            filters: Vec::new(),
+        }
+    }
+
+    // Applies the directives from `XENON_LOG` at compile time, then any configured by the host.
+    pub fn from_env() -> Self {
+        Self::new()
+            .with_directives(option_env!("XENON_LOG").unwrap_or_default())
+            .with_host_directives()
+    }
+
+    pub fn with_level(mut self, level: LevelFilter) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn with_filter(mut self, target: impl Into<String>, level: LevelFilter) -> Self {
+        let target = target.into();
+
+        match self.filters.iter_mut().find(|(t, _)| *t == target) {
+            Some((_, l)) => *l = level,
+            None => self.filters.push((target, level)),
+        }
+
+        self
+    }
+
+    // Parses env_logger style directives, e.g. `warn,xenon_firmware::net=debug,mqtt=off`. A bare
+    // level sets the default, and a bare target enables everything for it. Invalid directives are
+    // ignored.
+    pub fn with_directives(mut self, directives: &str) -> Self {
+        for directive in directives.split(',').map(str::trim) {
+            if directive.is_empty() {
+                continue;
+            }
+
+            self = match directive.split_once('=') {
+                Some((target, level)) => match level.trim().parse() {
+                    Ok(level) => self.with_filter(target.trim(), level),
+                    Err(_) => self,
+                },
+                None => match directive.parse() {
+                    Ok(level) => self.with_level(level),
+                    Err(_) => self.with_filter(directive, LevelFilter::Trace),
+                },
+            };
+        }
+
+        self
+    }
+
+    // Applies the directives configured on the host, if any.
+    pub fn with_host_directives(self) -> Self {
+        let mut buf = vec![0; 128];
+
+        loop {
+            let ret = unsafe { ffi::io::log_directives(buf.as_mut_ptr(), buf.len()) };
+
+            match net::cvt(ret) {
+                Ok(len) if len > buf.len() => buf.resize(len, 0),
+                Ok(len) => {
+                    buf.truncate(len);
+                    break;
+                }
+                Err(_) => return self,
+            }
+        }
+
+        match std::str::from_utf8(&buf) {
+            Ok(directives) => self.with_directives(directives),
+            Err(_) => self,
+        }
+    }
+
+    pub fn build(self) -> Logger {
+        Logger {
+            level: self.level,
+            filters: self.filters,
+        }
+    }
+
+    // Installs the logger and sets the maximum level to the most verbose filter.
+    pub fn init(self) -> Result<(), SetLoggerError> {
+        let logger = self.build();
+        let max_level = logger.max_level();
+
+        log::set_logger(Box::leak(Box::new(logger)))?;
+        log::set_max_level(max_level);
+
+        Ok(())
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Sends log records to the host, along with their source location and key-value pairs.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Logger {
+    level: LevelFilter,
+    filters: Vec<(String, LevelFilter)>,
+}
+
+impl Logger {
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    pub fn max_level(&self) -> LevelFilter {
+        self.filters
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.level, Ord::max)
+    }
+
+    pub fn filter(&self, target: &str) -> LevelFilter {
+        self.filters
+            .iter()
+            .filter(|(t, _)| {
+                target
+                    .strip_prefix(t.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(t, _)| t.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            log_to_host(record);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+// Passes a record to the host's `log_record` syscall, without any filtering.
+pub(crate) fn log_to_host(record: &Record) {
+    let msg;
+    let args = record.args();
+    let message = match args.as_str() {
+        Some(s) => s,
+        None => {
+            msg = args.to_string();
+            &msg
+        }
+    };
+
+    let mut fields = Fields(Vec::new());
+    let _ = record.key_values().visit(&mut fields);
+
+    let location = (record.module_path(), record.file(), record.line());
+    with_raw_record(
+        level_to_ffi(record.level()),
+        record.target(),
+        location,
+        message,
+        &fields.0,
+        |raw| unsafe { ffi::io::log_record(raw) },
+    );
+}
+
+// Builds the host's view of a record, which borrows everything passed in.
+pub(crate) fn with_raw_record<R>(
+    level: u8,
+    target: &str,
+    (module_path, file, line): (Option<&str>, Option<&str>, Option<u32>),
+    message: &str,
+    fields: &[(String, String)],
+    f: impl FnOnce(&ffi::io::LogRecord) -> R,
+) -> R {
+    let raw_fields = fields
+        .iter()
+        .map(|(key, value)| ffi::io::LogField {
+            key_ptr: key.as_ptr(),
+            key_len: key.len(),
+            value_ptr: value.as_ptr(),
+            value_len: value.len(),
+        })
+        .collect::<Vec<_>>();
+
+    let (module_path_ptr, module_path_len) = str_parts(module_path);
+    let (file_ptr, file_len) = str_parts(file);
+
+    f(&ffi::io::LogRecord {
+        level,
+        target_ptr: target.as_ptr(),
+        target_len: target.len(),
+        module_path_ptr,
+        module_path_len,
+        file_ptr,
+        file_len,
+        line: line.unwrap_or(0),
+        message_ptr: message.as_ptr(),
+        message_len: message.len(),
+        fields_ptr: raw_fields.as_ptr(),
+        fields_len: raw_fields.len(),
+    })
+}
+
+pub(crate) fn level_to_ffi(level: Level) -> u8 {
+    match level {
+        Level::Error => LOG_LEVEL_ERROR,
+        Level::Warn => LOG_LEVEL_WARN,
+        Level::Info => LOG_LEVEL_INFO,
+        Level::Debug => LOG_LEVEL_DEBUG,
+        Level::Trace => LOG_LEVEL_TRACE,
+    }
+}
+
+fn str_parts(s: Option<&str>) -> (*const u8, usize) {
+    match s {
+        Some(s) => (s.as_ptr(), s.len()),
+        None => (ptr::null(), 0),
+    }
+}
+
+struct Fields(Vec<(String, String)>);
+
+impl<'kvs> kv::VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.push((key.as_str().to_owned(), value.to_string()));
+        Ok(())
+    }
+}
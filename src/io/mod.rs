@@ -1 +1,8 @@
-pub mod stdio;
\ No newline at end of file
+pub mod framed;
+pub mod logger;
+pub mod remote;
+pub mod stdio;
+#[cfg(feature = "tracing")]
+pub mod trace;
+
+pub use stdio::{stderr, stdout, Stderr, Stdout};
\ No newline at end of file
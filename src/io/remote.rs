@@ -0,0 +1,200 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fmt::Write as _,
+    io, mem,
+    sync::atomic::{AtomicBool, Ordering},
+    task::Poll,
+};
+
+use critical_section as cs;
+use futures::{future::poll_fn, task::AtomicWaker, AsyncWriteExt};
+use log::{kv, Level, Log, Metadata, Record, SetLoggerError};
+
+use crate::{
+    ffi,
+    net::TcpStream,
+    time::{self, Duration, Instant},
+};
+
+use super::logger::{self, Logger};
+
+const DEFAULT_CAPACITY: usize = 256;
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default)]
+struct Ring {
+    lines: VecDeque<(Level, String)>,
+    dropped: usize,
+}
+
+// A log backend for development that streams formatted records to a TCP listener, e.g.
+// `nc -lk 4000` on a laptop. Records are queued in a bounded ring, dropping the oldest when it
+// fills up, and go to the host's log instead while there is no connection.
+//
+// `init` installs the logger, after which `run` has to be spawned to maintain the connection.
+#[derive(Debug)]
+pub struct RemoteLogger {
+    logger: Logger,
+    capacity: usize,
+    ring: cs::Mutex<RefCell<Ring>>,
+    waker: AtomicWaker,
+    connected: AtomicBool,
+}
+
+impl RemoteLogger {
+    pub fn new(logger: Logger) -> Self {
+        Self {
+            logger,
+            capacity: DEFAULT_CAPACITY,
+            ring: cs::Mutex::new(RefCell::new(Ring::default())),
+            waker: AtomicWaker::new(),
+            connected: AtomicBool::new(false),
+        }
+    }
+
+    // The number of records to hold while the connection can't keep up.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    pub fn init(self) -> Result<&'static Self, SetLoggerError> {
+        let this: &'static Self = Box::leak(Box::new(self));
+
+        log::set_logger(this)?;
+        log::set_max_level(this.logger.max_level());
+
+        Ok(this)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Acquire)
+    }
+
+    // Connects to `host:port` and streams records to it, reconnecting with an exponentially
+    // growing backoff whenever the connection fails.
+    pub async fn run(&self, host: &str, port: u16) -> ! {
+        let mut backoff = MIN_BACKOFF;
+
+        loop {
+            if let Ok(mut stream) = TcpStream::connect_host(host, port).await {
+                backoff = MIN_BACKOFF;
+                self.connected.store(true, Ordering::Release);
+
+                while let Ok(()) = self.send_next(&mut stream).await {}
+
+                self.connected.store(false, Ordering::Release);
+                self.drain_to_host();
+            }
+
+            time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    async fn send_next(&self, stream: &mut TcpStream) -> io::Result<()> {
+        let (line, dropped) = poll_fn(|cx| {
+            self.waker.register(cx.waker());
+
+            cs::with(|cs| {
+                let mut ring = self.ring.borrow_ref_mut(cs);
+
+                if ring.lines.is_empty() && ring.dropped == 0 {
+                    return Poll::Pending;
+                }
+
+                let dropped = mem::take(&mut ring.dropped);
+                Poll::Ready((ring.lines.pop_front(), dropped))
+            })
+        })
+        .await;
+
+        if dropped > 0 {
+            let notice = format!("... {dropped} records dropped\n");
+            stream.write_all(notice.as_bytes()).await?;
+        }
+
+        if let Some((level, line)) = line {
+            if let Err(e) = stream.write_all(line.as_bytes()).await {
+                // Put it back so it goes to the host along with the rest.
+                cs::with(|cs| self.ring.borrow_ref_mut(cs).lines.push_front((level, line)));
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Hands anything still queued to the host so it isn't lost with the connection.
+    fn drain_to_host(&self) {
+        let lines = cs::with(|cs| mem::take(&mut self.ring.borrow_ref_mut(cs).lines));
+
+        for (level, line) in lines {
+            let line = line.trim_end();
+            unsafe { ffi::io::log(logger::level_to_ffi(level), line.as_ptr(), line.len()) }
+        }
+    }
+}
+
+impl Log for RemoteLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.logger.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if !self.is_connected() {
+            let message = record.args().to_string();
+            let level = logger::level_to_ffi(record.level());
+            unsafe { ffi::io::log(level, message.as_ptr(), message.len()) }
+            return;
+        }
+
+        let line = format_line(record);
+
+        cs::with(|cs| {
+            let mut ring = self.ring.borrow_ref_mut(cs);
+
+            if ring.lines.len() >= self.capacity {
+                ring.lines.pop_front();
+                ring.dropped += 1;
+            }
+
+            ring.lines.push_back((record.level(), line));
+        });
+
+        self.waker.wake();
+    }
+
+    fn flush(&self) {}
+}
+
+// Formats a record as `<micros since boot> <LEVEL> <target>: <message> key=value...`.
+fn format_line(record: &Record) -> String {
+    let mut line = format!(
+        "{} {:<5} {}: {}",
+        Instant::now().as_micros(),
+        record.level(),
+        record.target(),
+        record.args()
+    );
+
+    let _ = record.key_values().visit(&mut Fields(&mut line));
+    line.push('\n');
+
+    line
+}
+
+struct Fields<'a>(&'a mut String);
+
+impl<'kvs> kv::VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let _ = write!(self.0, " {key}={value}");
+        Ok(())
+    }
+}
@@ -1,104 +1,153 @@
-use std::fmt;
+use std::{cell::RefCell, fmt, io};
 
-use log::Level;
+use critical_section as cs;
 
 use crate::ffi;
 
-const LOG_LEVEL_ERROR: u8 = 1;
-const LOG_LEVEL_WARN: u8 = 2;
-const LOG_LEVEL_INFO: u8 = 3;
-const LOG_LEVEL_DEBUG: u8 = 4;
-const LOG_LEVEL_TRACE: u8 = 5;
+use super::logger;
 
+// Partial lines are passed on once they get this long, so a missing newline can't grow the buffer
+// forever.
+const MAX_LINE_LEN: usize = 1024;
+
+static STDOUT: cs::Mutex<RefCell<Vec<u8>>> = cs::Mutex::new(RefCell::new(Vec::new()));
+static STDERR: cs::Mutex<RefCell<Vec<u8>>> = cs::Mutex::new(RefCell::new(Vec::new()));
+
+#[macro_export]
 macro_rules! print {
     ($($arg:tt)*) => {
-        $crate::ffi::io::print(::std::format_args!($($arg)*), false)
+        $crate::io::stdio::print(::std::format_args!($($arg)*), false)
     }
 }
 
+#[macro_export]
 macro_rules! println {
     ($($arg:tt)*) => {
-        $crate::ffi::io::print(::std::format_args!($($arg)*), true)
+        $crate::io::stdio::print(::std::format_args!($($arg)*), true)
     }
 }
 
+#[macro_export]
 macro_rules! eprint {
     ($($arg:tt)*) => {
-        $crate::ffi::io::eprint(::std::format_args!($($arg)*), false)
+        $crate::io::stdio::eprint(::std::format_args!($($arg)*), false)
     }
 }
 
+#[macro_export]
 macro_rules! eprintln {
     ($($arg:tt)*) => {
-        $crate::ffi::io::eprint(::std::format_args!($($arg)*), true)
+        $crate::io::stdio::eprint(::std::format_args!($($arg)*), true)
     }
 }
 
+// Installs a logger configured from `XENON_LOG` and the host. Use `logger::Builder` for more
+// control.
 pub fn init_logger() {
-    static LOGGER: Logger = Logger;
-
-    if log::set_logger(&LOGGER).is_err() {
+    if logger::Builder::from_env().init().is_err() {
         panic!("attempted to initialize logger twice")
     }
 }
 
 pub fn print(args: fmt::Arguments<'_>, new_line: bool) {
-    print_internal(args, new_line, ffi::io::print);
+    print_internal(stdout(), args, new_line);
 }
 
 pub fn eprint(args: fmt::Arguments<'_>, new_line: bool) {
-    print_internal(args, new_line, ffi::io::eprint)
+    print_internal(stderr(), args, new_line)
+}
+
+fn print_internal(mut out: impl fmt::Write, args: fmt::Arguments<'_>, new_line: bool) {
+    // Writing to the buffers never fails.
+    let _ = out.write_fmt(args);
+
+    if new_line {
+        let _ = out.write_char('\n');
+    }
 }
 
-fn print_internal(
-    args: fmt::Arguments<'_>,
-    new_line: bool,
-    f: unsafe extern "C" fn(*const u8, usize, bool),
-) {
-    let s;
-    let (ptr, len) = match args.as_str() {
-        Some(s) => (s.as_ptr(), s.len()),
-        None => {
-            s = args.to_string();
-            (s.as_ptr(), s.len())
+pub fn stdout() -> Stdout {
+    Stdout(())
+}
+
+pub fn stderr() -> Stderr {
+    Stderr(())
+}
+
+// A handle to the host's standard output. Output is line buffered, and all handles share the
+// same buffer.
+#[derive(Debug)]
+pub struct Stdout(());
+
+// Like `Stdout`, but for the host's standard error.
+#[derive(Debug)]
+pub struct Stderr(());
+
+macro_rules! impl_write {
+    ($ty:ty, $buffer:expr, $f:expr) => {
+        impl io::Write for $ty {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                write_buffered(&$buffer, $f, buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                flush(&$buffer, $f);
+                Ok(())
+            }
         }
-    };
 
-    unsafe { f(ptr, len, new_line) }
+        impl fmt::Write for $ty {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                write_buffered(&$buffer, $f, s.as_bytes());
+                Ok(())
+            }
+        }
+    };
 }
 
-struct Logger;
+impl_write!(Stdout, STDOUT, ffi::io::print);
+impl_write!(Stderr, STDERR, ffi::io::eprint);
 
-impl log::Log for Logger {
-    fn enabled(&self, metadata: &log::Metadata) -> bool {
-        metadata.level() >= log::max_level()
-    }
+type PrintFn = unsafe extern "C" fn(*const u8, usize, bool);
 
-    fn log(&self, record: &log::Record) {
-        if record.level() >= log::max_level() {
-            let msg;
-            let level = match record.level() {
-                Level::Error => LOG_LEVEL_ERROR,
-                Level::Warn => LOG_LEVEL_WARN,
-                Level::Info => LOG_LEVEL_INFO,
-                Level::Debug => LOG_LEVEL_DEBUG,
-                Level::Trace => LOG_LEVEL_TRACE,
-            };
-
-            let args = record.args();
-            let (ptr, len) = match args.as_str() {
-                Some(s) => (s.as_ptr(), s.len()),
-                None => {
-                    msg = args.to_string();
-                    (msg.as_ptr(), msg.len())
-                }
-            };
+// Buffers `buf`, passing every complete line to the host without its newline.
+fn write_buffered(buffer: &cs::Mutex<RefCell<Vec<u8>>>, f: PrintFn, mut buf: &[u8]) {
+    cs::with(|cs| {
+        let mut buffer = buffer.borrow_ref_mut(cs);
+
+        while let Some(pos) = buf.iter().position(|&b| b == b'\n') {
+            let line = &buf[..pos];
 
-            unsafe {
-                ffi::io::log(level, ptr, len);
+            match buffer.is_empty() {
+                true => unsafe { f(line.as_ptr(), line.len(), true) },
+                false => {
+                    buffer.extend_from_slice(line);
+                    unsafe { f(buffer.as_ptr(), buffer.len(), true) };
+                    buffer.clear();
+                }
             }
+
+            buf = &buf[pos + 1..];
         }
-    }
 
-    fn flush(&self) {}
+        buffer.extend_from_slice(buf);
+
+        if buffer.len() >= MAX_LINE_LEN {
+            unsafe { f(buffer.as_ptr(), buffer.len(), false) };
+            buffer.clear();
+        }
+    })
+}
+
+// Passes any partial line to the host as is.
+fn flush(buffer: &cs::Mutex<RefCell<Vec<u8>>>, f: PrintFn) {
+    cs::with(|cs| {
+        let mut buffer = buffer.borrow_ref_mut(cs);
+
+        if !buffer.is_empty() {
+            unsafe { f(buffer.as_ptr(), buffer.len(), false) };
+            buffer.clear();
+        }
+    })
 }
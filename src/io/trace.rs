@@ -0,0 +1,235 @@
+use std::{cell::RefCell, collections::HashMap, fmt};
+
+use critical_section as cs;
+use tracing_core::{
+    dispatcher::{self, SetGlobalDefaultError},
+    field::{Field, Visit},
+    span, Dispatch, Event, Level, LevelFilter, Metadata, Subscriber,
+};
+
+use crate::ffi;
+
+use super::logger;
+
+// Installs a `HostSubscriber` as the global default.
+pub fn init(max_level: LevelFilter) -> Result<(), SetGlobalDefaultError> {
+    dispatcher::set_global_default(Dispatch::new(HostSubscriber::new(max_level)))
+}
+
+#[derive(Debug, Default)]
+struct State {
+    next_id: u64,
+    // Reference counts of open spans.
+    spans: HashMap<u64, usize>,
+    // Entered spans, innermost last.
+    stack: Vec<u64>,
+}
+
+// A `tracing` subscriber that forwards spans and events to the host, timestamped with the host
+// clock, so enter/exit pairs can be turned into a profile.
+#[derive(Debug)]
+pub struct HostSubscriber {
+    max_level: LevelFilter,
+    state: cs::Mutex<RefCell<State>>,
+}
+
+impl HostSubscriber {
+    pub fn new(max_level: LevelFilter) -> Self {
+        Self {
+            max_level,
+            state: cs::Mutex::new(RefCell::new(State::default())),
+        }
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        cs::with(|cs| f(&mut self.state.borrow_ref_mut(cs)))
+    }
+
+    fn current(&self) -> u64 {
+        self.with_state(|state| state.stack.last().copied().unwrap_or(0))
+    }
+
+    fn parent(&self, explicit: Option<&span::Id>, contextual: bool) -> u64 {
+        match (explicit, contextual) {
+            (Some(id), _) => id.into_u64(),
+            (None, true) => self.current(),
+            (None, false) => 0,
+        }
+    }
+}
+
+impl Subscriber for HostSubscriber {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= &self.max_level
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(self.max_level)
+    }
+
+    fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+        let parent = self.parent(span.parent(), span.is_contextual());
+        let id = self.with_state(|state| {
+            state.next_id += 1;
+            state.spans.insert(state.next_id, 1);
+            state.next_id
+        });
+
+        let mut fields = Fields::default();
+        span.record(&mut fields);
+
+        let metadata = span.metadata();
+        let name = metadata.name();
+
+        with_raw_metadata(metadata, "", &fields.fields, |record| {
+            let raw = ffi::io::SpanRecord {
+                id,
+                parent,
+                timestamp: unsafe { ffi::time::get_time() },
+                name_ptr: name.as_ptr(),
+                name_len: name.len(),
+                record: *record,
+            };
+
+            unsafe { ffi::io::trace_span_new(&raw) }
+        });
+
+        span::Id::from_u64(id)
+    }
+
+    fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+        let mut fields = Fields::default();
+        values.record(&mut fields);
+
+        let raw_fields = fields
+            .fields
+            .iter()
+            .map(|(key, value)| ffi::io::LogField {
+                key_ptr: key.as_ptr(),
+                key_len: key.len(),
+                value_ptr: value.as_ptr(),
+                value_len: value.len(),
+            })
+            .collect::<Vec<_>>();
+
+        unsafe {
+            ffi::io::trace_span_record(span.into_u64(), raw_fields.as_ptr(), raw_fields.len())
+        }
+    }
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let parent = self.parent(event.parent(), event.is_contextual());
+
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+
+        let message = fields.message.take().unwrap_or_default();
+        let timestamp = unsafe { ffi::time::get_time() };
+
+        with_raw_metadata(
+            event.metadata(),
+            &message,
+            &fields.fields,
+            |record| unsafe { ffi::io::trace_event(parent, timestamp, record) },
+        );
+    }
+
+    fn enter(&self, span: &span::Id) {
+        let id = span.into_u64();
+        self.with_state(|state| state.stack.push(id));
+
+        unsafe { ffi::io::trace_span_enter(id, ffi::time::get_time()) }
+    }
+
+    fn exit(&self, span: &span::Id) {
+        let id = span.into_u64();
+
+        // Spans in different tasks don't have to exit in order.
+        self.with_state(|state| {
+            if let Some(pos) = state.stack.iter().rposition(|&s| s == id) {
+                state.stack.remove(pos);
+            }
+        });
+
+        unsafe { ffi::io::trace_span_exit(id, ffi::time::get_time()) }
+    }
+
+    fn clone_span(&self, span: &span::Id) -> span::Id {
+        self.with_state(|state| {
+            if let Some(refs) = state.spans.get_mut(&span.into_u64()) {
+                *refs += 1;
+            }
+        });
+
+        span.clone()
+    }
+
+    fn try_close(&self, span: span::Id) -> bool {
+        let id = span.into_u64();
+        let closed = self.with_state(|state| match state.spans.get_mut(&id) {
+            Some(refs) if *refs > 1 => {
+                *refs -= 1;
+                false
+            }
+            Some(_) => {
+                state.spans.remove(&id);
+                true
+            }
+            None => false,
+        });
+
+        if closed {
+            unsafe { ffi::io::trace_span_close(id) }
+        }
+
+        closed
+    }
+}
+
+fn with_raw_metadata<R>(
+    metadata: &Metadata<'_>,
+    message: &str,
+    fields: &[(String, String)],
+    f: impl FnOnce(&ffi::io::LogRecord) -> R,
+) -> R {
+    let level = match *metadata.level() {
+        Level::ERROR => log::Level::Error,
+        Level::WARN => log::Level::Warn,
+        Level::INFO => log::Level::Info,
+        Level::DEBUG => log::Level::Debug,
+        _ => log::Level::Trace,
+    };
+
+    logger::with_raw_record(
+        logger::level_to_ffi(level),
+        metadata.target(),
+        (metadata.module_path(), metadata.file(), metadata.line()),
+        message,
+        fields,
+        f,
+    )
+}
+
+#[derive(Default)]
+struct Fields {
+    message: Option<String>,
+    fields: Vec<(String, String)>,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = Some(value.to_owned()),
+            name => self.fields.push((name.to_owned(), value.to_owned())),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => self.message = Some(format!("{value:?}")),
+            name => self.fields.push((name.to_owned(), format!("{value:?}"))),
+        }
+    }
+}
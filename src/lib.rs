@@ -1,14 +1,30 @@
-#[cfg(not(all(target_family = "wasm", target_vendor = "unknown")))]
+// Unit tests run on the build machine instead, against the stand-in syscalls in `test_host`.
+#[cfg(not(any(test, all(target_family = "wasm", target_vendor = "unknown"))))]
 compile_error!("This crate is intended for use with the wasm32-unknown-unknown target only.");
 
 pub mod asynch;
+pub mod ble;
+pub mod coap;
+pub mod companion;
 pub mod critical_section;
 pub mod ffi;
+pub mod fs;
+pub mod http;
+pub mod input;
 pub mod io;
-pub(crate) mod panic;
+pub mod mqtt;
+pub mod net;
+pub mod panic;
 pub mod rng;
+pub mod sensors;
+pub mod storage;
+pub mod time;
+pub mod websocket;
 pub mod widget;
 
+#[cfg(all(test, not(target_family = "wasm")))]
+mod test_host;
+
 macro_rules! syscalls {
     (
         $(
@@ -36,6 +52,7 @@ pub(crate) use syscalls;
 extern "C" fn __xenon_start() {
     static EXECUTOR: StaticCell<Executor> = StaticCell::new();
 
+    panic::init();
     std::panic::set_hook(Box::new(panic::panic_hook));
     let executor = EXECUTOR.init(Executor::new());
 
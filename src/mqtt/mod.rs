@@ -0,0 +1,576 @@
+use std::{collections::VecDeque, future::pending, io, pin::Pin};
+
+use futures::{
+    future::{poll_fn, Either},
+    pin_mut, select_biased, AsyncRead, AsyncWriteExt, FutureExt,
+};
+use thiserror::Error;
+
+use crate::{
+    net::TcpStream,
+    rng,
+    time::{self, Duration, Instant},
+};
+
+mod packet;
+
+use packet::{Connect, Incoming, Outgoing};
+
+pub const DEFAULT_PORT: u16 = 1883;
+
+const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_PACKET_SIZE: usize = 16 * 1024;
+const DEFAULT_MIN_BACKOFF: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_CAPACITY: usize = 8;
+// Unacknowledged QoS 1 publishes are kept for retransmission. Once this many are outstanding, no
+// further requests are taken from the client until the broker catches up.
+const MAX_INFLIGHT: usize = 8;
+const READ_CHUNK: usize = 512;
+
+#[repr(u8)]
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum QoS {
+    AtMostOnce = 0,
+    AtLeastOnce = 1,
+}
+
+impl QoS {
+    fn from_u8(qos: u8) -> Option<Self> {
+        match qos {
+            0 => Some(Self::AtMostOnce),
+            1 => Some(Self::AtLeastOnce),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Publish {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub qos: QoS,
+    pub retain: bool,
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct LastWill {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub qos: QoS,
+    pub retain: bool,
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Options {
+    client_id: String,
+    keep_alive: Duration,
+    clean_session: bool,
+    username: Option<String>,
+    password: Option<Vec<u8>>,
+    will: Option<LastWill>,
+    max_packet_size: usize,
+    min_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Options {
+    pub fn new(client_id: impl Into<String>) -> Self {
+        Self {
+            client_id: client_id.into(),
+            keep_alive: DEFAULT_KEEP_ALIVE,
+            clean_session: true,
+            username: None,
+            password: None,
+            will: None,
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+            min_backoff: DEFAULT_MIN_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        }
+    }
+
+    // A zero keep-alive disables pings entirely.
+    pub fn with_keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive.min(Duration::from_secs(u16::MAX as u64));
+        self
+    }
+
+    pub fn with_clean_session(mut self, clean_session: bool) -> Self {
+        self.clean_session = clean_session;
+        self
+    }
+
+    pub fn with_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.username = Some(username.into());
+        self.password = Some(password.into());
+        self
+    }
+
+    pub fn with_last_will(mut self, will: LastWill) -> Self {
+        self.will = Some(will);
+        self
+    }
+
+    pub fn with_max_packet_size(mut self, max_packet_size: usize) -> Self {
+        self.max_packet_size = max_packet_size;
+        self
+    }
+
+    pub fn with_backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.min_backoff = min;
+        self.max_backoff = max.max(min);
+        self
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Event {
+    Connected { session_present: bool },
+    Message(Publish),
+    Subscribed { topic: String, granted: Option<QoS> },
+    Unsubscribed { topic: String },
+    Disconnected,
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("broker refused the connection (return code {0})")]
+    ConnectionRefused(u8),
+    #[error("protocol error: {0}")]
+    Protocol(&'static str),
+    #[error("packet exceeds {limit} bytes")]
+    PacketTooLarge { limit: usize },
+    #[error("invalid topic")]
+    InvalidTopic,
+    #[error("broker did not answer a ping in time")]
+    PingTimeout,
+    #[error("timed out connecting to the broker")]
+    ConnectTimeout,
+    #[error("connection closed by the broker")]
+    ConnectionClosed,
+    #[error("client was disconnected")]
+    Disconnected,
+}
+
+enum Request {
+    Publish(Publish),
+    Subscribe(String, QoS),
+    Unsubscribe(String),
+    Disconnect,
+}
+
+// A cheaply cloneable handle for issuing requests. They are carried out by the `EventLoop`, which
+// must be polled for anything to happen.
+#[derive(Clone)]
+pub struct Client {
+    requests: flume::Sender<Request>,
+}
+
+impl Client {
+    pub fn new(host: impl Into<String>, port: u16, options: Options) -> (Self, EventLoop) {
+        let (sender, receiver) = flume::bounded(REQUEST_CAPACITY);
+
+        let event_loop = EventLoop {
+            host: host.into(),
+            port,
+            backoff: options.min_backoff,
+            options,
+            requests: receiver,
+            conn: None,
+            next_pkid: 0,
+            inflight: VecDeque::new(),
+            subscriptions: Vec::new(),
+            pending_subscribes: Vec::new(),
+            pending_unsubscribes: Vec::new(),
+            reconnect_at: None,
+            disconnected: false,
+        };
+
+        (Self { requests: sender }, event_loop)
+    }
+
+    pub async fn publish(
+        &self,
+        topic: impl Into<String>,
+        qos: QoS,
+        retain: bool,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<(), Error> {
+        let topic = topic.into();
+
+        if topic.is_empty() || topic.contains(['+', '#']) {
+            return Err(Error::InvalidTopic);
+        }
+
+        self.request(Request::Publish(Publish {
+            topic,
+            payload: payload.into(),
+            qos,
+            retain,
+        }))
+        .await
+    }
+
+    pub async fn subscribe(&self, topic: impl Into<String>, qos: QoS) -> Result<(), Error> {
+        let topic = topic.into();
+
+        if topic.is_empty() {
+            return Err(Error::InvalidTopic);
+        }
+
+        self.request(Request::Subscribe(topic, qos)).await
+    }
+
+    pub async fn unsubscribe(&self, topic: impl Into<String>) -> Result<(), Error> {
+        self.request(Request::Unsubscribe(topic.into())).await
+    }
+
+    pub async fn disconnect(&self) -> Result<(), Error> {
+        self.request(Request::Disconnect).await
+    }
+
+    async fn request(&self, request: Request) -> Result<(), Error> {
+        self.requests
+            .send_async(request)
+            .await
+            .map_err(|_| Error::Disconnected)
+    }
+}
+
+struct Connection {
+    stream: TcpStream,
+    read_buf: Vec<u8>,
+    last_sent: Instant,
+    ping_sent: Option<Instant>,
+}
+
+impl Connection {
+    async fn send(&mut self, packet: Outgoing<'_>) -> Result<(), Error> {
+        let mut buf = Vec::new();
+        packet.encode(&mut buf);
+
+        self.stream.write_all(&buf).await?;
+        self.stream.flush().await?;
+        self.last_sent = Instant::now();
+
+        Ok(())
+    }
+
+    async fn recv(&mut self, max_packet_size: usize) -> Result<Incoming, Error> {
+        loop {
+            if let Some((packet, len)) = Incoming::decode(&self.read_buf, max_packet_size)? {
+                self.read_buf.drain(..len);
+                return Ok(packet);
+            }
+
+            if read_some(&mut self.stream, &mut self.read_buf).await? == 0 {
+                return Err(Error::ConnectionClosed);
+            }
+        }
+    }
+}
+
+pub struct EventLoop {
+    host: String,
+    port: u16,
+    options: Options,
+    requests: flume::Receiver<Request>,
+    conn: Option<Connection>,
+    next_pkid: u16,
+    inflight: VecDeque<(u16, Publish)>,
+    // Subscriptions are replayed on reconnect, in case the broker didn't keep the session.
+    subscriptions: Vec<(String, QoS)>,
+    pending_subscribes: Vec<(u16, String)>,
+    pending_unsubscribes: Vec<(u16, String)>,
+    reconnect_at: Option<Instant>,
+    backoff: Duration,
+    disconnected: bool,
+}
+
+impl EventLoop {
+    // Drives the connection and returns the next event. Connection failures are returned as
+    // errors, and the next call reconnects after an exponentially growing backoff.
+    pub async fn poll(&mut self) -> Result<Event, Error> {
+        if self.disconnected {
+            return Err(Error::Disconnected);
+        }
+
+        if self.conn.is_none() {
+            return self.reconnect().await;
+        }
+
+        loop {
+            match self.step().await {
+                Ok(Some(event)) => return Ok(event),
+                Ok(None) => {}
+                Err(e) => {
+                    self.conn = None;
+                    self.reconnect_at = Some(Instant::now() + self.backoff);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.conn.is_some()
+    }
+
+    async fn reconnect(&mut self) -> Result<Event, Error> {
+        if let Some(at) = self.reconnect_at.take() {
+            time::sleep_until(at).await;
+        }
+
+        let res = time::timeout(CONNECT_TIMEOUT, self.connect())
+            .await
+            .unwrap_or(Err(Error::ConnectTimeout));
+
+        match res {
+            Ok(event) => {
+                self.backoff = self.options.min_backoff;
+                Ok(event)
+            }
+            Err(e) => {
+                // Jitter keeps a fleet of watches from reconnecting in lockstep after an outage.
+                let jitter = Duration::from_millis(rng::random::<u64>() % 1000);
+                self.reconnect_at = Some(Instant::now() + self.backoff + jitter);
+                self.backoff = (self.backoff * 2).min(self.options.max_backoff);
+
+                Err(e)
+            }
+        }
+    }
+
+    async fn connect(&mut self) -> Result<Event, Error> {
+        let mut conn = Connection {
+            stream: TcpStream::connect_host(&self.host, self.port).await?,
+            read_buf: Vec::new(),
+            last_sent: Instant::now(),
+            ping_sent: None,
+        };
+
+        let options = &self.options;
+        conn.send(Outgoing::Connect(Connect {
+            client_id: &options.client_id,
+            keep_alive: options.keep_alive.as_secs() as u16,
+            clean_session: options.clean_session,
+            username: options.username.as_deref(),
+            password: options.password.as_deref(),
+            will: options.will.as_ref(),
+        }))
+        .await?;
+
+        let session_present = match conn.recv(options.max_packet_size).await? {
+            Incoming::ConnAck { code: 0, session_present } => session_present,
+            Incoming::ConnAck { code, .. } => return Err(Error::ConnectionRefused(code)),
+            _ => return Err(Error::Protocol("expected CONNACK")),
+        };
+
+        if !session_present {
+            self.pending_subscribes.clear();
+            self.pending_unsubscribes.clear();
+
+            for (topic, qos) in &self.subscriptions {
+                let pkid = next_pkid(&mut self.next_pkid);
+                conn.send(Outgoing::Subscribe {
+                    pkid,
+                    topic,
+                    qos: *qos,
+                })
+                .await?;
+                self.pending_subscribes.push((pkid, topic.clone()));
+            }
+        }
+
+        // Unacknowledged publishes must be retransmitted with the DUP flag after a reconnect.
+        for (pkid, publish) in &self.inflight {
+            conn.send(Outgoing::Publish {
+                publish,
+                pkid: *pkid,
+                dup: true,
+            })
+            .await?;
+        }
+
+        self.conn = Some(conn);
+        Ok(Event::Connected { session_present })
+    }
+
+    async fn step(&mut self) -> Result<Option<Event>, Error> {
+        enum Wake {
+            Read(io::Result<usize>),
+            Request(Result<Request, flume::RecvError>),
+            KeepAlive,
+        }
+
+        let max_packet_size = self.options.max_packet_size;
+        let keep_alive = self.options.keep_alive;
+        let conn = self.conn.as_mut().ok_or(Error::Disconnected)?;
+
+        if let Some((packet, len)) = Incoming::decode(&conn.read_buf, max_packet_size)? {
+            conn.read_buf.drain(..len);
+            return self.handle_incoming(packet).await;
+        }
+
+        let wake = {
+            let read = read_some(&mut conn.stream, &mut conn.read_buf).fuse();
+
+            let request = if self.inflight.len() < MAX_INFLIGHT {
+                Either::Left(self.requests.recv_async())
+            } else {
+                Either::Right(pending())
+            }
+            .fuse();
+
+            // The broker drops clients that stay silent for 1.5x the keep-alive, so ping once a
+            // full interval passes without sending anything. An unanswered ping is given the same
+            // interval before the connection is considered dead.
+            let keep_alive = if keep_alive.is_zero() {
+                Either::Left(pending())
+            } else {
+                let deadline = conn.ping_sent.unwrap_or(conn.last_sent) + keep_alive;
+                Either::Right(time::sleep_until(deadline))
+            }
+            .fuse();
+
+            pin_mut!(read, request, keep_alive);
+
+            select_biased! {
+                res = read => Wake::Read(res),
+                res = request => Wake::Request(res),
+                _ = keep_alive => Wake::KeepAlive,
+            }
+        };
+
+        match wake {
+            Wake::Read(Ok(0)) => Err(Error::ConnectionClosed),
+            Wake::Read(Ok(_)) => Ok(None),
+            Wake::Read(Err(e)) => Err(e.into()),
+            Wake::Request(Ok(request)) => self.handle_request(request).await,
+            // Every client handle was dropped, so nobody can issue requests anymore.
+            Wake::Request(Err(_)) => self.handle_request(Request::Disconnect).await,
+            Wake::KeepAlive if conn.ping_sent.is_some() => Err(Error::PingTimeout),
+            Wake::KeepAlive => {
+                conn.send(Outgoing::PingReq).await?;
+                conn.ping_sent = Some(Instant::now());
+                Ok(None)
+            }
+        }
+    }
+
+    async fn handle_incoming(&mut self, packet: Incoming) -> Result<Option<Event>, Error> {
+        let conn = self.conn.as_mut().ok_or(Error::Disconnected)?;
+
+        match packet {
+            Incoming::Publish { publish, pkid } => {
+                if publish.qos == QoS::AtLeastOnce {
+                    conn.send(Outgoing::PubAck(pkid)).await?;
+                }
+
+                Ok(Some(Event::Message(publish)))
+            }
+            Incoming::PubAck(pkid) => {
+                self.inflight.retain(|(id, _)| *id != pkid);
+                Ok(None)
+            }
+            Incoming::SubAck { pkid, granted } => {
+                Ok(take_pending(&mut self.pending_subscribes, pkid)
+                    .map(|topic| Event::Subscribed { topic, granted }))
+            }
+            Incoming::UnsubAck(pkid) => Ok(take_pending(&mut self.pending_unsubscribes, pkid)
+                .map(|topic| Event::Unsubscribed { topic })),
+            Incoming::PingResp => {
+                conn.ping_sent = None;
+                Ok(None)
+            }
+            Incoming::ConnAck { .. } => Err(Error::Protocol("unexpected CONNACK")),
+        }
+    }
+
+    async fn handle_request(&mut self, request: Request) -> Result<Option<Event>, Error> {
+        let conn = self.conn.as_mut().ok_or(Error::Disconnected)?;
+
+        match request {
+            Request::Publish(publish) => {
+                let pkid = match publish.qos {
+                    QoS::AtMostOnce => 0,
+                    QoS::AtLeastOnce => next_pkid(&mut self.next_pkid),
+                };
+
+                conn.send(Outgoing::Publish {
+                    publish: &publish,
+                    pkid,
+                    dup: false,
+                })
+                .await?;
+
+                if publish.qos == QoS::AtLeastOnce {
+                    self.inflight.push_back((pkid, publish));
+                }
+            }
+            Request::Subscribe(topic, qos) => {
+                let pkid = next_pkid(&mut self.next_pkid);
+                conn.send(Outgoing::Subscribe {
+                    pkid,
+                    topic: &topic,
+                    qos,
+                })
+                .await?;
+
+                self.subscriptions.retain(|(t, _)| *t != topic);
+                self.subscriptions.push((topic.clone(), qos));
+                self.pending_subscribes.push((pkid, topic));
+            }
+            Request::Unsubscribe(topic) => {
+                let pkid = next_pkid(&mut self.next_pkid);
+                conn.send(Outgoing::Unsubscribe {
+                    pkid,
+                    topic: &topic,
+                })
+                .await?;
+
+                self.subscriptions.retain(|(t, _)| *t != topic);
+                self.pending_unsubscribes.push((pkid, topic));
+            }
+            Request::Disconnect => {
+                // Best-effort, the connection is dropped either way.
+                let _ = conn.send(Outgoing::Disconnect).await;
+
+                self.conn = None;
+                self.disconnected = true;
+                return Ok(Some(Event::Disconnected));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+// Packet identifiers must be non-zero.
+fn next_pkid(pkid: &mut u16) -> u16 {
+    *pkid = pkid.checked_add(1).unwrap_or(1);
+    *pkid
+}
+
+fn take_pending(pending: &mut Vec<(u16, String)>, pkid: u16) -> Option<String> {
+    let idx = pending.iter().position(|(id, _)| *id == pkid)?;
+    Some(pending.remove(idx).1)
+}
+
+// Appends whatever the stream has available to `buf`. This is cancellation safe, so it can race
+// against other events without losing data.
+async fn read_some<S: AsyncRead + Unpin>(stream: &mut S, buf: &mut Vec<u8>) -> io::Result<usize> {
+    let mut chunk = [0; READ_CHUNK];
+
+    let n = poll_fn(|cx| Pin::new(&mut *stream).poll_read(cx, &mut chunk)).await?;
+    buf.extend_from_slice(&chunk[..n]);
+
+    Ok(n)
+}
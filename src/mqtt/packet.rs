@@ -0,0 +1,417 @@
+use super::{Error, LastWill, Publish, QoS};
+
+const CONNECT: u8 = 1;
+const CONNACK: u8 = 2;
+const PUBLISH: u8 = 3;
+const PUBACK: u8 = 4;
+const SUBSCRIBE: u8 = 8;
+const SUBACK: u8 = 9;
+const UNSUBSCRIBE: u8 = 10;
+const UNSUBACK: u8 = 11;
+const PINGREQ: u8 = 12;
+const PINGRESP: u8 = 13;
+const DISCONNECT: u8 = 14;
+
+const PROTOCOL_LEVEL: u8 = 4;
+const MAX_REMAINING_LENGTH: usize = 268_435_455;
+
+pub(crate) struct Connect<'a> {
+    pub client_id: &'a str,
+    pub keep_alive: u16,
+    pub clean_session: bool,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a [u8]>,
+    pub will: Option<&'a LastWill>,
+}
+
+// Packets sent by the client.
+pub(crate) enum Outgoing<'a> {
+    Connect(Connect<'a>),
+    Publish {
+        publish: &'a Publish,
+        pkid: u16,
+        dup: bool,
+    },
+    PubAck(u16),
+    Subscribe {
+        pkid: u16,
+        topic: &'a str,
+        qos: QoS,
+    },
+    Unsubscribe {
+        pkid: u16,
+        topic: &'a str,
+    },
+    PingReq,
+    Disconnect,
+}
+
+// Packets sent by the broker.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub(crate) enum Incoming {
+    ConnAck { session_present: bool, code: u8 },
+    Publish { publish: Publish, pkid: u16 },
+    PubAck(u16),
+    SubAck { pkid: u16, granted: Option<QoS> },
+    UnsubAck(u16),
+    PingResp,
+}
+
+impl Outgoing<'_> {
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        let mut body = Vec::new();
+
+        let header = match self {
+            Self::Connect(connect) => {
+                write_str(&mut body, "MQTT");
+                body.push(PROTOCOL_LEVEL);
+
+                let mut flags = 0;
+                if connect.clean_session {
+                    flags |= 0x02;
+                }
+                if let Some(will) = connect.will {
+                    flags |= 0x04 | ((will.qos as u8) << 3);
+                    if will.retain {
+                        flags |= 0x20;
+                    }
+                }
+                if connect.password.is_some() {
+                    flags |= 0x40;
+                }
+                if connect.username.is_some() {
+                    flags |= 0x80;
+                }
+                body.push(flags);
+                body.extend_from_slice(&connect.keep_alive.to_be_bytes());
+
+                write_str(&mut body, connect.client_id);
+                if let Some(will) = connect.will {
+                    write_str(&mut body, &will.topic);
+                    write_bytes(&mut body, &will.payload);
+                }
+                if let Some(username) = connect.username {
+                    write_str(&mut body, username);
+                }
+                if let Some(password) = connect.password {
+                    write_bytes(&mut body, password);
+                }
+
+                CONNECT << 4
+            }
+            Self::Publish { publish, pkid, dup } => {
+                write_str(&mut body, &publish.topic);
+                if publish.qos != QoS::AtMostOnce {
+                    body.extend_from_slice(&pkid.to_be_bytes());
+                }
+                body.extend_from_slice(&publish.payload);
+
+                (PUBLISH << 4)
+                    | ((*dup as u8) << 3)
+                    | ((publish.qos as u8) << 1)
+                    | publish.retain as u8
+            }
+            Self::PubAck(pkid) => {
+                body.extend_from_slice(&pkid.to_be_bytes());
+                PUBACK << 4
+            }
+            Self::Subscribe { pkid, topic, qos } => {
+                body.extend_from_slice(&pkid.to_be_bytes());
+                write_str(&mut body, topic);
+                body.push(*qos as u8);
+                (SUBSCRIBE << 4) | 0x02
+            }
+            Self::Unsubscribe { pkid, topic } => {
+                body.extend_from_slice(&pkid.to_be_bytes());
+                write_str(&mut body, topic);
+                (UNSUBSCRIBE << 4) | 0x02
+            }
+            Self::PingReq => PINGREQ << 4,
+            Self::Disconnect => DISCONNECT << 4,
+        };
+
+        out.push(header);
+        write_remaining_length(out, body.len());
+        out.extend_from_slice(&body);
+    }
+}
+
+impl Incoming {
+    // Tries to decode a single packet from the start of `buf`, returning the packet and the number
+    // of bytes it occupied, or `None` if `buf` doesn't hold a complete packet yet.
+    pub(crate) fn decode(buf: &[u8], max_packet_size: usize) -> Result<Option<(Self, usize)>, Error> {
+        let Some(&header) = buf.first() else {
+            return Ok(None);
+        };
+
+        let mut len = 0;
+        let mut header_len = 1;
+
+        loop {
+            let Some(&byte) = buf.get(header_len) else {
+                return Ok(None);
+            };
+
+            len |= ((byte & 0x7f) as usize) << (7 * (header_len - 1));
+            header_len += 1;
+
+            if byte & 0x80 == 0 {
+                break;
+            }
+
+            if header_len > 4 {
+                return Err(Error::Protocol("malformed remaining length"));
+            }
+        }
+
+        if len > max_packet_size {
+            return Err(Error::PacketTooLarge {
+                limit: max_packet_size,
+            });
+        }
+
+        let end = header_len + len;
+        let Some(body) = buf.get(header_len..end) else {
+            return Ok(None);
+        };
+
+        let packet = match header >> 4 {
+            CONNACK => match body {
+                [flags, code] => Self::ConnAck {
+                    session_present: flags & 0x01 != 0,
+                    code: *code,
+                },
+                _ => return Err(Error::Protocol("malformed CONNACK")),
+            },
+            PUBLISH => {
+                let qos = QoS::from_u8((header >> 1) & 0x03)
+                    .ok_or(Error::Protocol("unsupported QoS"))?;
+                let (topic, rest) = read_str(body)?;
+
+                let (pkid, payload) = match (qos, rest) {
+                    (QoS::AtMostOnce, rest) => (0, rest),
+                    (QoS::AtLeastOnce, [a, b, rest @ ..]) => (u16::from_be_bytes([*a, *b]), rest),
+                    _ => return Err(Error::Protocol("malformed PUBLISH")),
+                };
+
+                Self::Publish {
+                    publish: Publish {
+                        topic,
+                        payload: payload.to_vec(),
+                        qos,
+                        retain: header & 0x01 != 0,
+                    },
+                    pkid,
+                }
+            }
+            PUBACK => Self::PubAck(read_pkid(body)?),
+            SUBACK => match body {
+                [a, b, code] => Self::SubAck {
+                    pkid: u16::from_be_bytes([*a, *b]),
+                    granted: QoS::from_u8(*code),
+                },
+                _ => return Err(Error::Protocol("malformed SUBACK")),
+            },
+            UNSUBACK => Self::UnsubAck(read_pkid(body)?),
+            PINGRESP => Self::PingResp,
+            _ => return Err(Error::Protocol("unexpected packet type")),
+        };
+
+        Ok(Some((packet, end)))
+    }
+}
+
+fn write_remaining_length(out: &mut Vec<u8>, len: usize) {
+    debug_assert!(len <= MAX_REMAINING_LENGTH);
+
+    let mut len = len;
+
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+
+        if len > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+
+        if len == 0 {
+            break;
+        }
+    }
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_bytes(out, s.as_bytes());
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn read_str(buf: &[u8]) -> Result<(String, &[u8]), Error> {
+    let [a, b, rest @ ..] = buf else {
+        return Err(Error::Protocol("truncated string"));
+    };
+
+    let len = u16::from_be_bytes([*a, *b]) as usize;
+    if rest.len() < len {
+        return Err(Error::Protocol("truncated string"));
+    }
+
+    let (s, rest) = rest.split_at(len);
+    let s = String::from_utf8(s.to_vec()).map_err(|_| Error::Protocol("invalid UTF-8 string"))?;
+
+    Ok((s, rest))
+}
+
+fn read_pkid(buf: &[u8]) -> Result<u16, Error> {
+    match buf {
+        [a, b] => Ok(u16::from_be_bytes([*a, *b])),
+        _ => Err(Error::Protocol("malformed packet identifier")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(packet: Outgoing<'_>) -> Vec<u8> {
+        let mut out = Vec::new();
+        packet.encode(&mut out);
+        out
+    }
+
+    #[test]
+    fn publish_round_trip() {
+        // Payloads that need one, two and three bytes of remaining length.
+        for (len, qos) in [
+            (0, QoS::AtMostOnce),
+            (200, QoS::AtLeastOnce),
+            (20_000, QoS::AtLeastOnce),
+        ] {
+            let publish = Publish {
+                topic: "a/b".into(),
+                payload: (0..len).map(|i| i as u8).collect(),
+                qos,
+                retain: len == 200,
+            };
+            let pkid = if qos == QoS::AtMostOnce { 0 } else { 42 };
+
+            let mut buf = encode(Outgoing::Publish {
+                publish: &publish,
+                pkid,
+                dup: true,
+            });
+            let total = buf.len();
+            buf.push(PINGRESP << 4);
+
+            assert_eq!(
+                Incoming::decode(&buf, 32 * 1024).unwrap(),
+                Some((Incoming::Publish { publish, pkid }, total))
+            );
+        }
+    }
+
+    #[test]
+    fn connect() {
+        let will = LastWill {
+            topic: "w".into(),
+            payload: b"bye".to_vec(),
+            qos: QoS::AtLeastOnce,
+            retain: true,
+        };
+
+        let buf = encode(Outgoing::Connect(Connect {
+            client_id: "id",
+            keep_alive: 60,
+            clean_session: true,
+            username: Some("u"),
+            password: Some(b"p"),
+            will: Some(&will),
+        }));
+
+        #[rustfmt::skip]
+        let expected = [
+            0x10, 28,
+            0, 4, b'M', b'Q', b'T', b'T', 4, 0xee, 0, 60,
+            0, 2, b'i', b'd',
+            0, 1, b'w',
+            0, 3, b'b', b'y', b'e',
+            0, 1, b'u',
+            0, 1, b'p',
+        ];
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn control_packets() {
+        assert_eq!(encode(Outgoing::PubAck(0x1234)), [0x40, 2, 0x12, 0x34]);
+        assert_eq!(
+            encode(Outgoing::Subscribe {
+                pkid: 1,
+                topic: "t",
+                qos: QoS::AtLeastOnce
+            }),
+            [0x82, 6, 0, 1, 0, 1, b't', 1]
+        );
+        assert_eq!(
+            encode(Outgoing::Unsubscribe {
+                pkid: 1,
+                topic: "t"
+            }),
+            [0xa2, 5, 0, 1, 0, 1, b't']
+        );
+        assert_eq!(encode(Outgoing::PingReq), [0xc0, 0]);
+        assert_eq!(encode(Outgoing::Disconnect), [0xe0, 0]);
+    }
+
+    #[test]
+    fn decode_acks() {
+        let decode = |buf: &[u8]| Incoming::decode(buf, 1024).unwrap().unwrap().0;
+
+        assert_eq!(
+            decode(&[0x20, 2, 1, 5]),
+            Incoming::ConnAck {
+                session_present: true,
+                code: 5
+            }
+        );
+        assert_eq!(decode(&[0x40, 2, 0, 7]), Incoming::PubAck(7));
+        assert_eq!(
+            decode(&[0x90, 3, 0, 7, 0x80]),
+            Incoming::SubAck {
+                pkid: 7,
+                granted: None
+            }
+        );
+        assert_eq!(decode(&[0xb0, 2, 0, 7]), Incoming::UnsubAck(7));
+        assert_eq!(decode(&[0xd0, 0]), Incoming::PingResp);
+    }
+
+    #[test]
+    fn decode_partial_and_invalid() {
+        let buf = [0x90, 3, 0, 7, 1];
+        for len in 0..buf.len() {
+            assert_eq!(Incoming::decode(&buf[..len], 1024).unwrap(), None);
+        }
+
+        assert!(matches!(
+            Incoming::decode(&[0x30, 0xff, 0xff, 0xff, 0xff, 0x01], 1024),
+            Err(Error::Protocol(_))
+        ));
+        assert!(matches!(
+            Incoming::decode(&[0x30, 0x80, 0x01], 100),
+            Err(Error::PacketTooLarge { limit: 100 })
+        ));
+        assert!(matches!(
+            Incoming::decode(&[0x34, 0], 1024),
+            Err(Error::Protocol(_))
+        ));
+        assert!(matches!(
+            Incoming::decode(&[0x10, 0], 1024),
+            Err(Error::Protocol(_))
+        ));
+    }
+}
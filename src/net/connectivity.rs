@@ -0,0 +1,165 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{future::poll_fn, Stream};
+
+use super::IpAddr;
+use crate::{asynch::reactor, ffi};
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub enum Transport {
+    #[default]
+    None,
+    Wifi,
+    BleTether,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Status {
+    transport: Transport,
+    link_up: bool,
+    ip: Option<IpAddr>,
+    signal_strength: Option<i8>,
+}
+
+impl Status {
+    pub fn transport(&self) -> Transport {
+        self.transport
+    }
+
+    pub fn is_link_up(&self) -> bool {
+        self.link_up
+    }
+
+    pub fn ip_addr(&self) -> Option<IpAddr> {
+        self.ip
+    }
+
+    // The received signal strength in dBm, if the transport reports one.
+    pub fn signal_strength(&self) -> Option<i8> {
+        self.signal_strength
+    }
+
+    // Whether the link is up and has an address, i.e. connections can be attempted.
+    pub fn is_online(&self) -> bool {
+        self.link_up && self.ip.is_some()
+    }
+}
+
+pub fn status() -> Status {
+    let mut raw = ffi::net::NetStatus::default();
+    unsafe { ffi::net::net_status(&mut raw) };
+
+    let transport = match raw.transport {
+        ffi::net::TRANSPORT_WIFI => Transport::Wifi,
+        ffi::net::TRANSPORT_BLE_TETHER => Transport::BleTether,
+        _ => Transport::None,
+    };
+
+    Status {
+        transport,
+        link_up: raw.link_up && transport != Transport::None,
+        ip: raw.has_ip.then(|| std::net::SocketAddr::from(raw.ip).ip()),
+        signal_strength: raw.has_rssi.then_some(raw.rssi),
+    }
+}
+
+// Waits until the device is online, returning immediately if it already is.
+pub async fn wait_online() -> Status {
+    poll_fn(|cx| {
+        // Register first, so a change right after checking isn't missed.
+        reactor::register_net_change(cx.waker());
+
+        match status() {
+            status if status.is_online() => Poll::Ready(status),
+            _ => Poll::Pending,
+        }
+    })
+    .await
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Change {
+    LinkUp(Transport),
+    LinkDown,
+    IpAcquired(IpAddr),
+    IpLost,
+    SignalStrength(i8),
+}
+
+// A stream of connectivity changes. The status is only sampled when the host reports a change, so
+// short-lived intermediate states may be coalesced.
+pub struct Changes {
+    last: Status,
+    pending: VecDeque<Change>,
+}
+
+pub fn changes() -> Changes {
+    Changes {
+        last: status(),
+        pending: VecDeque::new(),
+    }
+}
+
+impl Changes {
+    // The status as of the last change yielded by the stream.
+    pub fn last_status(&self) -> Status {
+        self.last
+    }
+
+    fn diff(&mut self, current: Status) {
+        let last = self.last;
+
+        if last.is_link_up() && !current.is_link_up() {
+            if last.ip.is_some() {
+                self.pending.push_back(Change::IpLost);
+            }
+            self.pending.push_back(Change::LinkDown);
+        } else if current.is_link_up()
+            && (!last.is_link_up() || last.transport != current.transport)
+        {
+            self.pending.push_back(Change::LinkUp(current.transport));
+        }
+
+        if current.is_link_up() {
+            match (last.ip, current.ip) {
+                (_, Some(ip)) if last.ip != Some(ip) || !last.is_link_up() => {
+                    self.pending.push_back(Change::IpAcquired(ip));
+                }
+                (Some(_), None) => self.pending.push_back(Change::IpLost),
+                _ => {}
+            }
+
+            if let Some(rssi) = current.signal_strength {
+                if last.signal_strength != Some(rssi) {
+                    self.pending.push_back(Change::SignalStrength(rssi));
+                }
+            }
+        }
+
+        self.last = current;
+    }
+}
+
+impl Stream for Changes {
+    type Item = Change;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if let Some(change) = this.pending.pop_front() {
+            return Poll::Ready(Some(change));
+        }
+
+        reactor::register_net_change(cx.waker());
+        this.diff(status());
+
+        match this.pending.pop_front() {
+            Some(change) => Poll::Ready(Some(change)),
+            None => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,474 @@
+use super::{Error, Ipv4Addr, Ipv6Addr};
+
+pub(crate) const TYPE_A: u16 = 1;
+pub(crate) const TYPE_PTR: u16 = 12;
+pub(crate) const TYPE_TXT: u16 = 16;
+pub(crate) const TYPE_AAAA: u16 = 28;
+pub(crate) const TYPE_SRV: u16 = 33;
+pub(crate) const TYPE_ANY: u16 = 255;
+
+pub(crate) const CLASS_IN: u16 = 1;
+// In questions this bit requests a unicast response, in records it's the cache-flush bit.
+pub(crate) const CLASS_TOP_BIT: u16 = 0x8000;
+
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_AUTHORITATIVE: u16 = 0x0400;
+const MAX_POINTER_JUMPS: usize = 16;
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub(crate) struct Question {
+    pub name: String,
+    pub qtype: u16,
+    pub unicast_response: bool,
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub(crate) enum RData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Ptr(String),
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+    Txt(Vec<String>),
+    Other(u16, Vec<u8>),
+}
+
+impl RData {
+    pub(crate) fn rtype(&self) -> u16 {
+        match self {
+            Self::A(_) => TYPE_A,
+            Self::Aaaa(_) => TYPE_AAAA,
+            Self::Ptr(_) => TYPE_PTR,
+            Self::Srv { .. } => TYPE_SRV,
+            Self::Txt(_) => TYPE_TXT,
+            Self::Other(rtype, _) => *rtype,
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub(crate) struct Record {
+    pub name: String,
+    pub ttl: u32,
+    pub cache_flush: bool,
+    pub data: RData,
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub(crate) struct Message {
+    pub id: u16,
+    pub response: bool,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+    pub additionals: Vec<Record>,
+}
+
+impl Message {
+    pub(crate) fn query(questions: Vec<Question>) -> Self {
+        Self {
+            questions,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn response(id: u16) -> Self {
+        Self {
+            id,
+            response: true,
+            ..Self::default()
+        }
+    }
+
+    // Every record in a message, answers first.
+    pub(crate) fn records(&self) -> impl Iterator<Item = &Record> {
+        self.answers.iter().chain(self.additionals.iter())
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(512);
+
+        let flags = if self.response {
+            FLAG_RESPONSE | FLAG_AUTHORITATIVE
+        } else {
+            0
+        };
+
+        for field in [
+            self.id,
+            flags,
+            self.questions.len() as u16,
+            self.answers.len() as u16,
+            0,
+            self.additionals.len() as u16,
+        ] {
+            out.extend_from_slice(&field.to_be_bytes());
+        }
+
+        for question in &self.questions {
+            write_name(&mut out, &question.name);
+            out.extend_from_slice(&question.qtype.to_be_bytes());
+
+            let class = CLASS_IN
+                | if question.unicast_response {
+                    CLASS_TOP_BIT
+                } else {
+                    0
+                };
+            out.extend_from_slice(&class.to_be_bytes());
+        }
+
+        for record in self.records() {
+            write_record(&mut out, record);
+        }
+
+        out
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader { buf, pos: 0 };
+
+        let id = reader.u16()?;
+        let flags = reader.u16()?;
+        let qdcount = reader.u16()?;
+        let ancount = reader.u16()?;
+        let nscount = reader.u16()?;
+        let arcount = reader.u16()?;
+
+        let mut message = Self {
+            id,
+            response: flags & FLAG_RESPONSE != 0,
+            ..Self::default()
+        };
+
+        for _ in 0..qdcount {
+            let name = reader.name()?;
+            let qtype = reader.u16()?;
+            let class = reader.u16()?;
+
+            message.questions.push(Question {
+                name,
+                qtype,
+                unicast_response: class & CLASS_TOP_BIT != 0,
+            });
+        }
+
+        for _ in 0..ancount {
+            message.answers.push(reader.record()?);
+        }
+
+        // Authority records only matter for probing, which isn't implemented.
+        for _ in 0..nscount {
+            reader.record()?;
+        }
+
+        for _ in 0..arcount {
+            message.additionals.push(reader.record()?);
+        }
+
+        Ok(message)
+    }
+}
+
+// Names are compared case-insensitively, and without any trailing dot.
+pub(crate) fn name_eq(a: &str, b: &str) -> bool {
+    a.trim_end_matches('.')
+        .eq_ignore_ascii_case(b.trim_end_matches('.'))
+}
+
+fn write_name(out: &mut Vec<u8>, name: &str) {
+    for label in name
+        .trim_end_matches('.')
+        .split('.')
+        .filter(|l| !l.is_empty())
+    {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        out.push(label.len() as u8);
+        out.extend_from_slice(label);
+    }
+
+    out.push(0);
+}
+
+fn write_record(out: &mut Vec<u8>, record: &Record) {
+    write_name(out, &record.name);
+    out.extend_from_slice(&record.data.rtype().to_be_bytes());
+
+    let class = CLASS_IN | if record.cache_flush { CLASS_TOP_BIT } else { 0 };
+    out.extend_from_slice(&class.to_be_bytes());
+    out.extend_from_slice(&record.ttl.to_be_bytes());
+
+    let len_pos = out.len();
+    out.extend_from_slice(&[0, 0]);
+
+    match &record.data {
+        RData::A(ip) => out.extend_from_slice(&ip.octets()),
+        RData::Aaaa(ip) => out.extend_from_slice(&ip.octets()),
+        RData::Ptr(name) => write_name(out, name),
+        RData::Srv {
+            priority,
+            weight,
+            port,
+            target,
+        } => {
+            out.extend_from_slice(&priority.to_be_bytes());
+            out.extend_from_slice(&weight.to_be_bytes());
+            out.extend_from_slice(&port.to_be_bytes());
+            write_name(out, target);
+        }
+        // An empty TXT record still has to contain a single empty string.
+        RData::Txt(entries) if entries.is_empty() => out.push(0),
+        RData::Txt(entries) => {
+            for entry in entries {
+                let entry = &entry.as_bytes()[..entry.len().min(255)];
+                out.push(entry.len() as u8);
+                out.extend_from_slice(entry);
+            }
+        }
+        RData::Other(_, data) => out.extend_from_slice(data),
+    }
+
+    let len = (out.len() - len_pos - 2) as u16;
+    out[len_pos..len_pos + 2].copy_from_slice(&len.to_be_bytes());
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn bytes(&mut self, len: usize) -> Result<&[u8], Error> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .ok_or(Error::Malformed)?;
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    // Reads a possibly compressed name. Compression pointers may only point backwards, and the
+    // number of jumps is capped so a malicious packet can't make us loop forever.
+    fn name(&mut self) -> Result<String, Error> {
+        let mut labels = Vec::new();
+        let mut pos = self.pos;
+        let mut jumps = 0;
+        let mut end = None;
+
+        loop {
+            let len = *self.buf.get(pos).ok_or(Error::Malformed)? as usize;
+
+            match len {
+                0 => {
+                    pos += 1;
+                    break;
+                }
+                len if len & 0xc0 == 0xc0 => {
+                    let low = *self.buf.get(pos + 1).ok_or(Error::Malformed)? as usize;
+                    let target = ((len & 0x3f) << 8) | low;
+
+                    jumps += 1;
+                    if target >= pos || jumps > MAX_POINTER_JUMPS {
+                        return Err(Error::Malformed);
+                    }
+
+                    end.get_or_insert(pos + 2);
+                    pos = target;
+                }
+                len if len < 64 => {
+                    let label = self
+                        .buf
+                        .get(pos + 1..pos + 1 + len)
+                        .ok_or(Error::Malformed)?;
+                    labels.push(String::from_utf8_lossy(label).into_owned());
+                    pos += 1 + len;
+                }
+                _ => return Err(Error::Malformed),
+            }
+        }
+
+        self.pos = end.unwrap_or(pos);
+        Ok(labels.join("."))
+    }
+
+    fn record(&mut self) -> Result<Record, Error> {
+        let name = self.name()?;
+        let rtype = self.u16()?;
+        let class = self.u16()?;
+        let ttl = self.u32()?;
+        let len = self.u16()? as usize;
+
+        let end = self.pos + len;
+        if end > self.buf.len() {
+            return Err(Error::Malformed);
+        }
+
+        let data = match rtype {
+            TYPE_A if len == 4 => {
+                let b = self.bytes(4)?;
+                RData::A(Ipv4Addr::new(b[0], b[1], b[2], b[3]))
+            }
+            TYPE_AAAA if len == 16 => {
+                let b: [u8; 16] = self.bytes(16)?.try_into().unwrap();
+                RData::Aaaa(Ipv6Addr::from(b))
+            }
+            TYPE_PTR => RData::Ptr(self.name()?),
+            TYPE_SRV => RData::Srv {
+                priority: self.u16()?,
+                weight: self.u16()?,
+                port: self.u16()?,
+                target: self.name()?,
+            },
+            TYPE_TXT => {
+                let mut entries = Vec::new();
+
+                while self.pos < end {
+                    let len = self.u8()? as usize;
+                    let entry = self.bytes(len)?;
+
+                    if !entry.is_empty() {
+                        entries.push(String::from_utf8_lossy(entry).into_owned());
+                    }
+                }
+
+                RData::Txt(entries)
+            }
+            rtype => RData::Other(rtype, self.bytes(len)?.to_vec()),
+        };
+
+        if self.pos != end {
+            return Err(Error::Malformed);
+        }
+
+        Ok(Record {
+            name,
+            ttl,
+            cache_flush: class & CLASS_TOP_BIT != 0,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, data: RData) -> Record {
+        Record {
+            name: name.into(),
+            ttl: 120,
+            cache_flush: matches!(data, RData::Srv { .. }),
+            data,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let query = Message::query(vec![
+            Question {
+                name: "_http._tcp.local".into(),
+                qtype: TYPE_PTR,
+                unicast_response: true,
+            },
+            Question {
+                name: "watch.local".into(),
+                qtype: TYPE_ANY,
+                unicast_response: false,
+            },
+        ]);
+        assert_eq!(Message::decode(&query.encode()).unwrap(), query);
+
+        let mut response = Message::response(7);
+        response.answers = vec![
+            record(
+                "_http._tcp.local",
+                RData::Ptr("Watch._http._tcp.local".into()),
+            ),
+            record(
+                "Watch._http._tcp.local",
+                RData::Srv {
+                    priority: 0,
+                    weight: 0,
+                    port: 8080,
+                    target: "watch.local".into(),
+                },
+            ),
+            record(
+                "Watch._http._tcp.local",
+                RData::Txt(vec!["path=/".into(), "v=1".into()]),
+            ),
+        ];
+        response.additionals = vec![
+            record("watch.local", RData::A(Ipv4Addr::new(192, 168, 1, 2))),
+            record("watch.local", RData::Aaaa(Ipv6Addr::LOCALHOST)),
+            record("watch.local", RData::Other(99, vec![1, 2, 3])),
+            record("empty.local", RData::Txt(Vec::new())),
+        ];
+        assert_eq!(Message::decode(&response.encode()).unwrap(), response);
+    }
+
+    #[test]
+    fn compressed_names() {
+        #[rustfmt::skip]
+        let buf = [
+            0, 0, 0x84, 0, 0, 0, 0, 2, 0, 0, 0, 0,
+            // watch.local PTR -> a.watch.local, using a pointer back to offset 12.
+            5, b'w', b'a', b't', b'c', b'h', 5, b'l', b'o', b'c', b'a', b'l', 0,
+            0, 12, 0, 1, 0, 0, 0, 60, 0, 4,
+            1, b'a', 0xc0, 12,
+            // The second record's name is just a pointer to the first.
+            0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 10, 0, 0, 1,
+        ];
+
+        let message = Message::decode(&buf).unwrap();
+        assert!(message.response);
+        assert_eq!(message.answers[0].data, RData::Ptr("a.watch.local".into()));
+        assert_eq!(message.answers[1].name, "watch.local");
+        assert_eq!(
+            message.answers[1].data,
+            RData::A(Ipv4Addr::new(10, 0, 0, 1))
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let header = [0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+
+        // Truncated header, and a question with a pointer to itself.
+        assert!(Message::decode(&header[..11]).is_err());
+        let looped = [&header[..], &[0xc0, 12, 0, 1, 0, 1]].concat();
+        assert!(Message::decode(&looped).is_err());
+
+        // A record whose data runs past its declared length.
+        let mut response = Message::response(0);
+        response.answers = vec![record("a", RData::Ptr("b".into()))];
+        let mut buf = response.encode();
+        let len_pos = buf.len() - 5;
+        buf[len_pos + 1] = 2;
+        assert!(Message::decode(&buf).is_err());
+    }
+
+    #[test]
+    fn names() {
+        assert!(name_eq("Watch.Local.", "watch.local"));
+        assert!(!name_eq("watch.local", "watch.lan"));
+    }
+}
@@ -0,0 +1,661 @@
+use std::{collections::VecDeque, io};
+
+use futures::{
+    future::{select, Either},
+    Stream,
+};
+use thiserror::Error;
+
+use super::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use crate::time::{self, Duration, Instant};
+
+mod dns;
+
+use dns::{name_eq, Message, Question, RData, Record, TYPE_A, TYPE_ANY, TYPE_PTR, TYPE_SRV};
+
+pub const MDNS_PORT: u16 = 5353;
+pub const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub const XENON_SERVICE: &str = "_xenon._tcp";
+
+const SERVICE_ENUMERATION: &str = "_services._dns-sd._udp.local";
+// TTLs recommended by RFC 6762 for records containing a host name, and for everything else.
+const HOST_TTL: u32 = 120;
+const SERVICE_TTL: u32 = 4500;
+const MAX_PACKET: usize = 1500;
+const MIN_QUERY_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_QUERY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("malformed DNS message")]
+    Malformed,
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct ServiceInfo {
+    // The human-readable instance name, e.g. "Living Room Desktop".
+    pub instance: String,
+    // The service type without the domain, e.g. "_xenon._tcp".
+    pub service_type: String,
+    // The target host name, e.g. "desktop.local".
+    pub host: String,
+    pub addresses: Vec<IpAddr>,
+    pub port: u16,
+    pub txt: Vec<(String, String)>,
+}
+
+impl ServiceInfo {
+    pub fn new(instance: impl Into<String>, service_type: impl Into<String>, port: u16) -> Self {
+        Self {
+            instance: instance.into(),
+            service_type: service_type.into(),
+            host: String::new(),
+            addresses: Vec::new(),
+            port,
+            txt: Vec::new(),
+        }
+    }
+
+    pub fn with_txt(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.txt.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn txt(&self, key: &str) -> Option<&str> {
+        self.txt
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn socket_addrs(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.addresses
+            .iter()
+            .map(|ip| SocketAddr::new(*ip, self.port))
+    }
+
+    fn type_name(&self) -> String {
+        format!("{}.local", self.service_type)
+    }
+
+    fn instance_name(&self) -> String {
+        format!("{}.{}.local", self.instance, self.service_type)
+    }
+
+    fn txt_entries(&self) -> Vec<String> {
+        self.txt
+            .iter()
+            .map(|(k, v)| match v.is_empty() {
+                true => k.clone(),
+                false => format!("{k}={v}"),
+            })
+            .collect()
+    }
+}
+
+pub struct Responder {
+    socket: UdpSocket,
+    hostname: String,
+    addresses: Vec<IpAddr>,
+    services: Vec<ServiceInfo>,
+}
+
+impl Responder {
+    // Binds the mDNS port and joins the multicast group. `hostname` is given without the
+    // `.local` suffix.
+    pub fn new(hostname: &str, addresses: &[IpAddr]) -> Result<Self, Error> {
+        let socket = UdpSocket::bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), MDNS_PORT))?;
+        socket.join_multicast(MDNS_GROUP.into())?;
+
+        Ok(Self {
+            socket,
+            hostname: format!("{}.local", hostname.trim_end_matches(".local")),
+            addresses: addresses.to_vec(),
+            services: Vec::new(),
+        })
+    }
+
+    pub fn register(&mut self, mut service: ServiceInfo) {
+        service.host = self.hostname.clone();
+        service.addresses = self.addresses.clone();
+
+        self.services
+            .retain(|s| !name_eq(&s.instance_name(), &service.instance_name()));
+        self.services.push(service);
+    }
+
+    pub fn unregister(&mut self, instance: &str) {
+        self.services.retain(|s| s.instance != instance);
+    }
+
+    // Announces every registered service twice, one second apart, then answers queries forever.
+    pub async fn run(&self) -> Result<(), Error> {
+        self.announce().await?;
+        time::sleep(MIN_QUERY_INTERVAL).await;
+        self.announce().await?;
+
+        let mut buf = [0; MAX_PACKET];
+
+        loop {
+            let (len, from) = self.socket.recv_from(&mut buf).await?;
+
+            // Malformed packets from other hosts are not our problem.
+            let Ok(query) = Message::decode(&buf[..len]) else {
+                continue;
+            };
+
+            if !query.response {
+                self.respond(&query, from).await?;
+            }
+        }
+    }
+
+    pub async fn announce(&self) -> Result<(), Error> {
+        self.broadcast(false).await
+    }
+
+    // Tells everyone on the network that the registered services are going away.
+    pub async fn goodbye(&self) -> Result<(), Error> {
+        self.broadcast(true).await
+    }
+
+    async fn broadcast(&self, goodbye: bool) -> Result<(), Error> {
+        for service in &self.services {
+            let mut message = Message::response(0);
+            message.answers.push(self.ptr_record(service));
+            message.answers.extend(self.service_records(service));
+            message.answers.extend(self.address_records());
+
+            if goodbye {
+                message.answers.iter_mut().for_each(|r| r.ttl = 0);
+            }
+
+            self.socket
+                .send_to(
+                    &message.encode(),
+                    SocketAddr::new(MDNS_GROUP.into(), MDNS_PORT),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn respond(&self, query: &Message, from: SocketAddr) -> Result<(), Error> {
+        // Queries from a port other than 5353 come from simple resolvers, which expect a plain
+        // unicast DNS reply echoing the query ID and questions (RFC 6762 section 6.7).
+        let legacy = from.port() != MDNS_PORT;
+
+        let mut response = Message::response(if legacy { query.id } else { 0 });
+        let mut unicast = legacy;
+
+        for question in &query.questions {
+            let answers = self.answer(question);
+
+            if !answers.is_empty() {
+                unicast |= question.unicast_response;
+
+                if legacy {
+                    response.questions.push(question.clone());
+                }
+                response.answers.extend(answers);
+            }
+        }
+
+        if response.answers.is_empty() {
+            return Ok(());
+        }
+
+        // Provide the records the querier is going to ask for next.
+        for service in &self.services {
+            let answered = |rtype| {
+                response
+                    .answers
+                    .iter()
+                    .any(|r| r.data.rtype() == rtype && name_eq(&r.name, &service.instance_name()))
+            };
+            let pointed_to = response
+                .answers
+                .iter()
+                .any(|r| matches!(&r.data, RData::Ptr(p) if name_eq(p, &service.instance_name())));
+
+            if pointed_to && !answered(TYPE_SRV) {
+                response.additionals.extend(self.service_records(service));
+            }
+        }
+        if !response
+            .answers
+            .iter()
+            .any(|r| matches!(r.data, RData::A(_) | RData::Aaaa(_)))
+        {
+            response.additionals.extend(self.address_records());
+        }
+
+        // Known-answer suppression for the simple case: drop answers the querier already listed.
+        response.answers.retain(|a| {
+            !query
+                .answers
+                .iter()
+                .any(|k| k.data == a.data && name_eq(&k.name, &a.name) && k.ttl > a.ttl / 2)
+        });
+
+        if response.answers.is_empty() {
+            return Ok(());
+        }
+
+        if legacy {
+            for record in response.answers.iter_mut().chain(&mut response.additionals) {
+                record.cache_flush = false;
+                record.ttl = record.ttl.min(10);
+            }
+        }
+
+        let dest = if unicast {
+            from
+        } else {
+            SocketAddr::new(MDNS_GROUP.into(), MDNS_PORT)
+        };
+
+        self.socket.send_to(&response.encode(), dest).await?;
+
+        Ok(())
+    }
+
+    fn answer(&self, question: &Question) -> Vec<Record> {
+        let mut answers = Vec::new();
+        let any = question.qtype == TYPE_ANY;
+
+        if (question.qtype == TYPE_PTR || any) && name_eq(&question.name, SERVICE_ENUMERATION) {
+            for service in &self.services {
+                answers.push(Record {
+                    name: SERVICE_ENUMERATION.to_owned(),
+                    ttl: SERVICE_TTL,
+                    cache_flush: false,
+                    data: RData::Ptr(service.type_name()),
+                });
+            }
+        }
+
+        for service in &self.services {
+            if (question.qtype == TYPE_PTR || any) && name_eq(&question.name, &service.type_name())
+            {
+                answers.push(self.ptr_record(service));
+            }
+
+            if name_eq(&question.name, &service.instance_name()) {
+                answers.extend(
+                    self.service_records(service)
+                        .into_iter()
+                        .filter(|r| any || r.data.rtype() == question.qtype),
+                );
+            }
+        }
+
+        if name_eq(&question.name, &self.hostname) {
+            answers.extend(
+                self.address_records()
+                    .into_iter()
+                    .filter(|r| any || r.data.rtype() == question.qtype),
+            );
+        }
+
+        answers
+    }
+
+    fn ptr_record(&self, service: &ServiceInfo) -> Record {
+        Record {
+            name: service.type_name(),
+            ttl: SERVICE_TTL,
+            cache_flush: false,
+            data: RData::Ptr(service.instance_name()),
+        }
+    }
+
+    fn service_records(&self, service: &ServiceInfo) -> Vec<Record> {
+        vec![
+            Record {
+                name: service.instance_name(),
+                ttl: HOST_TTL,
+                cache_flush: true,
+                data: RData::Srv {
+                    priority: 0,
+                    weight: 0,
+                    port: service.port,
+                    target: self.hostname.clone(),
+                },
+            },
+            Record {
+                name: service.instance_name(),
+                ttl: SERVICE_TTL,
+                cache_flush: true,
+                data: RData::Txt(service.txt_entries()),
+            },
+        ]
+    }
+
+    fn address_records(&self) -> Vec<Record> {
+        self.addresses
+            .iter()
+            .map(|ip| Record {
+                name: self.hostname.clone(),
+                ttl: HOST_TTL,
+                cache_flush: true,
+                data: match ip {
+                    IpAddr::V4(v4) => RData::A(*v4),
+                    IpAddr::V6(v6) => RData::Aaaa(*v6),
+                },
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum ServiceEvent {
+    Found(ServiceInfo),
+    Removed { instance: String },
+}
+
+struct Known {
+    service: ServiceInfo,
+    expires: Instant,
+    // When to ask again before the record expires.
+    refresh: Instant,
+}
+
+impl Known {
+    fn new(service: ServiceInfo, ttl: u32) -> Self {
+        let mut known = Self {
+            service,
+            expires: Instant::now(),
+            refresh: Instant::now(),
+        };
+        known.renew(ttl);
+        known
+    }
+
+    fn renew(&mut self, ttl: u32) {
+        let ttl = Duration::from_secs(ttl as u64);
+        let now = Instant::now();
+
+        self.expires = now + ttl;
+        self.refresh = now + ttl * 4 / 5;
+    }
+}
+
+// Browses for instances of a service type by sending PTR queries with an exponential backoff and
+// following up on incomplete answers. Queries are sent from an ephemeral port, so responders reply
+// directly to us and the mDNS port stays free for a `Responder`.
+//
+// Responders cap the TTL of such direct replies at 10 seconds, so known services are queried
+// again at 80% of their TTL rather than waiting for the backoff.
+pub struct Browser {
+    socket: UdpSocket,
+    service_type: String,
+    known: Vec<Known>,
+    // Instances we've seen a PTR for, but are still missing the port or an address of.
+    partial: Vec<ServiceInfo>,
+    events: VecDeque<ServiceEvent>,
+    next_query: Instant,
+    interval: Duration,
+}
+
+impl Browser {
+    pub fn new(service_type: &str) -> Result<Self, Error> {
+        let socket = UdpSocket::bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0))?;
+
+        Ok(Self {
+            socket,
+            service_type: service_type.trim_end_matches(".local").to_owned(),
+            known: Vec::new(),
+            partial: Vec::new(),
+            events: VecDeque::new(),
+            next_query: Instant::now(),
+            interval: MIN_QUERY_INTERVAL,
+        })
+    }
+
+    pub fn services(&self) -> impl Iterator<Item = &ServiceInfo> {
+        self.known.iter().map(|k| &k.service)
+    }
+
+    pub async fn next_event(&mut self) -> Result<ServiceEvent, Error> {
+        let mut buf = [0; MAX_PACKET];
+
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+
+            self.expire();
+
+            let next_expiry = self.known.iter().map(|k| k.expires.min(k.refresh)).min();
+            let deadline = next_expiry.map_or(self.next_query, |e| e.min(self.next_query));
+
+            let received = match select(
+                Box::pin(self.socket.recv_from(&mut buf)),
+                time::sleep_until(deadline),
+            )
+            .await
+            {
+                Either::Left((res, _)) => Some(res?),
+                Either::Right(_) => None,
+            };
+
+            match received {
+                Some((len, _)) => {
+                    if let Ok(message) = Message::decode(&buf[..len]) {
+                        if message.response {
+                            self.process(&message).await?;
+                        }
+                    }
+                }
+                None if Instant::now() >= self.next_query => {
+                    self.query().await?;
+                    self.next_query = Instant::now() + self.interval;
+                    self.interval = (self.interval * 2).min(MAX_QUERY_INTERVAL);
+                }
+                None if self.refresh_due() => {
+                    // Something is about to expire, so ask now and go back to querying often
+                    // until answers come in again.
+                    self.query().await?;
+                    self.interval = MIN_QUERY_INTERVAL;
+                    self.next_query = Instant::now() + self.interval;
+                }
+                None => {}
+            }
+        }
+    }
+
+    pub fn into_stream(self) -> impl Stream<Item = Result<ServiceEvent, Error>> {
+        futures::stream::unfold(self, |mut browser| async move {
+            let event = browser.next_event().await;
+            Some((event, browser))
+        })
+    }
+
+    fn type_name(&self) -> String {
+        format!("{}.local", self.service_type)
+    }
+
+    async fn query(&self) -> Result<(), Error> {
+        let mut questions = vec![Question {
+            name: self.type_name(),
+            qtype: TYPE_PTR,
+            unicast_response: true,
+        }];
+
+        for service in &self.partial {
+            questions.push(Question {
+                name: service.instance_name(),
+                qtype: TYPE_ANY,
+                unicast_response: true,
+            });
+
+            if !service.host.is_empty() {
+                questions.push(Question {
+                    name: service.host.clone(),
+                    qtype: TYPE_A,
+                    unicast_response: true,
+                });
+            }
+        }
+
+        let mut message = Message::query(questions);
+
+        // Known answers stop responders from repeating what we already have.
+        for known in &self.known {
+            let remaining = known.expires.duration_since(Instant::now()).as_secs() as u32;
+
+            message.answers.push(Record {
+                name: self.type_name(),
+                ttl: remaining,
+                cache_flush: false,
+                data: RData::Ptr(known.service.instance_name()),
+            });
+        }
+
+        self.socket
+            .send_to(
+                &message.encode(),
+                SocketAddr::new(MDNS_GROUP.into(), MDNS_PORT),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn process(&mut self, message: &Message) -> Result<(), Error> {
+        let type_name = self.type_name();
+        let suffix = format!(".{type_name}");
+
+        for record in message.records() {
+            let RData::Ptr(target) = &record.data else {
+                continue;
+            };
+            if !name_eq(&record.name, &type_name) {
+                continue;
+            }
+
+            let instance = match target.len().checked_sub(suffix.len()) {
+                Some(idx) if name_eq(&target[idx..], &suffix) => target[..idx].to_owned(),
+                _ => continue,
+            };
+
+            if record.ttl == 0 {
+                self.partial.retain(|s| s.instance != instance);
+
+                if let Some(idx) = self
+                    .known
+                    .iter()
+                    .position(|k| k.service.instance == instance)
+                {
+                    self.known.remove(idx);
+                    self.events.push_back(ServiceEvent::Removed { instance });
+                }
+            } else if let Some(known) = self
+                .known
+                .iter_mut()
+                .find(|k| k.service.instance == instance)
+            {
+                known.renew(record.ttl);
+            } else if !self.partial.iter().any(|s| s.instance == instance) {
+                self.partial
+                    .push(ServiceInfo::new(instance, self.service_type.clone(), 0));
+            }
+        }
+
+        let mut incomplete = false;
+
+        for mut service in std::mem::take(&mut self.partial) {
+            merge_records(&mut service, message);
+
+            if service.port != 0 && !service.addresses.is_empty() {
+                let ttl = message
+                    .records()
+                    .find(|r| matches!(&r.data, RData::Ptr(p) if name_eq(p, &service.instance_name())))
+                    .map_or(HOST_TTL, |r| r.ttl);
+
+                self.events.push_back(ServiceEvent::Found(service.clone()));
+                self.known.push(Known::new(service, ttl));
+            } else {
+                incomplete = true;
+                self.partial.push(service);
+            }
+        }
+
+        // Ask for the missing records right away rather than waiting for the next PTR query.
+        if incomplete {
+            self.query().await?;
+        }
+
+        Ok(())
+    }
+
+    // Whether any service is past its refresh time. Each is only refreshed once per answer, and
+    // expires if nothing comes back.
+    fn refresh_due(&mut self) -> bool {
+        let now = Instant::now();
+        let mut due = false;
+
+        for known in self.known.iter_mut().filter(|k| k.refresh <= now) {
+            known.refresh = known.expires;
+            due = true;
+        }
+
+        due
+    }
+
+    fn expire(&mut self) {
+        let now = Instant::now();
+
+        while let Some(idx) = self.known.iter().position(|k| k.expires <= now) {
+            let known = self.known.remove(idx);
+
+            self.events.push_back(ServiceEvent::Removed {
+                instance: known.service.instance,
+            });
+        }
+    }
+}
+
+fn merge_records(service: &mut ServiceInfo, message: &Message) {
+    let instance_name = service.instance_name();
+
+    for record in message.records() {
+        match &record.data {
+            RData::Srv { port, target, .. } if name_eq(&record.name, &instance_name) => {
+                service.port = *port;
+                service.host = target.clone();
+            }
+            RData::Txt(entries) if name_eq(&record.name, &instance_name) => {
+                service.txt = entries
+                    .iter()
+                    .map(|e| match e.split_once('=') {
+                        Some((k, v)) => (k.to_owned(), v.to_owned()),
+                        None => (e.clone(), String::new()),
+                    })
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+
+    // Addresses are matched separately, since the SRV record might come after them.
+    for record in message.records() {
+        if service.host.is_empty() || !name_eq(&record.name, &service.host) {
+            continue;
+        }
+
+        let ip = match record.data {
+            RData::A(v4) => IpAddr::V4(v4),
+            RData::Aaaa(v6) => IpAddr::V6(v6),
+            _ => continue,
+        };
+
+        if !service.addresses.contains(&ip) {
+            service.addresses.push(ip);
+        }
+    }
+}
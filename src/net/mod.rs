@@ -0,0 +1,96 @@
+use std::io;
+
+use crate::ffi;
+
+mod connectivity;
+pub mod mdns;
+mod tcp;
+mod tls;
+mod udp;
+
+pub use connectivity::{changes, status, wait_online, Change, Changes, Status, Transport};
+pub use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr};
+pub use tcp::{TcpListener, TcpStream};
+pub use tls::{TlsConnector, TlsStream};
+pub use udp::UdpSocket;
+
+const ERR_WOULD_BLOCK: isize = -1;
+const ERR_CONNECTION_REFUSED: isize = -2;
+const ERR_CONNECTION_RESET: isize = -3;
+const ERR_CONNECTION_ABORTED: isize = -4;
+const ERR_NOT_CONNECTED: isize = -5;
+const ERR_ADDR_IN_USE: isize = -6;
+const ERR_ADDR_NOT_AVAILABLE: isize = -7;
+const ERR_TIMED_OUT: isize = -8;
+const ERR_INVALID_INPUT: isize = -9;
+const ERR_HOST_UNREACHABLE: isize = -10;
+const ERR_NOT_FOUND: isize = -11;
+const ERR_TLS_HANDSHAKE: isize = -12;
+const ERR_CERTIFICATE_PIN: isize = -13;
+const ERR_ALREADY_EXISTS: isize = -14;
+const ERR_PERMISSION_DENIED: isize = -15;
+const ERR_NOT_A_DIRECTORY: isize = -16;
+const ERR_IS_A_DIRECTORY: isize = -17;
+const ERR_DIRECTORY_NOT_EMPTY: isize = -18;
+const ERR_STORAGE_FULL: isize = -19;
+
+pub fn lookup_host(host: &str, port: u16) -> io::Result<SocketAddr> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, port));
+    }
+
+    let mut addr = ffi::net::SocketAddr::default();
+    let err = unsafe { ffi::net::resolve_host(host.as_ptr(), host.len(), &mut addr) };
+    cvt(err as isize)?;
+
+    let mut addr = SocketAddr::from(addr);
+    addr.set_port(port);
+
+    Ok(addr)
+}
+
+pub(crate) fn cvt(ret: isize) -> io::Result<usize> {
+    if ret >= 0 {
+        Ok(ret as usize)
+    } else {
+        Err(wasm_to_io_error(ret))
+    }
+}
+
+fn wasm_to_io_error(err: isize) -> io::Error {
+    match err {
+        ERR_TLS_HANDSHAKE => {
+            return io::Error::new(io::ErrorKind::InvalidData, "TLS handshake failed")
+        }
+        ERR_CERTIFICATE_PIN => {
+            return io::Error::new(
+                io::ErrorKind::InvalidData,
+                "certificate did not match any pinned key",
+            )
+        }
+        _ => {}
+    }
+
+    let kind = match err {
+        ERR_WOULD_BLOCK => io::ErrorKind::WouldBlock,
+        ERR_CONNECTION_REFUSED => io::ErrorKind::ConnectionRefused,
+        ERR_CONNECTION_RESET => io::ErrorKind::ConnectionReset,
+        ERR_CONNECTION_ABORTED => io::ErrorKind::ConnectionAborted,
+        ERR_NOT_CONNECTED => io::ErrorKind::NotConnected,
+        ERR_ADDR_IN_USE => io::ErrorKind::AddrInUse,
+        ERR_ADDR_NOT_AVAILABLE => io::ErrorKind::AddrNotAvailable,
+        ERR_TIMED_OUT => io::ErrorKind::TimedOut,
+        ERR_INVALID_INPUT => io::ErrorKind::InvalidInput,
+        ERR_HOST_UNREACHABLE => io::ErrorKind::HostUnreachable,
+        ERR_NOT_FOUND => io::ErrorKind::NotFound,
+        ERR_ALREADY_EXISTS => io::ErrorKind::AlreadyExists,
+        ERR_PERMISSION_DENIED => io::ErrorKind::PermissionDenied,
+        ERR_NOT_A_DIRECTORY => io::ErrorKind::NotADirectory,
+        ERR_IS_A_DIRECTORY => io::ErrorKind::IsADirectory,
+        ERR_DIRECTORY_NOT_EMPTY => io::ErrorKind::DirectoryNotEmpty,
+        ERR_STORAGE_FULL => io::ErrorKind::StorageFull,
+        _ => io::ErrorKind::Other,
+    };
+
+    io::Error::from(kind)
+}
@@ -0,0 +1,182 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{future::poll_fn, AsyncRead, AsyncWrite};
+
+use super::{cvt, lookup_host, Shutdown, SocketAddr};
+use crate::{asynch::reactor, ffi};
+
+pub struct TcpStream {
+    inner: ffi::net::TcpStream,
+}
+
+impl TcpStream {
+    pub async fn connect(addr: SocketAddr) -> io::Result<Self> {
+        let addr = ffi::net::SocketAddr::from(addr);
+        let mut handle = 0;
+
+        cvt(unsafe { ffi::net::tcp_connect(&addr, &mut handle) } as isize)?;
+
+        // Dropping the stream closes the handle if the connection attempt fails.
+        let stream = Self::from_handle(handle);
+
+        poll_fn(|cx| {
+            match cvt(unsafe { ffi::net::tcp_connect_status(stream.handle()) } as isize) {
+                Ok(_) => Poll::Ready(Ok(())),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    reactor::register_io(cx.waker(), false, true);
+                    Poll::Pending
+                }
+                Err(e) => Poll::Ready(Err(e)),
+            }
+        })
+        .await?;
+
+        Ok(stream)
+    }
+
+    pub async fn connect_host(host: &str, port: u16) -> io::Result<Self> {
+        let addr = lookup_host(host, port)?;
+
+        Self::connect(addr).await
+    }
+
+    pub(crate) fn from_handle(handle: ffi::io::Handle) -> Self {
+        Self {
+            inner: ffi::net::TcpStream(handle),
+        }
+    }
+
+    pub(crate) fn handle(&self) -> ffi::io::Handle {
+        self.inner.0
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        let mut addr = ffi::net::SocketAddr::default();
+        cvt(unsafe { ffi::net::tcp_peer_addr(self.handle(), &mut addr) } as isize)?;
+
+        Ok(addr.into())
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        let mut addr = ffi::net::SocketAddr::default();
+        cvt(unsafe { ffi::net::tcp_local_addr(self.handle(), &mut addr) } as isize)?;
+
+        Ok(addr.into())
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        let how = match how {
+            Shutdown::Read => ffi::net::SHUTDOWN_READ,
+            Shutdown::Write => ffi::net::SHUTDOWN_WRITE,
+            Shutdown::Both => ffi::net::SHUTDOWN_BOTH,
+        };
+
+        cvt(unsafe { ffi::net::tcp_shutdown(self.handle(), how) } as isize)?;
+
+        Ok(())
+    }
+}
+
+impl AsyncRead for TcpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let ret = unsafe { ffi::net::tcp_read(self.handle(), buf.as_mut_ptr(), buf.len()) };
+
+        match cvt(ret) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                reactor::register_io(cx.waker(), true, false);
+                Poll::Pending
+            }
+            res => Poll::Ready(res),
+        }
+    }
+}
+
+impl AsyncWrite for TcpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let ret = unsafe { ffi::net::tcp_write(self.handle(), buf.as_ptr(), buf.len()) };
+
+        match cvt(ret) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                reactor::register_io(cx.waker(), false, true);
+                Poll::Pending
+            }
+            res => Poll::Ready(res),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // Writes are handed straight to the host's socket, there is nothing buffered on this side.
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.shutdown(Shutdown::Write))
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        unsafe { ffi::net::tcp_close(self.handle()) }
+    }
+}
+
+pub struct TcpListener {
+    inner: ffi::net::TcpListener,
+}
+
+impl TcpListener {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let addr = ffi::net::SocketAddr::from(addr);
+        let mut handle = 0;
+
+        cvt(unsafe { ffi::net::tcp_bind(&addr, &mut handle) } as isize)?;
+
+        Ok(Self {
+            inner: ffi::net::TcpListener(handle),
+        })
+    }
+
+    pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        poll_fn(|cx| {
+            let mut stream = 0;
+            let mut addr = ffi::net::SocketAddr::default();
+
+            let ret = unsafe { ffi::net::tcp_accept(self.inner.0, &mut stream, &mut addr) };
+
+            match cvt(ret as isize) {
+                Ok(_) => Poll::Ready(Ok((TcpStream::from_handle(stream), addr.into()))),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    reactor::register_io(cx.waker(), true, false);
+                    Poll::Pending
+                }
+                Err(e) => Poll::Ready(Err(e)),
+            }
+        })
+        .await
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        let mut addr = ffi::net::SocketAddr::default();
+        cvt(unsafe { ffi::net::tcp_listener_local_addr(self.inner.0, &mut addr) } as isize)?;
+
+        Ok(addr.into())
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        unsafe { ffi::net::tcp_listener_close(self.inner.0) }
+    }
+}
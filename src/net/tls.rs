@@ -0,0 +1,214 @@
+use std::{
+    io,
+    pin::Pin,
+    ptr,
+    task::{Context, Poll},
+};
+
+use futures::{future::poll_fn, AsyncRead, AsyncWrite};
+
+use super::{cvt, Shutdown, SocketAddr, TcpStream};
+use crate::{asynch::reactor, ffi};
+
+// Settings for TLS client connections. The handshake and record encryption are done by the host,
+// which verifies the server's certificate chain against its built-in roots by default.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct TlsConnector {
+    pins: Vec<[u8; 32]>,
+    roots: Vec<Vec<u8>>,
+    system_roots: bool,
+}
+
+impl TlsConnector {
+    pub const fn new() -> Self {
+        Self {
+            pins: Vec::new(),
+            roots: Vec::new(),
+            system_roots: true,
+        }
+    }
+
+    // Pins the SHA-256 hash of a DER-encoded SubjectPublicKeyInfo. Once any pin is set, the
+    // handshake fails unless a certificate in the server's chain matches one of them.
+    pub fn with_pin(mut self, spki_sha256: [u8; 32]) -> Self {
+        self.pins.push(spki_sha256);
+        self
+    }
+
+    // Trusts an additional DER-encoded root certificate, e.g. a private CA.
+    pub fn with_root_certificate(mut self, der: impl Into<Vec<u8>>) -> Self {
+        self.roots.push(der.into());
+        self
+    }
+
+    // Whether to trust the host's built-in root certificates in addition to any added ones.
+    pub fn with_system_roots(mut self, system_roots: bool) -> Self {
+        self.system_roots = system_roots;
+        self
+    }
+
+    pub async fn connect(&self, server_name: &str, stream: TcpStream) -> io::Result<TlsStream> {
+        let mut roots = Vec::new();
+        for root in &self.roots {
+            roots.extend_from_slice(&(root.len() as u32).to_le_bytes());
+            roots.extend_from_slice(root);
+        }
+
+        let config = ffi::net::TlsConfig {
+            server_name_ptr: server_name.as_ptr(),
+            server_name_len: server_name.len(),
+            pins_ptr: if self.pins.is_empty() {
+                ptr::null()
+            } else {
+                self.pins.as_ptr()
+            },
+            pins_len: self.pins.len(),
+            roots_ptr: roots.as_ptr(),
+            roots_len: roots.len(),
+            flags: if self.system_roots {
+                0
+            } else {
+                ffi::net::TLS_NO_SYSTEM_ROOTS
+            },
+        };
+
+        let mut handle = 0;
+        cvt(unsafe { ffi::net::tls_connect(stream.handle(), &config, &mut handle) } as isize)?;
+
+        // Dropping the stream closes the session if the handshake fails.
+        let stream = TlsStream {
+            inner: ffi::net::TlsStream(handle),
+            stream,
+        };
+
+        poll_fn(|cx| {
+            match cvt(unsafe { ffi::net::tls_handshake_status(stream.handle()) } as isize) {
+                Ok(_) => Poll::Ready(Ok(())),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    reactor::register_io(cx.waker(), true, true);
+                    Poll::Pending
+                }
+                Err(e) => Poll::Ready(Err(e)),
+            }
+        })
+        .await?;
+
+        Ok(stream)
+    }
+}
+
+impl Default for TlsConnector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A TLS session over a TCP stream. The underlying stream is kept so it outlives the session, but
+// it must not be read from or written to directly.
+pub struct TlsStream {
+    inner: ffi::net::TlsStream,
+    stream: TcpStream,
+}
+
+impl TlsStream {
+    // Connects to `host` and performs a handshake with the default settings.
+    pub async fn connect(host: &str, port: u16) -> io::Result<Self> {
+        let stream = TcpStream::connect_host(host, port).await?;
+
+        TlsConnector::new().connect(host, stream).await
+    }
+
+    fn handle(&self) -> ffi::io::Handle {
+        self.inner.0
+    }
+
+    pub fn get_ref(&self) -> &TcpStream {
+        &self.stream
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.local_addr()
+    }
+
+    // The DER-encoded certificate the server presented.
+    pub fn peer_certificate(&self) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0; 2048];
+
+        loop {
+            let len = cvt(unsafe {
+                ffi::net::tls_peer_certificate(self.handle(), buf.as_mut_ptr(), buf.len())
+            })?;
+
+            if len <= buf.len() {
+                buf.truncate(len);
+                return Ok(buf);
+            }
+
+            buf.resize(len, 0);
+        }
+    }
+}
+
+impl AsyncRead for TlsStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let ret = unsafe { ffi::net::tls_read(self.handle(), buf.as_mut_ptr(), buf.len()) };
+
+        // Reading may need to write too, e.g. to answer a renegotiation, so wait for either.
+        match cvt(ret) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                reactor::register_io(cx.waker(), true, true);
+                Poll::Pending
+            }
+            res => Poll::Ready(res),
+        }
+    }
+}
+
+impl AsyncWrite for TlsStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let ret = unsafe { ffi::net::tls_write(self.handle(), buf.as_ptr(), buf.len()) };
+
+        match cvt(ret) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                reactor::register_io(cx.waker(), false, true);
+                Poll::Pending
+            }
+            res => Poll::Ready(res),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // Records are encrypted and handed to the socket as they are written.
+        Poll::Ready(Ok(()))
+    }
+
+    // Sends close_notify before shutting down the write half of the TCP stream.
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match cvt(unsafe { ffi::net::tls_close_notify(self.handle()) } as isize) {
+            Ok(_) => Poll::Ready(self.stream.shutdown(Shutdown::Write)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                reactor::register_io(cx.waker(), false, true);
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+impl Drop for TlsStream {
+    fn drop(&mut self) {
+        unsafe { ffi::net::tls_close(self.handle()) }
+    }
+}
@@ -0,0 +1,92 @@
+use std::{io, task::Poll};
+
+use futures::future::poll_fn;
+
+use super::{cvt, IpAddr, SocketAddr};
+use crate::{asynch::reactor, ffi};
+
+pub struct UdpSocket {
+    inner: ffi::net::UdpSocket,
+}
+
+impl UdpSocket {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let addr = ffi::net::SocketAddr::from(addr);
+        let mut handle = 0;
+
+        cvt(unsafe { ffi::net::udp_bind(&addr, &mut handle) } as isize)?;
+
+        Ok(Self {
+            inner: ffi::net::UdpSocket(handle),
+        })
+    }
+
+    pub(crate) fn handle(&self) -> ffi::io::Handle {
+        self.inner.0
+    }
+
+    pub async fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        let addr = ffi::net::SocketAddr::from(addr);
+
+        poll_fn(|cx| {
+            let ret =
+                unsafe { ffi::net::udp_send_to(self.handle(), buf.as_ptr(), buf.len(), &addr) };
+
+            match cvt(ret) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    reactor::register_io(cx.waker(), false, true);
+                    Poll::Pending
+                }
+                res => Poll::Ready(res),
+            }
+        })
+        .await
+    }
+
+    // Receives a single datagram. If `buf` is too small, the rest of the datagram is discarded.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        poll_fn(|cx| {
+            let mut addr = ffi::net::SocketAddr::default();
+            let ret = unsafe {
+                ffi::net::udp_recv_from(self.handle(), buf.as_mut_ptr(), buf.len(), &mut addr)
+            };
+
+            match cvt(ret) {
+                Ok(n) => Poll::Ready(Ok((n, addr.into()))),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    reactor::register_io(cx.waker(), true, false);
+                    Poll::Pending
+                }
+                Err(e) => Poll::Ready(Err(e)),
+            }
+        })
+        .await
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        let mut addr = ffi::net::SocketAddr::default();
+        cvt(unsafe { ffi::net::udp_local_addr(self.handle(), &mut addr) } as isize)?;
+
+        Ok(addr.into())
+    }
+
+    pub fn join_multicast(&self, group: IpAddr) -> io::Result<()> {
+        let group = ffi::net::SocketAddr::from(SocketAddr::new(group, 0));
+        cvt(unsafe { ffi::net::udp_join_multicast(self.handle(), &group) } as isize)?;
+
+        Ok(())
+    }
+
+    pub fn leave_multicast(&self, group: IpAddr) -> io::Result<()> {
+        let group = ffi::net::SocketAddr::from(SocketAddr::new(group, 0));
+        cvt(unsafe { ffi::net::udp_leave_multicast(self.handle(), &group) } as isize)?;
+
+        Ok(())
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        unsafe { ffi::net::udp_close(self.handle()) }
+    }
+}
@@ -1,12 +1,185 @@
-use std::panic::PanicHookInfo;
+use std::{
+    cell::{Cell, RefCell},
+    io::{self, Write},
+    panic::PanicHookInfo,
+    time::{Duration, SystemTime},
+};
 
-use crate::{ffi, syscalls};
+use critical_section as cs;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    asynch::task,
+    fs::{self, File},
+    syscalls,
+    time::{self, Instant},
+};
 
 syscalls! {
-    fn panic(ptr: *const u8, len: usize);
+    fn panic(info: *const PanicInfo);
+}
+
+// The file is empty and the line and column are 0 if the location isn't known.
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+struct PanicInfo {
+    message_ptr: *const u8,
+    message_len: usize,
+    file_ptr: *const u8,
+    file_len: usize,
+    line: u32,
+    column: u32,
+}
+
+pub type Handler = Box<dyn Fn(&CrashReport) + Send>;
+
+// The crash slot. It holds only the most recent crash, and is kept until it's cleared.
+const CRASH_PATH: &str = ".crash";
+
+static START: cs::Mutex<Cell<Instant>> = cs::Mutex::new(Cell::new(Instant::from_micros(0)));
+static HANDLER: cs::Mutex<RefCell<Option<Handler>>> = cs::Mutex::new(RefCell::new(None));
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub struct Location {
+    file: String,
+    line: u32,
+    column: u32,
+}
+
+impl Location {
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+}
+
+// What was known about a panic when it happened.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub struct CrashReport {
+    message: String,
+    location: Option<Location>,
+    task: Option<String>,
+    uptime: Duration,
+    time: Option<SystemTime>,
+}
+
+impl CrashReport {
+    fn new(info: &PanicHookInfo<'_>) -> Self {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| String::from("Box<dyn Any>"));
+
+        Self {
+            message,
+            location: info.location().map(|location| Location {
+                file: location.file().to_owned(),
+                line: location.line(),
+                column: location.column(),
+            }),
+            task: task::current_name().map(str::to_owned),
+            uptime: Instant::now() - cs::with(|cs| START.borrow(cs).get()),
+            time: time::system_time(),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn location(&self) -> Option<&Location> {
+        self.location.as_ref()
+    }
+
+    // The name of the task that panicked, if it was spawned with one.
+    pub fn task(&self) -> Option<&str> {
+        self.task.as_deref()
+    }
+
+    // How long the app had been running.
+    pub fn uptime(&self) -> Duration {
+        self.uptime
+    }
+
+    // The wall-clock time of the crash, if it was known.
+    pub fn time(&self) -> Option<SystemTime> {
+        self.time
+    }
+}
+
+// The report from the last crash, if there was one since the slot was last cleared. A report that
+// was only partly written when the app went down is ignored.
+pub fn last_crash() -> Option<CrashReport> {
+    let data = fs::read(CRASH_PATH).ok()?;
+    postcard::from_bytes(&data).ok()
+}
+
+// Clears the crash slot, e.g. once the report has been uploaded.
+pub fn clear_last_crash() -> io::Result<()> {
+    match fs::remove_file(CRASH_PATH) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+// Sets a function to run when the app panics, after the crash report is saved and before the host
+// is told. This is the place to e.g. draw an error screen. The handler runs at most once, and it
+// must not panic: a panic inside it aborts the app on the spot, so the host never gets the panic
+// info and only sees a trap. The crash report is still saved for `last_crash` in that case.
+pub fn set_handler(handler: impl Fn(&CrashReport) + Send + 'static) {
+    cs::with(|cs| *HANDLER.borrow_ref_mut(cs) = Some(Box::new(handler)));
+}
+
+// Removes the handler, if one is set.
+pub fn take_handler() -> Option<Handler> {
+    cs::with(|cs| HANDLER.borrow_ref_mut(cs).take())
 }
 
-pub(crate) fn panic_hook(payload: &PanicHookInfo<'_>) {
-    let panic_message = format!("{}", payload);
-    unsafe { panic(panic_message.as_ptr(), panic_message.len()) }
+pub(crate) fn init() {
+    cs::with(|cs| START.borrow(cs).set(Instant::now()));
+}
+
+pub(crate) fn panic_hook(info: &PanicHookInfo<'_>) {
+    let report = CrashReport::new(info);
+
+    // Nothing can be done about a failed write at this point, and the host still gets the report.
+    let _ = write_crash(&report);
+
+    if let Some(handler) = take_handler() {
+        handler(&report);
+    }
+
+    let (file, line, column) = match &report.location {
+        Some(location) => (location.file.as_str(), location.line, location.column),
+        None => ("", 0, 0),
+    };
+
+    let info = PanicInfo {
+        message_ptr: report.message.as_ptr(),
+        message_len: report.message.len(),
+        file_ptr: file.as_ptr(),
+        file_len: file.len(),
+        line,
+        column,
+    };
+
+    unsafe { panic(&info) }
+}
+
+fn write_crash(report: &CrashReport) -> io::Result<()> {
+    let data = postcard::to_allocvec(report).map_err(io::Error::other)?;
+
+    // The app is about to go down, so make sure the report reaches flash first.
+    let mut file = File::create(CRASH_PATH)?;
+    file.write_all(&data)?;
+    file.sync_all()
 }
@@ -0,0 +1,242 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+
+use crate::{
+    asynch::reactor,
+    ffi::{self, io::Handle},
+    net,
+    time::Instant,
+};
+
+use super::{
+    motion::{Motion, MotionDetector},
+    Vector3,
+};
+
+const DEFAULT_RATE_HZ: u32 = 50;
+const DEFAULT_BATCH_LEN: u32 = 25;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum Range {
+    G2,
+    #[default]
+    G4,
+    G8,
+    G16,
+}
+
+impl Range {
+    pub fn as_g(self) -> u8 {
+        match self {
+            Self::G2 => 2,
+            Self::G4 => 4,
+            Self::G8 => 8,
+            Self::G16 => 16,
+        }
+    }
+
+    fn from_g(g: u8) -> Self {
+        match g {
+            0..=2 => Self::G2,
+            3..=4 => Self::G4,
+            5..=8 => Self::G8,
+            _ => Self::G16,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Config {
+    rate_hz: u32,
+    range: Range,
+    batch_len: u32,
+}
+
+impl Config {
+    pub const fn new() -> Self {
+        Self {
+            rate_hz: DEFAULT_RATE_HZ,
+            range: Range::G4,
+            batch_len: DEFAULT_BATCH_LEN,
+        }
+    }
+
+    // The host picks the closest rate the hardware supports.
+    pub const fn with_sample_rate(mut self, hz: u32) -> Self {
+        self.rate_hz = hz;
+        self
+    }
+
+    pub const fn with_range(mut self, range: Range) -> Self {
+        self.range = range;
+        self
+    }
+
+    // How many samples to collect before waking the app. Bigger batches mean fewer wakeups, at
+    // the cost of latency.
+    pub const fn with_batch_len(mut self, samples: u32) -> Self {
+        self.batch_len = if samples == 0 { 1 } else { samples };
+        self
+    }
+
+    pub fn open(self) -> io::Result<Accelerometer> {
+        Accelerometer::open(self)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Corrects each axis as `(raw - offset) * scale`, both in g.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Calibration {
+    pub offset: Vector3,
+    pub scale: Vector3,
+}
+
+impl Calibration {
+    pub const IDENTITY: Self = Self {
+        offset: Vector3::ZERO,
+        scale: Vector3::ONE,
+    };
+
+    // Offsets from readings taken while the device lies still, screen up. The readings should
+    // average to 1 g straight down the z axis.
+    pub fn from_rest(readings: &[Vector3]) -> Self {
+        if readings.is_empty() {
+            return Self::IDENTITY;
+        }
+
+        let sum = readings.iter().fold(Vector3::ZERO, |sum, &r| sum + r);
+        let mean = sum / readings.len() as f32;
+
+        Self {
+            offset: mean - Vector3::new(0.0, 0.0, 1.0),
+            scale: Vector3::ONE,
+        }
+    }
+
+    pub fn apply(&self, raw: Vector3) -> Vector3 {
+        (raw - self.offset).scale(self.scale)
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Sample {
+    pub timestamp: Instant,
+    // In g, with the calibration applied.
+    pub acceleration: Vector3,
+}
+
+// Samples arrive in batches of the configured length, as a stream of `Vec<Sample>`. Sampling
+// stops when the accelerometer is dropped.
+#[derive(Debug)]
+pub struct Accelerometer {
+    handle: Handle,
+    rate_hz: u32,
+    range: Range,
+    batch_len: u32,
+    calibration: Calibration,
+}
+
+impl Accelerometer {
+    pub fn open(config: Config) -> io::Result<Self> {
+        let mut raw = ffi::sensors::AccelConfig {
+            rate_hz: config.rate_hz,
+            range_g: config.range.as_g(),
+            batch_len: config.batch_len,
+        };
+        let mut handle = 0;
+
+        net::cvt(unsafe { ffi::sensors::accel_open(&mut raw, &mut handle) } as isize)?;
+
+        Ok(Self {
+            handle,
+            rate_hz: raw.rate_hz,
+            range: Range::from_g(raw.range_g),
+            batch_len: raw.batch_len.max(1),
+            calibration: Calibration::IDENTITY,
+        })
+    }
+
+    pub fn with_calibration(mut self, calibration: Calibration) -> Self {
+        self.calibration = calibration;
+        self
+    }
+
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+    }
+
+    pub fn calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    // The rate actually in use, which may differ from the one asked for.
+    pub fn sample_rate(&self) -> u32 {
+        self.rate_hz
+    }
+
+    pub fn range(&self) -> Range {
+        self.range
+    }
+
+    // Turns the samples into a stream of motion gestures.
+    pub fn motion(self, detector: MotionDetector) -> Motion<Self> {
+        Motion::new(self, detector)
+    }
+
+    fn to_sample(&self, raw: ffi::sensors::AccelSample) -> Sample {
+        let g = self.range.as_g() as f32 / 32768.0;
+        let raw_g = Vector3::new(raw.x as f32, raw.y as f32, raw.z as f32) * g;
+
+        Sample {
+            timestamp: Instant::from_micros(raw.timestamp),
+            acceleration: self.calibration.apply(raw_g),
+        }
+    }
+}
+
+impl Stream for Accelerometer {
+    type Item = io::Result<Vec<Sample>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut raw = vec![ffi::sensors::AccelSample::default(); self.batch_len as usize];
+
+        let ret = unsafe { ffi::sensors::accel_read(self.handle, raw.as_mut_ptr(), raw.len()) };
+
+        match net::cvt(ret) {
+            Ok(len) => {
+                raw.truncate(len);
+                let samples = raw.into_iter().map(|s| self.to_sample(s)).collect();
+
+                Poll::Ready(Some(Ok(samples)))
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                reactor::register_io(cx.waker(), true, false);
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Some(Err(e))),
+        }
+    }
+}
+
+impl Drop for Accelerometer {
+    fn drop(&mut self) {
+        unsafe { ffi::sensors::accel_close(self.handle) }
+    }
+}
@@ -0,0 +1,590 @@
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{Stream, StreamExt};
+
+use crate::time::{Duration, Instant};
+
+use super::{accel::Sample, Vector3};
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum MotionGesture {
+    Tap,
+    // The second tap of a double tap is reported as this instead of `Tap`.
+    DoubleTap,
+    Shake,
+    WristRaise,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct MotionEvent {
+    pub gesture: MotionGesture,
+    // The timestamp of the sample the gesture was detected at.
+    pub timestamp: Instant,
+}
+
+// Tracks gravity with a low-pass filter, so what's left over is the wearer's own movement.
+#[derive(Copy, Clone, Debug)]
+struct Gravity {
+    time_constant: f32,
+    value: Option<Vector3>,
+    last: Option<Instant>,
+}
+
+impl Gravity {
+    fn new(time_constant: Duration) -> Self {
+        Self {
+            time_constant: time_constant.as_secs_f32(),
+            value: None,
+            last: None,
+        }
+    }
+
+    fn update(&mut self, sample: &Sample) -> Vector3 {
+        let dt = self
+            .last
+            .map_or(0.0, |last| (sample.timestamp - last).as_secs_f32());
+        self.last = Some(sample.timestamp);
+
+        let value = match self.value {
+            Some(value) => {
+                let alpha = dt / (self.time_constant + dt);
+                value + (sample.acceleration - value) * alpha
+            }
+            None => sample.acceleration,
+        };
+
+        self.value = Some(value);
+        value
+    }
+
+    // Movement with gravity removed, measured against gravity as it was before this sample. The
+    // filter is slow enough that short spikes barely move it, and it still settles after the
+    // device is turned over.
+    fn dynamic(&mut self, sample: &Sample) -> Vector3 {
+        let gravity = self.value.unwrap_or(sample.acceleration);
+        self.update(sample);
+
+        sample.acceleration - gravity
+    }
+}
+
+// Detects short, sharp knocks on the device.
+#[derive(Copy, Clone, Debug)]
+pub struct TapDetector {
+    threshold: f32,
+    max_duration: Duration,
+    quiet: Duration,
+    double_tap_window: Duration,
+    gravity: Gravity,
+    spike_start: Option<Instant>,
+    quiet_until: Option<Instant>,
+    last_tap: Option<Instant>,
+}
+
+impl TapDetector {
+    pub fn new() -> Self {
+        Self {
+            threshold: 1.5,
+            max_duration: Duration::from_millis(60),
+            quiet: Duration::from_millis(80),
+            double_tap_window: Duration::from_millis(400),
+            gravity: Gravity::new(Duration::from_millis(500)),
+            spike_start: None,
+            quiet_until: None,
+            last_tap: None,
+        }
+    }
+
+    // How far above gravity, in g, a spike has to go.
+    pub fn with_threshold(mut self, g: f32) -> Self {
+        self.threshold = g;
+        self
+    }
+
+    // Spikes longer than this are movement rather than a tap.
+    pub fn with_max_duration(mut self, duration: Duration) -> Self {
+        self.max_duration = duration;
+        self
+    }
+
+    // How long to ignore the ringing after a tap.
+    pub fn with_quiet(mut self, duration: Duration) -> Self {
+        self.quiet = duration;
+        self
+    }
+
+    pub fn with_double_tap_window(mut self, window: Duration) -> Self {
+        self.double_tap_window = window;
+        self
+    }
+
+    pub fn process(&mut self, sample: &Sample) -> Option<MotionGesture> {
+        let ts = sample.timestamp;
+        let strong = self.gravity.dynamic(sample).magnitude() >= self.threshold;
+
+        if self.quiet_until.is_some_and(|until| ts < until) {
+            return None;
+        }
+
+        if strong {
+            self.spike_start.get_or_insert(ts);
+            return None;
+        }
+
+        let start = self.spike_start.take()?;
+        if ts - start > self.max_duration {
+            return None;
+        }
+
+        self.quiet_until = Some(ts + self.quiet);
+
+        match self.last_tap.take() {
+            Some(last) if start - last <= self.double_tap_window => Some(MotionGesture::DoubleTap),
+            _ => {
+                self.last_tap = Some(start);
+                Some(MotionGesture::Tap)
+            }
+        }
+    }
+}
+
+impl Default for TapDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Detects the device being shaken back and forth.
+#[derive(Clone, Debug)]
+pub struct ShakeDetector {
+    threshold: f32,
+    reversals: usize,
+    window: Duration,
+    cooldown: Duration,
+    gravity: Gravity,
+    direction: Option<Vector3>,
+    seen: VecDeque<Instant>,
+    cooldown_until: Option<Instant>,
+}
+
+impl ShakeDetector {
+    pub fn new() -> Self {
+        Self {
+            threshold: 1.0,
+            reversals: 4,
+            window: Duration::from_secs(1),
+            cooldown: Duration::from_secs(1),
+            gravity: Gravity::new(Duration::from_millis(500)),
+            direction: None,
+            seen: VecDeque::new(),
+            cooldown_until: None,
+        }
+    }
+
+    // How strong, in g, a movement has to be to count.
+    pub fn with_threshold(mut self, g: f32) -> Self {
+        self.threshold = g;
+        self
+    }
+
+    // How many changes of direction within `window` make a shake.
+    pub fn with_reversals(mut self, reversals: usize, window: Duration) -> Self {
+        self.reversals = reversals.max(1);
+        self.window = window;
+        self
+    }
+
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    pub fn process(&mut self, sample: &Sample) -> Option<MotionGesture> {
+        let ts = sample.timestamp;
+        let dynamic = self.gravity.dynamic(sample);
+
+        if self.cooldown_until.is_some_and(|until| ts < until) {
+            return None;
+        }
+
+        if dynamic.magnitude() < self.threshold {
+            return None;
+        }
+
+        let direction = dynamic.normalize();
+        if self.direction.is_some_and(|d| d.dot(direction) < -0.5) {
+            self.seen.push_back(ts);
+        }
+        self.direction = Some(direction);
+
+        while self.seen.front().is_some_and(|&t| ts - t > self.window) {
+            self.seen.pop_front();
+        }
+
+        if self.seen.len() < self.reversals {
+            return None;
+        }
+
+        self.seen.clear();
+        self.direction = None;
+        self.cooldown_until = Some(ts + self.cooldown);
+
+        Some(MotionGesture::Shake)
+    }
+}
+
+impl Default for ShakeDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Detects the wrist being turned to look at the screen. This assumes the z axis points out of
+// the screen, so the device reads +1 g on z while lying screen up.
+#[derive(Copy, Clone, Debug)]
+pub struct WristRaiseDetector {
+    facing: f32,
+    away: f32,
+    max_raise: Duration,
+    hold: Duration,
+    gravity: Gravity,
+    last_away: Option<Instant>,
+    facing_since: Option<Instant>,
+    armed: bool,
+}
+
+impl WristRaiseDetector {
+    pub fn new() -> Self {
+        Self {
+            facing: 40f32.to_radians().cos(),
+            away: 70f32.to_radians().cos(),
+            max_raise: Duration::from_secs(1),
+            hold: Duration::from_millis(150),
+            gravity: Gravity::new(Duration::from_millis(200)),
+            last_away: None,
+            facing_since: None,
+            armed: false,
+        }
+    }
+
+    // The screen counts as facing up within `facing` degrees of level, and as turned away past
+    // `away` degrees.
+    pub fn with_angles(mut self, facing: f32, away: f32) -> Self {
+        self.facing = facing.to_radians().cos();
+        self.away = away.to_radians().cos();
+        self
+    }
+
+    // How long the turn from away to facing may take.
+    pub fn with_max_raise(mut self, duration: Duration) -> Self {
+        self.max_raise = duration;
+        self
+    }
+
+    // How long the screen has to stay facing up.
+    pub fn with_hold(mut self, duration: Duration) -> Self {
+        self.hold = duration;
+        self
+    }
+
+    pub fn process(&mut self, sample: &Sample) -> Option<MotionGesture> {
+        let ts = sample.timestamp;
+        let z = self.gravity.update(sample).normalize().z;
+
+        if z <= self.away {
+            self.last_away = Some(ts);
+            self.facing_since = None;
+            self.armed = true;
+            return None;
+        }
+
+        if z < self.facing {
+            self.facing_since = None;
+            return None;
+        }
+
+        let since = *self.facing_since.get_or_insert(ts);
+        let raised = self
+            .last_away
+            .is_some_and(|away| since - away <= self.max_raise);
+
+        if !self.armed || !raised || ts - since < self.hold {
+            return None;
+        }
+
+        self.armed = false;
+        Some(MotionGesture::WristRaise)
+    }
+}
+
+impl Default for WristRaiseDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Runs a set of detectors over accelerometer samples. Detection only depends on the samples and
+// their timestamps, so recorded traces can be fed through `process` to check it.
+#[derive(Clone, Debug)]
+pub struct MotionDetector {
+    tap: Option<TapDetector>,
+    shake: Option<ShakeDetector>,
+    wrist_raise: Option<WristRaiseDetector>,
+}
+
+impl MotionDetector {
+    // All detectors, with their default settings.
+    pub fn new() -> Self {
+        Self {
+            tap: Some(TapDetector::new()),
+            shake: Some(ShakeDetector::new()),
+            wrist_raise: Some(WristRaiseDetector::new()),
+        }
+    }
+
+    // No detectors, to add just the ones that are needed.
+    pub fn empty() -> Self {
+        Self {
+            tap: None,
+            shake: None,
+            wrist_raise: None,
+        }
+    }
+
+    pub fn with_tap(mut self, tap: Option<TapDetector>) -> Self {
+        self.tap = tap;
+        self
+    }
+
+    pub fn with_shake(mut self, shake: Option<ShakeDetector>) -> Self {
+        self.shake = shake;
+        self
+    }
+
+    pub fn with_wrist_raise(mut self, wrist_raise: Option<WristRaiseDetector>) -> Self {
+        self.wrist_raise = wrist_raise;
+        self
+    }
+
+    pub fn process(&mut self, samples: &[Sample]) -> Vec<MotionEvent> {
+        let mut events = Vec::new();
+
+        for sample in samples {
+            let gestures = [
+                self.tap.as_mut().and_then(|d| d.process(sample)),
+                self.shake.as_mut().and_then(|d| d.process(sample)),
+                self.wrist_raise.as_mut().and_then(|d| d.process(sample)),
+            ];
+
+            events.extend(gestures.into_iter().flatten().map(|gesture| MotionEvent {
+                gesture,
+                timestamp: sample.timestamp,
+            }));
+        }
+
+        events
+    }
+}
+
+impl Default for MotionDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Runs a `MotionDetector` over a stream of sample batches, such as an `Accelerometer`.
+#[derive(Debug)]
+pub struct Motion<S> {
+    samples: S,
+    detector: MotionDetector,
+    queue: VecDeque<MotionEvent>,
+}
+
+impl<S: Stream<Item = io::Result<Vec<Sample>>> + Unpin> Motion<S> {
+    pub fn new(samples: S, detector: MotionDetector) -> Self {
+        Self {
+            samples,
+            detector,
+            queue: VecDeque::new(),
+        }
+    }
+
+    pub fn detector(&self) -> &MotionDetector {
+        &self.detector
+    }
+
+    pub fn into_inner(self) -> S {
+        self.samples
+    }
+}
+
+impl<S: Stream<Item = io::Result<Vec<Sample>>> + Unpin> Stream for Motion<S> {
+    type Item = io::Result<MotionEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+
+            match self.samples.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(samples))) => {
+                    let events = self.detector.process(&samples);
+                    self.queue.extend(events);
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 50 Hz, like the accelerometer's default rate.
+    const PERIOD_MS: u64 = 20;
+
+    struct Trace {
+        samples: Vec<Sample>,
+    }
+
+    impl Trace {
+        fn new() -> Self {
+            Self {
+                samples: Vec::new(),
+            }
+        }
+
+        fn push(mut self, acceleration: Vector3) -> Self {
+            let ms = self.samples.len() as u64 * PERIOD_MS;
+
+            self.samples.push(Sample {
+                timestamp: Instant::from_micros(ms * 1000),
+                acceleration,
+            });
+            self
+        }
+
+        fn hold(self, acceleration: Vector3, ms: u64) -> Self {
+            (0..ms / PERIOD_MS).fold(self, |trace, _| trace.push(acceleration))
+        }
+
+        // Moves linearly from the last reading to `to`.
+        fn turn(self, to: Vector3, ms: u64) -> Self {
+            let from = self.samples.last().map_or(to, |s| s.acceleration);
+            let steps = ms / PERIOD_MS;
+
+            (1..=steps).fold(self, |trace, i| {
+                trace.push(from + (to - from) * (i as f32 / steps as f32))
+            })
+        }
+
+        fn gestures(&self) -> Vec<MotionGesture> {
+            MotionDetector::new()
+                .process(&self.samples)
+                .into_iter()
+                .map(|e| e.gesture)
+                .collect()
+        }
+    }
+
+    const FLAT: Vector3 = Vector3::new(0.0, 0.0, 1.0);
+    const HANGING: Vector3 = Vector3::new(0.0, -1.0, 0.0);
+
+    #[test]
+    fn steady() {
+        let trace = Trace::new().hold(FLAT, 3000);
+        assert_eq!(trace.gestures(), []);
+    }
+
+    #[test]
+    fn tap() {
+        let trace = Trace::new()
+            .hold(FLAT, 1000)
+            .push(Vector3::new(0.0, 0.0, 3.0))
+            .hold(FLAT, 1000);
+
+        assert_eq!(trace.gestures(), [MotionGesture::Tap]);
+    }
+
+    #[test]
+    fn double_tap() {
+        let trace = Trace::new()
+            .hold(FLAT, 1000)
+            .push(Vector3::new(0.0, 0.0, 3.0))
+            .hold(FLAT, 200)
+            .push(Vector3::new(0.0, 0.0, 3.0))
+            .hold(FLAT, 1000);
+
+        assert_eq!(
+            trace.gestures(),
+            [MotionGesture::Tap, MotionGesture::DoubleTap]
+        );
+    }
+
+    #[test]
+    fn taps_far_apart() {
+        let trace = Trace::new()
+            .hold(FLAT, 1000)
+            .push(Vector3::new(0.0, 0.0, 3.0))
+            .hold(FLAT, 1000)
+            .push(Vector3::new(0.0, 0.0, 3.0))
+            .hold(FLAT, 1000);
+
+        assert_eq!(trace.gestures(), [MotionGesture::Tap, MotionGesture::Tap]);
+    }
+
+    #[test]
+    fn shake() {
+        let left = Vector3::new(-2.0, 0.0, 1.0);
+        let right = Vector3::new(2.0, 0.0, 1.0);
+
+        let trace = (0..5)
+            .fold(Trace::new().hold(FLAT, 1000), |trace, _| {
+                trace.hold(left, 60).hold(right, 60)
+            })
+            .hold(FLAT, 1000);
+
+        assert_eq!(trace.gestures(), [MotionGesture::Shake]);
+    }
+
+    #[test]
+    fn wrist_raise() {
+        let trace = Trace::new()
+            .hold(HANGING, 1000)
+            .turn(FLAT, 300)
+            .hold(FLAT, 1000);
+
+        assert_eq!(trace.gestures(), [MotionGesture::WristRaise]);
+    }
+
+    #[test]
+    fn slow_turn_is_not_a_wrist_raise() {
+        let trace = Trace::new()
+            .hold(HANGING, 1000)
+            .turn(FLAT, 5000)
+            .hold(FLAT, 1000);
+
+        assert_eq!(trace.gestures(), []);
+    }
+
+    #[test]
+    fn tap_after_turning_over() {
+        let trace = Trace::new()
+            .hold(FLAT, 1000)
+            .turn(-FLAT, 40)
+            .hold(-FLAT, 2000)
+            .push(Vector3::new(0.0, 0.0, -3.0))
+            .hold(-FLAT, 1000);
+
+        assert_eq!(trace.gestures(), [MotionGesture::Tap]);
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::fs::{self, File, OpenOptions};
+
+// Payload length and CRC-32, both little-endian.
+const HEADER_LEN: usize = 8;
+
+// An append-only file of checksummed records. A record that was only partly written when power
+// was lost fails its checksum, and it and everything after it are discarded on the next open.
+#[derive(Debug)]
+pub(crate) struct Log {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    // Set when the log was replaced but couldn't be reopened, so `file` still refers to the old
+    // one and mustn't be written to.
+    broken: bool,
+}
+
+impl Log {
+    // Opens or creates the log, returning it along with every intact record.
+    pub(crate) fn open(path: &Path) -> io::Result<(Self, Vec<Vec<u8>>)> {
+        // A leftover temporary file means a rewrite was interrupted before the rename, so the
+        // original is still complete.
+        let tmp = tmp_path(path);
+        if fs::exists(&tmp)? {
+            fs::remove_file(&tmp)?;
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .with_read(true)
+            .with_write(true)
+            .with_create(true)
+            .open(path)?;
+
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+
+        let mut records = Vec::new();
+        let mut rest = data.as_slice();
+
+        while let Some((record, next)) = parse(rest) {
+            records.push(record.to_vec());
+            rest = next;
+        }
+
+        let len = (data.len() - rest.len()) as u64;
+        if !rest.is_empty() {
+            file.truncate(len)?;
+            file.sync_all()?;
+        }
+        file.seek(SeekFrom::Start(len))?;
+
+        let log = Self {
+            path: path.to_path_buf(),
+            file,
+            len,
+            broken: false,
+        };
+
+        Ok((log, records))
+    }
+
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    // Appends a record and waits until it is durable.
+    pub(crate) fn append(&mut self, payload: &[u8]) -> io::Result<()> {
+        if self.broken {
+            self.reopen()?;
+        }
+
+        let frame = frame(payload);
+
+        let res = self
+            .file
+            .write_all(&frame)
+            .and_then(|_| self.file.sync_all());
+
+        if let Err(e) = res {
+            // Don't leave a torn record in front of the next one.
+            let _ = self.file.truncate(self.len);
+            let _ = self.file.seek(SeekFrom::Start(self.len));
+            return Err(e);
+        }
+
+        self.len += frame.len() as u64;
+        Ok(())
+    }
+
+    // Replaces the whole log with `records`. The new contents are written to a temporary file
+    // which is then renamed over the log, so a crash leaves either the old or the new log. If the
+    // new log can't be opened afterwards, appends retry the open and fail until it succeeds.
+    pub(crate) fn rewrite<'a>(
+        &mut self,
+        records: impl IntoIterator<Item = &'a [u8]>,
+    ) -> io::Result<()> {
+        let tmp = tmp_path(&self.path);
+        let mut file = File::create(&tmp)?;
+        let mut len = 0;
+
+        for record in records {
+            let frame = frame(record);
+            file.write_all(&frame)?;
+            len += frame.len() as u64;
+        }
+
+        file.sync_all()?;
+        drop(file);
+
+        fs::rename(&tmp, &self.path)?;
+
+        self.len = len;
+        self.reopen()
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        self.broken = true;
+
+        let mut file = OpenOptions::new()
+            .with_read(true)
+            .with_write(true)
+            .open(&self.path)?;
+        file.seek(SeekFrom::Start(self.len))?;
+
+        self.file = file;
+        self.broken = false;
+
+        Ok(())
+    }
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    tmp.into()
+}
+
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&crc32(payload).to_le_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn parse(buf: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (header, rest) = buf.split_first_chunk::<HEADER_LEN>()?;
+    let [l0, l1, l2, l3, c0, c1, c2, c3] = *header;
+
+    let len = u32::from_le_bytes([l0, l1, l2, l3]) as usize;
+    let crc = u32::from_le_bytes([c0, c1, c2, c3]);
+
+    let payload = rest.get(..len)?;
+    match crc32(payload) == crc {
+        true => Some((payload, &rest[len..])),
+        false => None,
+    }
+}
+
+// CRC-32 (IEEE). Records are small, so a bitwise implementation is fast enough and saves a table.
+pub(super) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(mut buf: &[u8]) -> Vec<&[u8]> {
+        let mut records = Vec::new();
+
+        while let Some((record, rest)) = parse(buf) {
+            records.push(record);
+            buf = rest;
+        }
+
+        records
+    }
+
+    fn log(payloads: &[&[u8]]) -> Vec<u8> {
+        payloads.iter().flat_map(|p| frame(p)).collect()
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn frames() {
+        let data = log(&[b"one", b"", b"three"]);
+
+        assert_eq!(data.len(), 3 * HEADER_LEN + 8);
+        assert_eq!(records(&data), [&b"one"[..], b"", b"three"]);
+    }
+
+    #[test]
+    fn torn_writes() {
+        let data = log(&[b"one", b"two"]);
+        let second = HEADER_LEN + 3;
+
+        // Torn in the second record's header, and in its payload.
+        assert_eq!(records(&data[..second + 5]), [b"one"]);
+        assert_eq!(records(&data[..data.len() - 1]), [b"one"]);
+    }
+
+    #[test]
+    fn corrupted() {
+        let mut data = log(&[b"one", b"two", b"three"]);
+        let second = HEADER_LEN + 3;
+
+        // Everything from the first bad record on is dropped, even if later ones are intact.
+        data[second + 4] ^= 0xff;
+        assert_eq!(records(&data), [b"one"]);
+
+        data[second + 4] ^= 0xff;
+        data[second + HEADER_LEN] ^= 0x01;
+        assert_eq!(records(&data), [b"one"]);
+    }
+
+    #[test]
+    fn bogus_length() {
+        let mut data = log(&[b"one", b"two"]);
+        let second = HEADER_LEN + 3;
+
+        data[second..second + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(records(&data), [b"one"]);
+    }
+
+    #[test]
+    fn open_truncates_torn_tail() {
+        let path = Path::new("log/torn");
+        fs::create_dir_all("log").unwrap();
+
+        let data = log(&[b"one", b"two"]);
+        fs::write(path, &data[..data.len() - 2]).unwrap();
+
+        let (mut log, records) = Log::open(path).unwrap();
+        assert_eq!(records, [b"one"]);
+        assert_eq!(log.len(), (HEADER_LEN + 3) as u64);
+
+        log.append(b"four").unwrap();
+        log.rewrite([&b"five"[..], b"six"]).unwrap();
+        log.append(b"seven").unwrap();
+        drop(log);
+
+        let (_, records) = Log::open(path).unwrap();
+        assert_eq!(records, [&b"five"[..], b"six", b"seven"]);
+    }
+}
@@ -0,0 +1,250 @@
+use std::{collections::BTreeMap, io, path::Path};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+mod log;
+mod series;
+
+use self::log::Log;
+
+pub use series::{Range, Sample, TimeSeries, Timestamp};
+
+// Once the log is this big, it's compacted whenever it holds more than twice the live data.
+const COMPACT_THRESHOLD: u64 = 16 * 1024;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("serialization error: {0}")]
+    Serialization(#[from] postcard::Error),
+    #[error("stored schema version {found} is newer than {supported}")]
+    VersionTooNew { found: u32, supported: u32 },
+    #[error("timestamp {timestamp} is older than the last sample at {last}")]
+    OutOfOrder { last: u64, timestamp: u64 },
+}
+
+// One commit is a single log record holding all of its operations, so it's applied completely or
+// not at all.
+#[derive(Serialize, Deserialize)]
+enum Op {
+    Set(String, Vec<u8>),
+    Remove(String),
+    Clear,
+    Version(u32),
+}
+
+// A persistent key-value store with postcard-encoded values, kept as a log of commits in a single
+// file. Every commit is synced to flash before it returns, and the log is compacted as it grows.
+//
+// The whole store is kept in memory, so it's meant for settings and small app state rather than
+// bulk data.
+#[derive(Debug)]
+pub struct Store {
+    log: Log,
+    entries: BTreeMap<String, Vec<u8>>,
+    version: u32,
+}
+
+impl Store {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let (log, records) = Log::open(path.as_ref())?;
+
+        let mut store = Self {
+            log,
+            entries: BTreeMap::new(),
+            version: 0,
+        };
+
+        for record in records {
+            // A record that doesn't decode was written by something else, so there's nothing
+            // sensible to do with it.
+            if let Ok(ops) = postcard::from_bytes::<Vec<Op>>(&record) {
+                store.apply(ops);
+            }
+        }
+
+        Ok(store)
+    }
+
+    // The schema version, which starts at 0 for a new store.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    // Brings the store up to `version`. If it is older, `migrate` is called with the stored
+    // version and its changes are committed together with the new version number.
+    pub fn migrate(
+        &mut self,
+        version: u32,
+        migrate: impl FnOnce(&mut Transaction<'_>, u32) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        if self.version > version {
+            return Err(Error::VersionTooNew {
+                found: self.version,
+                supported: version,
+            });
+        }
+
+        if self.version == version {
+            return Ok(());
+        }
+
+        let old = self.version;
+        let mut tx = self.transaction();
+        migrate(&mut tx, old)?;
+        tx.ops.push(Op::Version(version));
+
+        tx.commit()
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, Error> {
+        self.entries
+            .get(key)
+            .map(|value| postcard::from_bytes(value))
+            .transpose()
+            .map_err(Error::from)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn set<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        let mut tx = self.transaction();
+        tx.set(key, value)?;
+        tx.commit()
+    }
+
+    // Returns whether the key was present.
+    pub fn remove(&mut self, key: &str) -> Result<bool, Error> {
+        if !self.contains(key) {
+            return Ok(false);
+        }
+
+        let mut tx = self.transaction();
+        tx.remove(key);
+        tx.commit()?;
+
+        Ok(true)
+    }
+
+    pub fn clear(&mut self) -> Result<(), Error> {
+        let mut tx = self.transaction();
+        tx.clear();
+        tx.commit()
+    }
+
+    pub fn transaction(&mut self) -> Transaction<'_> {
+        Transaction {
+            store: self,
+            ops: Vec::new(),
+        }
+    }
+
+    // Rewrites the log with just the live data.
+    pub fn compact(&mut self) -> Result<(), Error> {
+        let mut ops = vec![Op::Version(self.version)];
+        ops.extend(
+            self.entries
+                .iter()
+                .map(|(key, value)| Op::Set(key.clone(), value.clone())),
+        );
+
+        let record = postcard::to_allocvec(&ops)?;
+        self.log.rewrite([record.as_slice()])?;
+
+        Ok(())
+    }
+
+    fn commit(&mut self, ops: Vec<Op>) -> Result<(), Error> {
+        if ops.is_empty() {
+            return Ok(());
+        }
+
+        self.log.append(&postcard::to_allocvec(&ops)?)?;
+        self.apply(ops);
+
+        let live = self
+            .entries
+            .iter()
+            .map(|(key, value)| (key.len() + value.len()) as u64)
+            .sum::<u64>();
+
+        // The commit is durable either way, and a failed compaction is retried on the next one.
+        if self.log.len() > COMPACT_THRESHOLD.max(live * 2) {
+            let _ = self.compact();
+        }
+
+        Ok(())
+    }
+
+    fn apply(&mut self, ops: Vec<Op>) {
+        for op in ops {
+            match op {
+                Op::Set(key, value) => {
+                    self.entries.insert(key, value);
+                }
+                Op::Remove(key) => {
+                    self.entries.remove(&key);
+                }
+                Op::Clear => self.entries.clear(),
+                Op::Version(version) => self.version = version,
+            }
+        }
+    }
+}
+
+// A batch of changes that is committed atomically. Dropping it without committing discards the
+// changes.
+pub struct Transaction<'a> {
+    store: &'a mut Store,
+    ops: Vec<Op>,
+}
+
+impl Transaction<'_> {
+    // Reads a value, including changes made earlier in this transaction.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, Error> {
+        for op in self.ops.iter().rev() {
+            match op {
+                Op::Set(k, value) if k == key => return Ok(Some(postcard::from_bytes(value)?)),
+                Op::Remove(k) if k == key => return Ok(None),
+                Op::Clear => return Ok(None),
+                _ => {}
+            }
+        }
+
+        self.store.get(key)
+    }
+
+    pub fn set<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        self.ops
+            .push(Op::Set(key.to_owned(), postcard::to_allocvec(value)?));
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.ops.push(Op::Remove(key.to_owned()));
+    }
+
+    pub fn clear(&mut self) {
+        self.ops.push(Op::Clear);
+    }
+
+    pub fn commit(self) -> Result<(), Error> {
+        self.store.commit(self.ops)
+    }
+}
@@ -0,0 +1,802 @@
+use std::{
+    collections::VecDeque,
+    io::Write,
+    ops::{Bound, RangeBounds},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    fs::{self, File, OpenOptions},
+    time::Duration,
+};
+
+use super::{log::crc32, Error};
+
+const DEFAULT_SEGMENT_LEN: usize = 512;
+const DEFAULT_FLUSH_LEN: usize = 32;
+// Timestamp and checksum.
+const OVERHEAD: usize = 8 + 2;
+
+// Milliseconds since the Unix epoch.
+pub type Timestamp = u64;
+
+// A value with a fixed-size encoding, so every record in a series takes the same space.
+pub trait Sample: Copy {
+    const SIZE: usize;
+
+    fn write(&self, buf: &mut [u8]);
+
+    fn read(buf: &[u8]) -> Self;
+}
+
+macro_rules! impl_sample {
+    ($($ty:ty),*) => {
+        $(
+            impl Sample for $ty {
+                const SIZE: usize = size_of::<$ty>();
+
+                fn write(&self, buf: &mut [u8]) {
+                    buf[..Self::SIZE].copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn read(buf: &[u8]) -> Self {
+                    let mut bytes = [0; size_of::<$ty>()];
+                    bytes.copy_from_slice(&buf[..Self::SIZE]);
+                    Self::from_le_bytes(bytes)
+                }
+            }
+        )*
+    };
+}
+
+impl_sample!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+
+impl<T: Sample, const N: usize> Sample for [T; N] {
+    const SIZE: usize = T::SIZE * N;
+
+    fn write(&self, buf: &mut [u8]) {
+        for (value, chunk) in self.iter().zip(buf.chunks_mut(T::SIZE)) {
+            value.write(chunk);
+        }
+    }
+
+    fn read(buf: &[u8]) -> Self {
+        std::array::from_fn(|i| T::read(&buf[i * T::SIZE..]))
+    }
+}
+
+// A segment file, named after its first timestamp and the bucket size it was downsampled to, or
+// 0 for raw samples.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+struct Segment {
+    start: Timestamp,
+    end: Timestamp,
+    bucket: u64,
+    records: usize,
+}
+
+impl Segment {
+    fn file_name(start: Timestamp, bucket: u64, ext: &str) -> String {
+        format!("{start:016x}-{bucket:x}.{ext}")
+    }
+
+    // A finished compaction output also names the first and last source segments it replaces,
+    // so they can be found again if the compaction is interrupted.
+    fn pending_name(start: Timestamp, bucket: u64, sources: &[Segment]) -> String {
+        let first = sources.first().map_or(0, |s| s.start);
+        let last = sources.last().map_or(0, |s| s.start);
+
+        format!("{start:016x}-{bucket:x}.{first:016x}-{last:016x}.pending")
+    }
+
+    fn parse_name(name: &str) -> Option<(Timestamp, u64, &str)> {
+        let (stem, ext) = name.split_once('.')?;
+        let (start, bucket) = parse_pair(stem)?;
+
+        Some((start, bucket, ext))
+    }
+
+    // Returns the output's start and bucket, and the range of source segment starts.
+    fn parse_pending(name: &str) -> Option<(Timestamp, u64, Timestamp, Timestamp)> {
+        let (stem, sources) = name.strip_suffix(".pending")?.split_once('.')?;
+        let (start, bucket) = parse_pair(stem)?;
+        let (first, last) = parse_pair(sources)?;
+
+        Some((start, bucket, first, last))
+    }
+}
+
+fn parse_pair(s: &str) -> Option<(u64, u64)> {
+    let (a, b) = s.split_once('-')?;
+    Some((
+        u64::from_str_radix(a, 16).ok()?,
+        u64::from_str_radix(b, 16).ok()?,
+    ))
+}
+
+// An append-only store of timestamped samples, e.g. hours of heart rate readings.
+//
+// Samples are stored in segment files of fixed-size, checksummed records. New samples are
+// buffered in memory and written a batch at a time to keep flash wear down, so anything not yet
+// flushed is lost if power goes. Old data is dropped a whole segment at a time to stay within the
+// retention limits, and can be downsampled with `compact` to keep a long history small.
+#[derive(Debug)]
+pub struct TimeSeries<T> {
+    dir: PathBuf,
+    segments: Vec<Segment>,
+    buffer: Vec<(Timestamp, T)>,
+    segment_len: usize,
+    flush_len: usize,
+    retention: Option<u64>,
+    max_bytes: Option<u64>,
+}
+
+impl<T: Sample> TimeSeries<T> {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            names.push(entry?.file_name().to_owned());
+        }
+
+        // Finish a compaction that was interrupted after its output was complete.
+        for name in &names {
+            if let Some((start, bucket, first, last)) = Segment::parse_pending(name) {
+                // The sources were adjacent, so every finer segment in between was one of them.
+                for other in &names {
+                    if let Some((s, b, "seg")) = Segment::parse_name(other) {
+                        if (first..=last).contains(&s) && b < bucket {
+                            fs::remove_file(dir.join(other))?;
+                        }
+                    }
+                }
+
+                // An earlier output with the same name was merged into this one, so it's fine to
+                // replace it.
+                let seg = Segment::file_name(start, bucket, "seg");
+                fs::rename(dir.join(name), dir.join(seg))?;
+            } else if let Some((_, _, "tmp")) = Segment::parse_name(name) {
+                fs::remove_file(dir.join(name))?;
+            }
+        }
+
+        let mut series = Self {
+            dir,
+            segments: Vec::new(),
+            buffer: Vec::new(),
+            segment_len: DEFAULT_SEGMENT_LEN,
+            flush_len: DEFAULT_FLUSH_LEN,
+            retention: None,
+            max_bytes: None,
+        };
+
+        for entry in fs::read_dir(&series.dir)? {
+            let entry = entry?;
+            let Some((start, bucket, "seg")) = Segment::parse_name(entry.file_name()) else {
+                continue;
+            };
+
+            let path = entry.path();
+            let records = read_records::<T>(path)?;
+
+            // Only the last write can be torn, so drop anything after the last intact record.
+            let intact = (records.len() * slot_len::<T>()) as u64;
+            if entry.metadata().len() != intact {
+                OpenOptions::new()
+                    .with_write(true)
+                    .open(path)?
+                    .truncate(intact)?;
+            }
+
+            match records.last() {
+                Some((end, _)) => series.segments.push(Segment {
+                    start,
+                    end: *end,
+                    bucket,
+                    records: records.len(),
+                }),
+                None => fs::remove_file(path)?,
+            }
+        }
+
+        series.segments.sort_by_key(|s| s.start);
+
+        Ok(series)
+    }
+
+    // The number of samples per segment file.
+    pub fn with_segment_len(mut self, records: usize) -> Self {
+        self.segment_len = records.max(1);
+        self
+    }
+
+    // The number of samples to buffer before writing them out.
+    pub fn with_flush_len(mut self, records: usize) -> Self {
+        self.flush_len = records.max(1);
+        self
+    }
+
+    // Drops samples older than `retention` relative to the newest one.
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = Some(retention.as_millis() as u64);
+        self
+    }
+
+    // Drops the oldest samples once the segment files take up more than `max_bytes`.
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    pub fn first(&self) -> Option<Timestamp> {
+        self.segments
+            .first()
+            .map(|s| s.start)
+            .or_else(|| self.buffer.first().map(|(ts, _)| *ts))
+    }
+
+    pub fn last(&self) -> Option<Timestamp> {
+        self.buffer
+            .last()
+            .map(|(ts, _)| *ts)
+            .or_else(|| self.segments.last().map(|s| s.end))
+    }
+
+    // The space taken by the segment files, not counting buffered samples.
+    pub fn size(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|s| (s.records * slot_len::<T>()) as u64)
+            .sum()
+    }
+
+    // Samples must be appended in timestamp order.
+    pub fn append(&mut self, timestamp: Timestamp, value: T) -> Result<(), Error> {
+        if let Some(last) = self.last().filter(|&last| timestamp < last) {
+            return Err(Error::OutOfOrder { last, timestamp });
+        }
+
+        self.buffer.push((timestamp, value));
+
+        if self.buffer.len() >= self.flush_len {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    // Writes buffered samples out and applies the retention limits. If a write fails, the samples
+    // that weren't written stay buffered for the next flush.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        while !self.buffer.is_empty() {
+            let written = self.write_batch()?;
+            self.buffer.drain(..written);
+        }
+
+        self.apply_retention()
+    }
+
+    // Writes as many buffered samples as fit in the active segment, returning how many that was.
+    fn write_batch(&mut self) -> Result<usize, Error> {
+        let first = self.buffer[0].0;
+
+        let mut active = match self.segments.last() {
+            Some(s) if s.bucket == 0 && s.records < self.segment_len => {
+                self.segments.pop().unwrap()
+            }
+            _ => Segment {
+                start: first,
+                end: first,
+                bucket: 0,
+                records: 0,
+            },
+        };
+
+        let count = self.buffer.len().min(self.segment_len - active.records);
+        let batch = &self.buffer[..count];
+
+        let path = self.dir.join(Segment::file_name(active.start, 0, "seg"));
+        let res = OpenOptions::new()
+            .with_write(true)
+            .with_append(true)
+            .with_create(true)
+            .open(path)
+            .and_then(|mut file| {
+                let res = file.write_all(&encode(batch)).and_then(|_| file.sync_all());
+
+                // Don't leave a torn record in front of the next batch.
+                if res.is_err() {
+                    let _ = file.truncate((active.records * slot_len::<T>()) as u64);
+                }
+                res
+            });
+
+        if let Err(e) = res {
+            if active.records > 0 {
+                self.segments.push(active);
+            }
+            return Err(e.into());
+        }
+
+        active.records += count;
+        active.end = batch[count - 1].0;
+        self.segments.push(active);
+
+        Ok(count)
+    }
+
+    // Iterates over samples in `range`, including buffered ones. Only one segment is held in
+    // memory at a time.
+    pub fn range(&self, range: impl RangeBounds<Timestamp>) -> Range<'_, T> {
+        let start = range.start_bound().cloned();
+        let end = range.end_bound().cloned();
+
+        let segments = self
+            .segments
+            .iter()
+            .filter(|s| in_range(&(start, end), s.start, s.end))
+            .copied()
+            .collect();
+
+        Range {
+            series: self,
+            start,
+            end,
+            segments,
+            current: Vec::new().into_iter(),
+            buffer_done: false,
+        }
+    }
+
+    // Combines the samples in `range` into one per `bucket`, timestamped with the start of the
+    // bucket.
+    pub fn downsample(
+        &self,
+        range: impl RangeBounds<Timestamp>,
+        bucket: Duration,
+        mut reduce: impl FnMut(&[T]) -> T,
+    ) -> Result<Vec<(Timestamp, T)>, Error> {
+        let bucket = (bucket.as_millis() as u64).max(1);
+        let mut out = Vec::new();
+        let mut current: Option<(Timestamp, Vec<T>)> = None;
+
+        for sample in self.range(range) {
+            let (ts, value) = sample?;
+            let key = ts - ts % bucket;
+
+            match &mut current {
+                Some((k, values)) if *k == key => values.push(value),
+                _ => {
+                    if let Some((k, values)) = current.replace((key, vec![value])) {
+                        out.push((k, reduce(&values)));
+                    }
+                }
+            }
+        }
+
+        if let Some((k, values)) = current {
+            out.push((k, reduce(&values)));
+        }
+
+        Ok(out)
+    }
+
+    // Replaces samples older than `before` with one per `bucket`. Segments that are already at
+    // least that coarse, and the one being appended to, are left alone.
+    pub fn compact(
+        &mut self,
+        before: Timestamp,
+        bucket: Duration,
+        mut reduce: impl FnMut(&[T]) -> T,
+    ) -> Result<(), Error> {
+        let bucket = (bucket.as_millis() as u64).max(1);
+        let eligible = |s: &Segment| s.end < before && s.bucket < bucket;
+
+        // Work through runs of adjacent eligible segments, so coarser segments in between aren't
+        // downsampled again.
+        loop {
+            let active = self.segments.len().saturating_sub(1);
+            let candidates = &self.segments[..active];
+
+            let Some(first) = candidates.iter().position(eligible) else {
+                return Ok(());
+            };
+            let len = candidates[first..]
+                .iter()
+                .take_while(|s| eligible(s))
+                .count();
+
+            let sources = candidates[first..first + len].to_vec();
+            self.compact_run(&sources, bucket, &mut reduce)?;
+        }
+    }
+
+    fn compact_run(
+        &mut self,
+        sources: &[Segment],
+        bucket: u64,
+        reduce: impl FnMut(&[T]) -> T,
+    ) -> Result<(), Error> {
+        let range = sources[0].start..=sources[sources.len() - 1].end;
+        let mut samples = self.downsample(range, Duration::from_millis(bucket), reduce)?;
+        let start = samples.first().map_or(sources[0].start, |(ts, _)| *ts);
+
+        // An earlier compaction that ended in the same bucket has an output with the same name,
+        // so carry its samples over instead of overwriting it.
+        let mut sources = sources.to_vec();
+        if let Some(&earlier) = self
+            .segments
+            .iter()
+            .find(|s| s.start == start && s.bucket == bucket)
+        {
+            let name = Segment::file_name(earlier.start, earlier.bucket, "seg");
+            samples.splice(0..0, read_records::<T>(self.dir.join(name))?);
+            sources.insert(0, earlier);
+        }
+
+        // The output is complete once it's renamed to `.pending`, and `open` finishes the job if
+        // we're interrupted after that.
+        let tmp = self.dir.join(Segment::file_name(start, bucket, "tmp"));
+        let pending = self
+            .dir
+            .join(Segment::pending_name(start, bucket, &sources));
+
+        let mut file = File::create(&tmp)?;
+        file.write_all(&encode(&samples))?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&tmp, &pending)?;
+
+        for source in &sources {
+            let name = Segment::file_name(source.start, source.bucket, "seg");
+            fs::remove_file(self.dir.join(name))?;
+        }
+
+        fs::rename(
+            &pending,
+            self.dir.join(Segment::file_name(start, bucket, "seg")),
+        )?;
+
+        self.segments.retain(|s| !sources.contains(s));
+        if let Some(&(end, _)) = samples.last() {
+            self.segments.push(Segment {
+                start,
+                end,
+                bucket,
+                records: samples.len(),
+            });
+            self.segments.sort_by_key(|s| s.start);
+        }
+
+        Ok(())
+    }
+
+    fn apply_retention(&mut self) -> Result<(), Error> {
+        let cutoff = match (self.retention, self.last()) {
+            (Some(retention), Some(last)) => last.saturating_sub(retention),
+            _ => 0,
+        };
+
+        // The newest segment is never removed.
+        while self.segments.len() > 1 {
+            let oldest = self.segments[0];
+            let too_old = oldest.end < cutoff;
+            let too_big = self.max_bytes.is_some_and(|max| self.size() > max);
+
+            if !too_old && !too_big {
+                break;
+            }
+
+            let name = Segment::file_name(oldest.start, oldest.bucket, "seg");
+            fs::remove_file(self.dir.join(name))?;
+            self.segments.remove(0);
+        }
+
+        Ok(())
+    }
+}
+
+pub struct Range<'a, T> {
+    series: &'a TimeSeries<T>,
+    start: Bound<Timestamp>,
+    end: Bound<Timestamp>,
+    segments: VecDeque<Segment>,
+    current: std::vec::IntoIter<(Timestamp, T)>,
+    buffer_done: bool,
+}
+
+impl<T: Sample> Iterator for Range<'_, T> {
+    type Item = Result<(Timestamp, T), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bounds = (self.start, self.end);
+
+        loop {
+            if let Some(sample) = self.current.find(|(ts, _)| bounds.contains(ts)) {
+                return Some(Ok(sample));
+            }
+
+            if let Some(segment) = self.segments.pop_front() {
+                let name = Segment::file_name(segment.start, segment.bucket, "seg");
+
+                match read_records(self.series.dir.join(name)) {
+                    Ok(records) => self.current = records.into_iter(),
+                    Err(e) => return Some(Err(e.into())),
+                }
+            } else if !self.buffer_done {
+                self.buffer_done = true;
+                self.current = self.series.buffer.clone().into_iter();
+            } else {
+                return None;
+            }
+        }
+    }
+}
+
+fn in_range(
+    bounds: &(Bound<Timestamp>, Bound<Timestamp>),
+    start: Timestamp,
+    end: Timestamp,
+) -> bool {
+    let after_start = match bounds.0 {
+        Bound::Included(s) => end >= s,
+        Bound::Excluded(s) => end > s,
+        Bound::Unbounded => true,
+    };
+    let before_end = match bounds.1 {
+        Bound::Included(e) => start <= e,
+        Bound::Excluded(e) => start < e,
+        Bound::Unbounded => true,
+    };
+
+    after_start && before_end
+}
+
+fn slot_len<T: Sample>() -> usize {
+    OVERHEAD + T::SIZE
+}
+
+fn encode<T: Sample>(samples: &[(Timestamp, T)]) -> Vec<u8> {
+    let slot = slot_len::<T>();
+    let mut buf = vec![0; samples.len() * slot];
+
+    for ((ts, value), chunk) in samples.iter().zip(buf.chunks_exact_mut(slot)) {
+        chunk[..8].copy_from_slice(&ts.to_le_bytes());
+        value.write(&mut chunk[8..8 + T::SIZE]);
+
+        let crc = crc32(&chunk[..8 + T::SIZE]) as u16;
+        chunk[8 + T::SIZE..].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    buf
+}
+
+// Reads a segment up to the first record that fails its checksum.
+fn read_records<T: Sample>(path: impl AsRef<Path>) -> std::io::Result<Vec<(Timestamp, T)>> {
+    let data = fs::read(path)?;
+
+    Ok(data
+        .chunks_exact(slot_len::<T>())
+        .map_while(|chunk| {
+            let (record, crc) = chunk.split_at(8 + T::SIZE);
+
+            match crc32(record) as u16 == u16::from_le_bytes([crc[0], crc[1]]) {
+                true => Some((
+                    u64::from_le_bytes(record[..8].try_into().unwrap()),
+                    T::read(&record[8..]),
+                )),
+                false => None,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(name: &str) -> TimeSeries<u32> {
+        TimeSeries::open(format!("series/{name}"))
+            .unwrap()
+            .with_segment_len(4)
+            .with_flush_len(1)
+    }
+
+    fn samples(series: &TimeSeries<u32>) -> Vec<(Timestamp, u32)> {
+        series.range(..).collect::<Result<_, _>>().unwrap()
+    }
+
+    fn sum(values: &[u32]) -> u32 {
+        values.iter().sum()
+    }
+
+    #[test]
+    fn records() {
+        let samples = [(1, 10u32), (2, 20), (u64::MAX, u32::MAX)];
+        let mut data = encode(&samples);
+        fs::create_dir_all("series").unwrap();
+        assert_eq!(data.len(), 3 * slot_len::<u32>());
+
+        fs::write("series/records", &data).unwrap();
+        assert_eq!(read_records::<u32>("series/records").unwrap(), samples);
+
+        // A torn last record is dropped, and so is everything after a corrupted one.
+        fs::write("series/records", &data[..data.len() - 1]).unwrap();
+        assert_eq!(read_records::<u32>("series/records").unwrap(), samples[..2]);
+
+        data[slot_len::<u32>() + 8] ^= 1;
+        fs::write("series/records", &data).unwrap();
+        assert_eq!(read_records::<u32>("series/records").unwrap(), samples[..1]);
+    }
+
+    #[test]
+    fn array_samples() {
+        let samples = [(5, [1.5f32, -2.0, 3.25])];
+        let data = encode(&samples);
+
+        fs::create_dir_all("series").unwrap();
+        fs::write("series/arrays", &data).unwrap();
+        assert_eq!(read_records::<[f32; 3]>("series/arrays").unwrap(), samples);
+    }
+
+    #[test]
+    fn ranges() {
+        use Bound::*;
+
+        assert!(in_range(&(Unbounded, Unbounded), 10, 20));
+        assert!(in_range(&(Included(20), Unbounded), 10, 20));
+        assert!(!in_range(&(Excluded(20), Unbounded), 10, 20));
+        assert!(in_range(&(Unbounded, Included(10)), 10, 20));
+        assert!(!in_range(&(Unbounded, Excluded(10)), 10, 20));
+        assert!(in_range(&(Included(12), Excluded(15)), 10, 20));
+        assert!(!in_range(&(Included(0), Included(9)), 10, 20));
+        assert!(!in_range(&(Included(21), Included(30)), 10, 20));
+    }
+
+    #[test]
+    fn names() {
+        let name = Segment::file_name(0x1234, 1000, "seg");
+        assert_eq!(name, "0000000000001234-3e8.seg");
+        assert_eq!(Segment::parse_name(&name), Some((0x1234, 1000, "seg")));
+        assert_eq!(Segment::parse_name("notes.txt"), None);
+
+        let source = |start| Segment {
+            start,
+            end: start,
+            bucket: 0,
+            records: 1,
+        };
+        let name = Segment::pending_name(0, 1000, &[source(5), source(600), source(900)]);
+        assert_eq!(Segment::parse_pending(&name), Some((0, 1000, 5, 900)));
+        assert_eq!(
+            Segment::parse_name(&name).map(|(s, b, _)| (s, b)),
+            Some((0, 1000))
+        );
+        assert_eq!(Segment::parse_pending("0000000000000000-0.seg"), None);
+    }
+
+    #[test]
+    fn reopen() {
+        let mut series = open("reopen");
+        for ts in 0..10 {
+            series.append(ts, ts as u32).unwrap();
+        }
+        drop(series);
+
+        let series = open("reopen");
+        assert_eq!((series.first(), series.last()), (Some(0), Some(9)));
+        assert_eq!(samples(&series).len(), 10);
+        assert_eq!(
+            series.range(3..5).collect::<Result<Vec<_>, _>>().unwrap(),
+            [(3, 3), (4, 4)]
+        );
+    }
+
+    #[test]
+    fn buffered_samples() {
+        let mut series = open("buffered").with_flush_len(100);
+        series.append(1, 1).unwrap();
+        series.append(2, 2).unwrap();
+
+        assert_eq!(series.size(), 0);
+        assert_eq!(samples(&series), [(1, 1), (2, 2)]);
+        assert!(matches!(
+            series.append(1, 0),
+            Err(Error::OutOfOrder {
+                last: 2,
+                timestamp: 1
+            })
+        ));
+
+        series.flush().unwrap();
+        assert_eq!(series.size(), 2 * slot_len::<u32>() as u64);
+    }
+
+    #[test]
+    fn downsample() {
+        let mut series = open("downsample");
+        for ts in [0, 400, 999, 1000, 2500, 2999] {
+            series.append(ts, 1).unwrap();
+        }
+
+        let buckets = series.downsample(.., Duration::from_secs(1), sum).unwrap();
+        assert_eq!(buckets, [(0, 3), (1000, 1), (2000, 2)]);
+
+        let buckets = series
+            .downsample(400..2600, Duration::from_secs(1), sum)
+            .unwrap();
+        assert_eq!(buckets, [(0, 2), (1000, 1), (2000, 1)]);
+    }
+
+    #[test]
+    fn retention() {
+        let mut series = open("retention").with_retention(Duration::from_millis(1000));
+        for ts in (0..=2000).step_by(100) {
+            series.append(ts, 1).unwrap();
+        }
+
+        // Whole segments are dropped, once all of their samples are too old.
+        assert_eq!(series.first(), Some(800));
+        assert_eq!(samples(&series).len(), 13);
+
+        let mut series = open("max_bytes").with_max_bytes(8 * slot_len::<u32>() as u64);
+        for ts in 0..20 {
+            series.append(ts, 1).unwrap();
+        }
+
+        assert!(series.size() <= 8 * slot_len::<u32>() as u64);
+        assert_eq!(series.first(), Some(12));
+    }
+
+    #[test]
+    fn interrupted_compaction() {
+        let mut series = open("interrupted");
+        for ts in (0..=800).step_by(100) {
+            series.append(ts, 1).unwrap();
+        }
+        let sources = series.segments[..2].to_vec();
+        drop(series);
+
+        // The output was written, but only the first source was removed.
+        let pending = Segment::pending_name(0, 1000, &sources);
+        fs::write(
+            format!("series/interrupted/{pending}"),
+            encode(&[(0, 8u32)]),
+        )
+        .unwrap();
+        fs::remove_file("series/interrupted/0000000000000000-0.seg").unwrap();
+
+        let series = open("interrupted");
+        assert_eq!(samples(&series), [(0, 8), (800, 1)]);
+    }
+
+    #[test]
+    fn compact_twice_in_one_bucket() {
+        let mut series = open("compact_twice");
+
+        // Segments of 0-300, 400-700 and 800-1100 ms, and 1200 ms being appended to.
+        for ts in (0..=1200).step_by(100) {
+            series.append(ts, 1).unwrap();
+        }
+
+        // Both compactions end up with a sample in the bucket starting at 0.
+        series.compact(500, Duration::from_secs(1), sum).unwrap();
+        series.compact(1000, Duration::from_secs(1), sum).unwrap();
+
+        let expected = [
+            (0, 4),
+            (0, 4),
+            (800, 1),
+            (900, 1),
+            (1000, 1),
+            (1100, 1),
+            (1200, 1),
+        ];
+        assert_eq!(samples(&series), expected);
+
+        drop(series);
+        assert_eq!(samples(&open("compact_twice")), expected);
+    }
+}
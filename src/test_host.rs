@@ -0,0 +1,306 @@
+// Stand-ins for the host's syscalls, so unit tests can use the clock, timers, critical sections,
+// the RNG and storage when they run on the build machine.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    slice, str,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Condvar, LazyLock, Mutex, OnceLock,
+    },
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
+};
+
+use crate::ffi::{fs as ffi, io::Handle, net};
+
+// The thread inside the critical section and how many times it has entered it.
+static OWNER: Mutex<Option<(ThreadId, usize)>> = Mutex::new(None);
+static RELEASED: Condvar = Condvar::new();
+
+static RNG: AtomicU64 = AtomicU64::new(0x9e37_79b9_7f4a_7c15);
+
+#[no_mangle]
+extern "C" fn cs_acquire() {
+    let me = thread::current().id();
+    let mut owner = OWNER.lock().unwrap();
+
+    loop {
+        match &mut *owner {
+            None => {
+                *owner = Some((me, 1));
+                return;
+            }
+            Some((id, depth)) if *id == me => {
+                *depth += 1;
+                return;
+            }
+            Some(_) => owner = RELEASED.wait(owner).unwrap(),
+        }
+    }
+}
+
+#[no_mangle]
+extern "C" fn cs_release() {
+    let mut owner = OWNER.lock().unwrap();
+
+    if let Some((_, depth)) = &mut *owner {
+        *depth -= 1;
+        if *depth == 0 {
+            *owner = None;
+            RELEASED.notify_all();
+        }
+    }
+}
+
+#[no_mangle]
+extern "C" fn get_time() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+
+    START.get_or_init(Instant::now).elapsed().as_micros() as u64
+}
+
+#[no_mangle]
+extern "C" fn register_timer_wake(wake: extern "C" fn(), micros: u64) {
+    thread::spawn(move || {
+        thread::sleep(Duration::from_micros(micros));
+        wake();
+    });
+}
+
+// splitmix64, which is plenty for tests.
+#[no_mangle]
+extern "C" fn random_64() -> u64 {
+    let mut z = RNG.fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[no_mangle]
+extern "C" fn random_32() -> u32 {
+    random_64() as u32
+}
+
+#[no_mangle]
+unsafe extern "C" fn random_bytes(ptr: *mut u8, len: usize) {
+    let dest = std::slice::from_raw_parts_mut(ptr, len);
+
+    for chunk in dest.chunks_mut(8) {
+        chunk.copy_from_slice(&random_64().to_le_bytes()[..chunk.len()]);
+    }
+}
+
+// Listeners only exist so servers can be built, tests serve connections over in-memory streams.
+#[no_mangle]
+unsafe extern "C" fn tcp_bind(_addr: *const net::SocketAddr, handle: *mut Handle) -> i32 {
+    *handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+#[no_mangle]
+extern "C" fn tcp_listener_close(_handle: Handle) {}
+
+// Storage lives in a directory of its own for each test run, so tests should use paths that no
+// other test does.
+static FILES: LazyLock<Mutex<HashMap<Handle, File>>> = LazyLock::new(Default::default);
+// The entries of each open directory that haven't been read yet.
+static DIRS: LazyLock<Mutex<HashMap<Handle, Vec<DirEntry>>>> = LazyLock::new(Default::default);
+static NEXT_HANDLE: AtomicU32 = AtomicU32::new(1);
+
+type DirEntry = (String, ffi::Metadata);
+
+fn root() -> &'static PathBuf {
+    static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+    ROOT.get_or_init(|| {
+        let root = std::env::temp_dir().join(format!("xenon-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    })
+}
+
+unsafe fn path(ptr: *const u8, len: usize) -> PathBuf {
+    let path = str::from_utf8(slice::from_raw_parts(ptr, len)).unwrap();
+    root().join(path.trim_start_matches('/'))
+}
+
+// The host's error codes, as decoded by `net::cvt`.
+fn code(e: io::Error) -> isize {
+    match e.kind() {
+        io::ErrorKind::InvalidInput => -9,
+        io::ErrorKind::NotFound => -11,
+        io::ErrorKind::AlreadyExists => -14,
+        io::ErrorKind::PermissionDenied => -15,
+        io::ErrorKind::NotADirectory => -16,
+        io::ErrorKind::IsADirectory => -17,
+        io::ErrorKind::DirectoryNotEmpty => -18,
+        _ => -100,
+    }
+}
+
+fn status(res: io::Result<()>) -> i32 {
+    res.map_or_else(|e| code(e) as i32, |_| 0)
+}
+
+fn metadata(metadata: &fs::Metadata) -> ffi::Metadata {
+    ffi::Metadata {
+        len: metadata.len(),
+        is_dir: metadata.is_dir(),
+        modified: 0,
+    }
+}
+
+fn with_file<R>(handle: Handle, f: impl FnOnce(&mut File) -> io::Result<R>) -> io::Result<R> {
+    match FILES.lock().unwrap().get_mut(&handle) {
+        Some(file) => f(file),
+        None => Err(io::ErrorKind::InvalidInput.into()),
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn fs_open(ptr: *const u8, len: usize, flags: u32, handle: *mut Handle) -> i32 {
+    let file = OpenOptions::new()
+        .read(flags & ffi::OPEN_READ != 0)
+        .write(flags & ffi::OPEN_WRITE != 0)
+        .append(flags & ffi::OPEN_APPEND != 0)
+        .create(flags & ffi::OPEN_CREATE != 0)
+        .create_new(flags & ffi::OPEN_CREATE_NEW != 0)
+        .truncate(flags & ffi::OPEN_TRUNCATE != 0)
+        .open(path(ptr, len));
+
+    status(file.map(|file| {
+        let id = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+        FILES.lock().unwrap().insert(id, file);
+        *handle = id;
+    }))
+}
+
+#[no_mangle]
+unsafe extern "C" fn fs_read(handle: Handle, ptr: *mut u8, len: usize) -> isize {
+    with_file(handle, |file| {
+        file.read(slice::from_raw_parts_mut(ptr, len))
+    })
+    .map_or_else(code, |n| n as isize)
+}
+
+#[no_mangle]
+unsafe extern "C" fn fs_write(handle: Handle, ptr: *const u8, len: usize) -> isize {
+    with_file(handle, |file| file.write(slice::from_raw_parts(ptr, len)))
+        .map_or_else(code, |n| n as isize)
+}
+
+#[no_mangle]
+unsafe extern "C" fn fs_seek(handle: Handle, offset: i64, whence: u8, pos: *mut u64) -> i32 {
+    let from = match whence {
+        ffi::SEEK_START => SeekFrom::Start(offset as u64),
+        ffi::SEEK_CURRENT => SeekFrom::Current(offset),
+        _ => SeekFrom::End(offset),
+    };
+
+    status(with_file(handle, |file| file.seek(from)).map(|p| *pos = p))
+}
+
+#[no_mangle]
+extern "C" fn fs_truncate(handle: Handle, len: u64) -> i32 {
+    status(with_file(handle, |file| file.set_len(len)))
+}
+
+#[no_mangle]
+extern "C" fn fs_sync(handle: Handle) -> i32 {
+    status(with_file(handle, |file| file.sync_all()))
+}
+
+#[no_mangle]
+unsafe extern "C" fn fs_file_metadata(handle: Handle, out: *mut ffi::Metadata) -> i32 {
+    status(with_file(handle, |file| file.metadata()).map(|m| *out = metadata(&m)))
+}
+
+#[no_mangle]
+extern "C" fn fs_close(handle: Handle) {
+    FILES.lock().unwrap().remove(&handle);
+}
+
+#[no_mangle]
+unsafe extern "C" fn fs_metadata(ptr: *const u8, len: usize, out: *mut ffi::Metadata) -> i32 {
+    status(fs::metadata(path(ptr, len)).map(|m| *out = metadata(&m)))
+}
+
+#[no_mangle]
+unsafe extern "C" fn fs_rename(
+    from_ptr: *const u8,
+    from_len: usize,
+    to_ptr: *const u8,
+    to_len: usize,
+) -> i32 {
+    status(fs::rename(path(from_ptr, from_len), path(to_ptr, to_len)))
+}
+
+#[no_mangle]
+unsafe extern "C" fn fs_remove_file(ptr: *const u8, len: usize) -> i32 {
+    status(fs::remove_file(path(ptr, len)))
+}
+
+#[no_mangle]
+unsafe extern "C" fn fs_create_dir(ptr: *const u8, len: usize) -> i32 {
+    status(fs::create_dir(path(ptr, len)))
+}
+
+#[no_mangle]
+unsafe extern "C" fn fs_remove_dir(ptr: *const u8, len: usize) -> i32 {
+    status(fs::remove_dir(path(ptr, len)))
+}
+
+#[no_mangle]
+unsafe extern "C" fn fs_read_dir(ptr: *const u8, len: usize, handle: *mut Handle) -> i32 {
+    let entries = fs::read_dir(path(ptr, len)).and_then(|dir| {
+        dir.map(|entry| {
+            let entry = entry?;
+            Ok((
+                entry.file_name().into_string().unwrap(),
+                metadata(&entry.metadata()?),
+            ))
+        })
+        .collect::<io::Result<Vec<_>>>()
+    });
+
+    status(entries.map(|mut entries| {
+        entries.reverse();
+
+        let id = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+        DIRS.lock().unwrap().insert(id, entries);
+        *handle = id;
+    }))
+}
+
+#[no_mangle]
+unsafe extern "C" fn fs_read_dir_next(
+    handle: Handle,
+    out: *mut ffi::Metadata,
+    name_ptr: *mut u8,
+    name_len: usize,
+) -> isize {
+    let mut dirs = DIRS.lock().unwrap();
+    let Some(entries) = dirs.get_mut(&handle) else {
+        return code(io::ErrorKind::InvalidInput.into());
+    };
+
+    let Some((name, metadata)) = entries.pop() else {
+        return 0;
+    };
+
+    let len = name.len().min(name_len);
+    slice::from_raw_parts_mut(name_ptr, len).copy_from_slice(&name.as_bytes()[..len]);
+    *out = metadata;
+
+    len as isize
+}
+
+#[no_mangle]
+extern "C" fn fs_close_dir(handle: Handle) {
+    DIRS.lock().unwrap().remove(&handle);
+}
@@ -0,0 +1,275 @@
+use std::{
+    cell::Cell,
+    future::Future,
+    ops::{Add, AddAssign, Sub, SubAssign},
+    pin::{pin, Pin},
+    task::{Context, Poll},
+};
+
+use critical_section as cs;
+use futures::{
+    future::{select, Either},
+    Stream,
+};
+use thiserror::Error;
+
+use crate::{asynch::reactor, ffi};
+
+pub mod sntp;
+
+pub use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Offset from the monotonic clock to the Unix epoch in microseconds, once the wall clock is known.
+static UNIX_OFFSET: cs::Mutex<Cell<Option<i64>>> = cs::Mutex::new(Cell::new(None));
+
+// The current wall-clock time, or `None` if it hasn't been set or synchronized yet.
+// `SystemTime::now` isn't available on wasm32-unknown-unknown.
+pub fn system_time() -> Option<SystemTime> {
+    let offset = unix_offset()?;
+    let micros = u64::try_from(Instant::now().0 as i64 + offset).ok()?;
+
+    Some(UNIX_EPOCH + Duration::from_micros(micros))
+}
+
+pub fn set_system_time(time: SystemTime) {
+    let unix = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_micros() as i64);
+
+    set_unix_offset(unix - Instant::now().0 as i64);
+}
+
+pub fn is_synchronized() -> bool {
+    unix_offset().is_some()
+}
+
+pub(crate) fn unix_offset() -> Option<i64> {
+    cs::with(|cs| UNIX_OFFSET.borrow(cs).get())
+}
+
+pub(crate) fn set_unix_offset(offset: i64) {
+    cs::with(|cs| UNIX_OFFSET.borrow(cs).set(Some(offset)));
+}
+
+// A point on the host's monotonic clock, with microsecond resolution. `std::time::Instant` isn't
+// available on wasm32-unknown-unknown.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub fn now() -> Self {
+        Self(unsafe { ffi::time::get_time() })
+    }
+
+    pub const fn from_micros(micros: u64) -> Self {
+        Self(micros)
+    }
+
+    pub const fn as_micros(&self) -> u64 {
+        self.0
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+
+    // Saturates to zero if `earlier` is later than `self`.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_micros(self.0.saturating_sub(earlier.0))
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        let micros = u64::try_from(duration.as_micros()).ok()?;
+        self.0.checked_add(micros).map(Self)
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        let micros = u64::try_from(duration.as_micros()).ok()?;
+        self.0.checked_sub(micros).map(Self)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        self.checked_add(rhs).unwrap_or(Self(u64::MAX))
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, rhs: Duration) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, rhs: Duration) -> Self::Output {
+        self.checked_sub(rhs).unwrap_or(Self(0))
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, rhs: Duration) {
+        *self = *self - rhs;
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Self::Output {
+        self.duration_since(rhs)
+    }
+}
+
+// The reactor entry is removed once the timer fires or is dropped.
+#[derive(Eq, PartialEq, Hash, Debug)]
+pub struct Timer {
+    deadline: Instant,
+    id: Option<u64>,
+}
+
+impl Timer {
+    pub fn at(deadline: Instant) -> Self {
+        Self { deadline, id: None }
+    }
+
+    pub fn after(duration: Duration) -> Self {
+        Self::at(Instant::now() + duration)
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+}
+
+impl Future for Timer {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if Instant::now() >= self.deadline {
+            if let Some(id) = self.id.take() {
+                reactor::cancel_timer(id);
+            }
+
+            Poll::Ready(())
+        } else {
+            self.id = Some(reactor::register_timer(
+                self.id,
+                self.deadline.0,
+                cx.waker(),
+            ));
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            reactor::cancel_timer(id);
+        }
+    }
+}
+
+pub fn sleep(duration: Duration) -> Timer {
+    Timer::after(duration)
+}
+
+pub fn sleep_until(deadline: Instant) -> Timer {
+    Timer::at(deadline)
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Error)]
+#[error("deadline has elapsed")]
+pub struct Elapsed;
+
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, Elapsed> {
+    timeout_at(Instant::now() + duration, future).await
+}
+
+pub async fn timeout_at<F: Future>(deadline: Instant, future: F) -> Result<F::Output, Elapsed> {
+    match select(pin!(future), Timer::at(deadline)).await {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(_) => Err(Elapsed),
+    }
+}
+
+// Yields at a fixed period. Missed ticks are skipped rather than delivered in a burst, which is
+// what watch apps want after the device was suspended.
+#[derive(Eq, PartialEq, Hash, Debug)]
+pub struct Interval {
+    next: Instant,
+    period: Duration,
+    timer: Option<u64>,
+}
+
+impl Interval {
+    pub fn new(period: Duration) -> Self {
+        Self::starting_at(Instant::now(), period)
+    }
+
+    pub fn starting_at(start: Instant, period: Duration) -> Self {
+        Self {
+            next: start,
+            period,
+            timer: None,
+        }
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    pub fn reset(&mut self) {
+        self.cancel_timer();
+        self.next = Instant::now() + self.period;
+    }
+
+    pub async fn tick(&mut self) -> Instant {
+        Timer::at(self.next).await;
+        self.advance()
+    }
+
+    fn advance(&mut self) -> Instant {
+        self.cancel_timer();
+
+        let tick = self.next;
+        let now = Instant::now();
+
+        self.next = tick + self.period;
+        if self.next <= now {
+            self.next = now + self.period;
+        }
+
+        tick
+    }
+
+    fn cancel_timer(&mut self) {
+        if let Some(id) = self.timer.take() {
+            reactor::cancel_timer(id);
+        }
+    }
+}
+
+impl Stream for Interval {
+    type Item = Instant;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if Instant::now() >= self.next {
+            Poll::Ready(Some(self.advance()))
+        } else {
+            self.timer = Some(reactor::register_timer(self.timer, self.next.0, cx.waker()));
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Interval {
+    fn drop(&mut self) {
+        self.cancel_timer();
+    }
+}
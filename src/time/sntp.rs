@@ -0,0 +1,249 @@
+use std::io;
+
+use thiserror::Error;
+
+use super::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::{
+    net::{self, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    rng, time,
+};
+
+pub const DEFAULT_PORT: u16 = 123;
+pub const DEFAULT_SERVER: &str = "pool.ntp.org";
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_SAMPLES: usize = 4;
+const MIN_RETRY: Duration = Duration::from_secs(15);
+
+const PACKET_LEN: usize = 48;
+// Seconds between the NTP epoch (1900) and the Unix epoch (1970).
+const NTP_UNIX_OFFSET: i64 = 2_208_988_800;
+const MODE_CLIENT: u8 = 3;
+const MODE_SERVER: u8 = 4;
+const VERSION: u8 = 4;
+const LEAP_UNSYNCHRONIZED: u8 = 3;
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Sample {
+    // Microseconds to add to the monotonic clock to get Unix time.
+    pub offset: i64,
+    pub delay: Duration,
+    pub stratum: u8,
+}
+
+impl Sample {
+    pub fn system_time_at(&self, instant: Instant) -> Option<SystemTime> {
+        let micros = u64::try_from(instant.as_micros() as i64 + self.offset).ok()?;
+
+        Some(UNIX_EPOCH + Duration::from_micros(micros))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("server did not respond in time")]
+    Timeout,
+    #[error("server sent kiss-of-death code {0:?}")]
+    KissOfDeath(String),
+    #[error("server clock is not synchronized")]
+    Unsynchronized,
+    #[error("invalid response: {0}")]
+    InvalidResponse(&'static str),
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Client {
+    server: String,
+    port: u16,
+    interval: Duration,
+    timeout: Duration,
+    samples: usize,
+}
+
+impl Client {
+    pub fn new(server: impl Into<String>) -> Self {
+        Self {
+            server: server.into(),
+            port: DEFAULT_PORT,
+            interval: DEFAULT_INTERVAL,
+            timeout: DEFAULT_TIMEOUT,
+            samples: DEFAULT_SAMPLES,
+        }
+    }
+
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    // How often `run` resynchronizes the clock.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // How many queries `sync` makes. The one with the lowest round-trip delay wins, since its
+    // offset is the least skewed by asymmetric network latency.
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    pub async fn query(&self) -> Result<Sample, Error> {
+        let addr = net::lookup_host(&self.server, self.port)?;
+
+        let local = match addr {
+            SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+            SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+        };
+        let socket = UdpSocket::bind(local)?;
+
+        // A random transmit timestamp is echoed back by the server as the originate timestamp,
+        // which lets us drop stale or spoofed replies.
+        let cookie = rng::random::<u64>();
+        let mut request = [0; PACKET_LEN];
+        request[0] = (VERSION << 3) | MODE_CLIENT;
+        request[40..48].copy_from_slice(&cookie.to_be_bytes());
+
+        let t1 = Instant::now();
+        socket.send_to(&request, addr).await?;
+
+        let (response, t4) = time::timeout(self.timeout, async {
+            let mut buf = [0; PACKET_LEN * 2];
+
+            loop {
+                let (len, from) = socket.recv_from(&mut buf).await?;
+                let t4 = Instant::now();
+
+                if from == addr && len >= PACKET_LEN && buf[24..32] == cookie.to_be_bytes() {
+                    let mut response = [0; PACKET_LEN];
+                    response.copy_from_slice(&buf[..PACKET_LEN]);
+
+                    return Ok::<_, Error>((response, t4));
+                }
+            }
+        })
+        .await
+        .map_err(|_| Error::Timeout)??;
+
+        parse_response(&response, t1, t4)
+    }
+
+    // Queries the server and sets the wall clock from the best sample.
+    pub async fn sync(&self) -> Result<Sample, Error> {
+        let mut best: Option<Sample> = None;
+        let mut last_error = None;
+
+        for _ in 0..self.samples {
+            match self.query().await {
+                Ok(sample) if best.is_none_or(|b| sample.delay < b.delay) => best = Some(sample),
+                Ok(_) => {}
+                // The server asked us to back off, so don't send it any more queries.
+                Err(e @ Error::KissOfDeath(_)) => return Err(e),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        match (best, last_error) {
+            (Some(sample), _) => {
+                time::set_unix_offset(sample.offset);
+                Ok(sample)
+            }
+            (None, Some(e)) => Err(e),
+            (None, None) => Err(Error::Timeout),
+        }
+    }
+
+    // Keeps the wall clock synchronized forever. Failed attempts are retried with an exponential
+    // backoff, capped at the regular interval.
+    pub async fn run(&self) -> ! {
+        let mut retry = MIN_RETRY;
+
+        loop {
+            match self.sync().await {
+                Ok(sample) => {
+                    log::debug!(
+                        "synchronized clock with {}, delay {:?}",
+                        self.server,
+                        sample.delay
+                    );
+
+                    retry = MIN_RETRY;
+                    time::sleep(self.interval).await;
+                }
+                Err(e) => {
+                    log::warn!("failed to synchronize clock with {}: {e}", self.server);
+
+                    time::sleep(retry.min(self.interval)).await;
+                    retry = (retry * 2).min(self.interval);
+                }
+            }
+        }
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new(DEFAULT_SERVER)
+    }
+}
+
+fn parse_response(packet: &[u8; PACKET_LEN], t1: Instant, t4: Instant) -> Result<Sample, Error> {
+    let leap = packet[0] >> 6;
+    let mode = packet[0] & 0x07;
+    let stratum = packet[1];
+
+    if mode != MODE_SERVER {
+        return Err(Error::InvalidResponse("not a server reply"));
+    }
+
+    if stratum == 0 {
+        let code = String::from_utf8_lossy(&packet[12..16]).into_owned();
+        return Err(Error::KissOfDeath(code));
+    }
+
+    if leap == LEAP_UNSYNCHRONIZED || stratum > 15 {
+        return Err(Error::Unsynchronized);
+    }
+
+    let t2 = ntp_to_unix_micros(&packet[32..40]);
+    let t3 = ntp_to_unix_micros(&packet[40..48]);
+    if t3 < t2 {
+        return Err(Error::InvalidResponse("transmit time before receive time"));
+    }
+
+    let t1 = t1.as_micros() as i64;
+    let t4 = t4.as_micros() as i64;
+
+    // The standard NTP offset and delay calculation, where the local timestamps come from the
+    // monotonic clock, so the offset maps it straight onto Unix time.
+    let offset = ((t2 - t1) + (t3 - t4)) / 2;
+    let delay = ((t4 - t1) - (t3 - t2)).max(0);
+
+    Ok(Sample {
+        offset,
+        delay: Duration::from_micros(delay as u64),
+        stratum,
+    })
+}
+
+fn ntp_to_unix_micros(timestamp: &[u8]) -> i64 {
+    let timestamp = u64::from_be_bytes(timestamp.try_into().unwrap());
+    let mut secs = (timestamp >> 32) as i64;
+    let frac = timestamp & 0xffff_ffff;
+
+    // Timestamps with the top bit clear belong to NTP era 1, which starts in 2036.
+    if secs & 0x8000_0000 == 0 {
+        secs += 1 << 32;
+    }
+
+    (secs - NTP_UNIX_OFFSET) * 1_000_000 + ((frac * 1_000_000) >> 32) as i64
+}
@@ -0,0 +1,209 @@
+use super::Error;
+
+pub(crate) const OPCODE_CONTINUATION: u8 = 0x0;
+pub(crate) const OPCODE_TEXT: u8 = 0x1;
+pub(crate) const OPCODE_BINARY: u8 = 0x2;
+pub(crate) const OPCODE_CLOSE: u8 = 0x8;
+pub(crate) const OPCODE_PING: u8 = 0x9;
+pub(crate) const OPCODE_PONG: u8 = 0xa;
+
+const MAX_CONTROL_PAYLOAD: usize = 125;
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub(crate) struct Frame {
+    pub fin: bool,
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub(crate) fn new(opcode: u8, payload: Vec<u8>) -> Self {
+        Self {
+            fin: true,
+            opcode,
+            payload,
+        }
+    }
+
+    pub(crate) fn is_control(&self) -> bool {
+        self.opcode & 0x8 != 0
+    }
+
+    // Tries to decode a single frame from the start of `buf`, returning the frame and the number of
+    // bytes it occupied, or `None` if `buf` doesn't hold a complete frame yet.
+    pub(crate) fn decode(buf: &[u8], max_payload: usize) -> Result<Option<(Self, usize)>, Error> {
+        let [b0, b1, rest @ ..] = buf else {
+            return Ok(None);
+        };
+
+        if b0 & 0x70 != 0 {
+            return Err(Error::Protocol("reserved bits set"));
+        }
+
+        // Servers must never mask their frames.
+        if b1 & 0x80 != 0 {
+            return Err(Error::Protocol("masked frame from server"));
+        }
+
+        let fin = b0 & 0x80 != 0;
+        let opcode = b0 & 0x0f;
+
+        let (len, header_len) = match b1 & 0x7f {
+            126 => match rest {
+                [a, b, ..] => (u16::from_be_bytes([*a, *b]) as u64, 4),
+                _ => return Ok(None),
+            },
+            127 => match rest.get(..8) {
+                Some(bytes) => (u64::from_be_bytes(bytes.try_into().unwrap()), 10),
+                None => return Ok(None),
+            },
+            len => (len as u64, 2),
+        };
+
+        let frame = Self {
+            fin,
+            opcode,
+            payload: Vec::new(),
+        };
+
+        if frame.is_control() && (!fin || len > MAX_CONTROL_PAYLOAD as u64) {
+            return Err(Error::Protocol("invalid control frame"));
+        }
+
+        if len > max_payload as u64 {
+            return Err(Error::MessageTooLarge { limit: max_payload });
+        }
+
+        let end = header_len + len as usize;
+        let Some(payload) = buf.get(header_len..end) else {
+            return Ok(None);
+        };
+
+        Ok(Some((
+            Self {
+                payload: payload.to_vec(),
+                ..frame
+            },
+            end,
+        )))
+    }
+
+    // Appends the frame to `out`, masked with `mask` as required for client-to-server frames.
+    pub(crate) fn encode(&self, mask: [u8; 4], out: &mut Vec<u8>) {
+        let len = self.payload.len();
+
+        out.push(((self.fin as u8) << 7) | self.opcode);
+
+        match len {
+            0..=125 => out.push(0x80 | len as u8),
+            126..=0xffff => {
+                out.push(0x80 | 126);
+                out.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            _ => {
+                out.push(0x80 | 127);
+                out.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+
+        out.extend_from_slice(&mask);
+        out.extend(
+            self.payload
+                .iter()
+                .zip(mask.iter().cycle())
+                .map(|(b, m)| b ^ m),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Turns a client frame into what a server would send: the same frame without the mask.
+    fn unmask(frame: &[u8]) -> Vec<u8> {
+        let header_len = match frame[1] & 0x7f {
+            126 => 4,
+            127 => 10,
+            _ => 2,
+        };
+        let mask = &frame[header_len..header_len + 4];
+
+        let mut out = frame[..header_len].to_vec();
+        out[1] &= 0x7f;
+        out.extend(
+            frame[header_len + 4..]
+                .iter()
+                .zip(mask.iter().cycle())
+                .map(|(b, m)| b ^ m),
+        );
+        out
+    }
+
+    #[test]
+    fn rfc_examples() {
+        let mut out = Vec::new();
+        Frame::new(OPCODE_TEXT, b"Hello".to_vec()).encode([0x37, 0xfa, 0x21, 0x3d], &mut out);
+        assert_eq!(
+            out,
+            [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58]
+        );
+
+        let buf = [0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f];
+        let (frame, len) = Frame::decode(&buf, 1024).unwrap().unwrap();
+        assert_eq!(frame, Frame::new(OPCODE_TEXT, b"Hello".to_vec()));
+        assert_eq!(len, buf.len());
+    }
+
+    #[test]
+    fn round_trip() {
+        // Covers each of the three length encodings.
+        for len in [0, 125, 126, 0xffff, 0x10000] {
+            let frame = Frame {
+                fin: len != 126,
+                opcode: OPCODE_BINARY,
+                payload: (0..len).map(|i| i as u8).collect(),
+            };
+
+            let mut out = Vec::new();
+            frame.encode([1, 2, 3, 4], &mut out);
+            let mut buf = unmask(&out);
+            let total = buf.len();
+            buf.extend_from_slice(b"next");
+
+            assert_eq!(Frame::decode(&buf, 0x10000).unwrap(), Some((frame, total)));
+        }
+    }
+
+    #[test]
+    fn partial() {
+        let mut out = Vec::new();
+        Frame::new(OPCODE_BINARY, vec![7; 300]).encode([0; 4], &mut out);
+        let buf = unmask(&out);
+
+        for len in 0..buf.len() {
+            assert_eq!(Frame::decode(&buf[..len], 1024).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        let decode = |buf: &[u8]| Frame::decode(buf, 4);
+
+        assert!(matches!(decode(&[0xc1, 0x00]), Err(Error::Protocol(_))));
+        assert!(matches!(
+            decode(&[0x81, 0x80, 0, 0, 0, 0]),
+            Err(Error::Protocol(_))
+        ));
+        // Control frames can't be fragmented or longer than 125 bytes.
+        assert!(matches!(decode(&[0x09, 0x00]), Err(Error::Protocol(_))));
+        assert!(matches!(
+            decode(&[0x89, 0x7e, 0x00, 0x7e]),
+            Err(Error::Protocol(_))
+        ));
+        assert!(matches!(
+            decode(&[0x82, 0x05, 1, 2, 3, 4, 5]),
+            Err(Error::MessageTooLarge { limit: 4 })
+        ));
+    }
+}
@@ -0,0 +1,452 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures::{AsyncRead, AsyncWrite, AsyncWriteExt, Sink, Stream};
+use thiserror::Error;
+
+use crate::{
+    http::{self, body::Reader, Headers, Url, DEFAULT_MAX_HEADER_SIZE, MAX_HEADERS},
+    net::{TcpStream, TlsStream},
+    rng,
+};
+
+mod frame;
+
+use frame::{
+    Frame, OPCODE_BINARY, OPCODE_CLOSE, OPCODE_CONTINUATION, OPCODE_PING, OPCODE_PONG, OPCODE_TEXT,
+};
+
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_GOING_AWAY: u16 = 1001;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const CLOSE_MESSAGE_TOO_BIG: u16 = 1009;
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const READ_CHUNK: usize = 512;
+// Outgoing frames are buffered up to this size before `poll_ready` starts pushing them out.
+const WRITE_BUFFER_SIZE: usize = 1024;
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    Close(Option<CloseFrame>),
+}
+
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+impl CloseFrame {
+    fn decode(payload: &[u8]) -> Result<Option<Self>, Error> {
+        match payload {
+            [] => Ok(None),
+            [a, b, reason @ ..] => Ok(Some(Self {
+                code: u16::from_be_bytes([*a, *b]),
+                reason: String::from_utf8(reason.to_vec()).map_err(|_| Error::InvalidUtf8)?,
+            })),
+            _ => Err(Error::Protocol("invalid close frame")),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut payload = self.code.to_be_bytes().to_vec();
+        payload.extend_from_slice(self.reason.as_bytes());
+        payload
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("HTTP error: {0}")]
+    Http(#[from] http::Error),
+    #[error("server refused the upgrade with status {status}")]
+    HandshakeFailed { status: u16 },
+    #[error("server sent an invalid Sec-WebSocket-Accept key")]
+    InvalidAcceptKey,
+    #[error("protocol error: {0}")]
+    Protocol(&'static str),
+    #[error("message exceeds {limit} bytes")]
+    MessageTooLarge { limit: usize },
+    #[error("text message is not valid UTF-8")]
+    InvalidUtf8,
+    #[error("connection closed")]
+    ConnectionClosed,
+}
+
+pub struct WebSocket<S = TcpStream> {
+    stream: S,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+    // Opcode and payload of a fragmented message that is still being received.
+    fragment: Option<(u8, Vec<u8>)>,
+    max_message_size: usize,
+    close_sent: bool,
+    close_received: bool,
+}
+
+impl WebSocket<TcpStream> {
+    pub async fn connect(url: &str) -> Result<Self, Error> {
+        let url = match url.split_once("://") {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("ws") => {
+                Url::parse(&format!("http://{rest}"))?
+            }
+            _ => return Err(http::Error::UnsupportedScheme.into()),
+        };
+
+        let stream = TcpStream::connect_host(url.host(), url.port()).await?;
+
+        Self::handshake(stream, &url, Headers::new()).await
+    }
+}
+
+impl WebSocket<TlsStream> {
+    pub async fn connect_secure(url: &str) -> Result<Self, Error> {
+        let url = match url.split_once("://") {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("wss") => {
+                Url::parse(&format!("https://{rest}"))?
+            }
+            _ => return Err(http::Error::UnsupportedScheme.into()),
+        };
+
+        let stream = TlsStream::connect(url.host(), url.port()).await?;
+
+        Self::handshake(stream, &url, Headers::new()).await
+    }
+}
+
+impl<S> WebSocket<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // Performs the opening handshake over an already connected stream. `headers` are added to the
+    // upgrade request, e.g. for authentication or `Sec-WebSocket-Protocol`.
+    pub async fn handshake(mut stream: S, url: &Url, headers: Headers) -> Result<Self, Error> {
+        headers.validate()?;
+
+        let key = BASE64.encode(rng::random::<[u8; 16]>());
+
+        let mut request = format!(
+            "GET {} HTTP/1.1\r\n\
+             Host: {}\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Key: {key}\r\n\
+             Sec-WebSocket-Version: 13\r\n",
+            url.path(),
+            url.authority(),
+        );
+        for (name, value) in headers.iter() {
+            request.push_str(&format!("{name}: {value}\r\n"));
+        }
+        request.push_str("\r\n");
+
+        stream.write_all(request.as_bytes()).await?;
+        stream.flush().await?;
+
+        let mut reader = Reader::new(&mut stream);
+        let head = reader
+            .read_head(DEFAULT_MAX_HEADER_SIZE)
+            .await?
+            .ok_or(Error::ConnectionClosed)?;
+
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut parsed = httparse::Response::new(&mut headers);
+        parsed.parse(&head).map_err(http::Error::from)?;
+
+        let status = parsed.code.unwrap_or_default();
+        if status != 101 {
+            return Err(Error::HandshakeFailed { status });
+        }
+
+        let headers = Headers::from_parsed(parsed.headers)?;
+        if !headers.has_token("Upgrade", "websocket") || !headers.has_token("Connection", "upgrade")
+        {
+            return Err(Error::HandshakeFailed { status });
+        }
+
+        let mut sha1 = sha1_smol::Sha1::new();
+        sha1.update(key.as_bytes());
+        sha1.update(ACCEPT_GUID.as_bytes());
+        let expected = BASE64.encode(sha1.digest().bytes());
+
+        if headers.get("Sec-WebSocket-Accept").map(str::trim) != Some(expected.as_str()) {
+            return Err(Error::InvalidAcceptKey);
+        }
+
+        // The server may send its first frames right behind the handshake response.
+        let read_buf = reader.into_buffered();
+
+        Ok(Self {
+            stream,
+            read_buf,
+            write_buf: Vec::new(),
+            fragment: None,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            close_sent: false,
+            close_received: false,
+        })
+    }
+
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.close_sent && self.close_received
+    }
+
+    fn queue(&mut self, frame: Frame) {
+        frame.encode(rng::random(), &mut self.write_buf);
+    }
+
+    fn poll_write_buf(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        while !self.write_buf.is_empty() {
+            let n = ready!(Pin::new(&mut self.stream).poll_write(cx, &self.write_buf))?;
+
+            if n == 0 {
+                return Poll::Ready(Err(io::Error::from(io::ErrorKind::WriteZero).into()));
+            }
+
+            self.write_buf.drain(..n);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn handle_frame(&mut self, frame: Frame) -> Result<Option<Message>, Error> {
+        match frame.opcode {
+            OPCODE_TEXT | OPCODE_BINARY if self.fragment.is_some() => {
+                Err(Error::Protocol("expected continuation frame"))
+            }
+            OPCODE_TEXT | OPCODE_BINARY if !frame.fin => {
+                self.fragment = Some((frame.opcode, frame.payload));
+                Ok(None)
+            }
+            OPCODE_TEXT | OPCODE_BINARY => data_message(frame.opcode, frame.payload).map(Some),
+            OPCODE_CONTINUATION => {
+                let Some((_, payload)) = self.fragment.as_mut() else {
+                    return Err(Error::Protocol("unexpected continuation frame"));
+                };
+
+                if payload.len() + frame.payload.len() > self.max_message_size {
+                    return Err(Error::MessageTooLarge {
+                        limit: self.max_message_size,
+                    });
+                }
+                payload.extend_from_slice(&frame.payload);
+
+                match (frame.fin, self.fragment.take()) {
+                    (true, Some((opcode, payload))) => data_message(opcode, payload).map(Some),
+                    (_, fragment) => {
+                        self.fragment = fragment;
+                        Ok(None)
+                    }
+                }
+            }
+            OPCODE_PING => {
+                self.queue(Frame::new(OPCODE_PONG, frame.payload.clone()));
+                Ok(Some(Message::Ping(frame.payload)))
+            }
+            OPCODE_PONG => Ok(Some(Message::Pong(frame.payload))),
+            OPCODE_CLOSE => {
+                let close = CloseFrame::decode(&frame.payload)?;
+                self.close_received = true;
+
+                // Echo the close frame to complete the closing handshake.
+                if !self.close_sent {
+                    let code = close.as_ref().map_or(CLOSE_NORMAL, |c| c.code);
+                    self.queue(Frame::new(OPCODE_CLOSE, code.to_be_bytes().to_vec()));
+                    self.close_sent = true;
+                }
+
+                Ok(Some(Message::Close(close)))
+            }
+            _ => Err(Error::Protocol("unknown opcode")),
+        }
+    }
+
+    fn fail(&mut self, error: Error) -> Error {
+        let code = match error {
+            Error::MessageTooLarge { .. } => Some(CLOSE_MESSAGE_TOO_BIG),
+            Error::Protocol(_) | Error::InvalidUtf8 => Some(CLOSE_PROTOCOL_ERROR),
+            _ => None,
+        };
+
+        if let (Some(code), false) = (code, self.close_sent) {
+            self.queue(Frame::new(OPCODE_CLOSE, code.to_be_bytes().to_vec()));
+            self.close_sent = true;
+        }
+
+        self.close_received = true;
+        error
+    }
+}
+
+fn data_message(opcode: u8, payload: Vec<u8>) -> Result<Message, Error> {
+    match opcode {
+        OPCODE_TEXT => String::from_utf8(payload)
+            .map(Message::Text)
+            .map_err(|_| Error::InvalidUtf8),
+        _ => Ok(Message::Binary(payload)),
+    }
+}
+
+impl<S> Stream for WebSocket<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = Result<Message, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        // Push out any queued pongs or close replies. This is best-effort, a pending write is
+        // retried on the next poll.
+        if let Poll::Ready(Err(e)) = this.poll_write_buf(cx) {
+            return Poll::Ready(Some(Err(e)));
+        }
+
+        if this.close_received {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            match Frame::decode(&this.read_buf, this.max_message_size) {
+                Ok(Some((frame, len))) => {
+                    this.read_buf.drain(..len);
+
+                    match this.handle_frame(frame) {
+                        Ok(Some(message)) => {
+                            let _ = this.poll_write_buf(cx);
+                            return Poll::Ready(Some(Ok(message)));
+                        }
+                        Ok(None) => continue,
+                        Err(e) => return Poll::Ready(Some(Err(this.fail(e)))),
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => return Poll::Ready(Some(Err(this.fail(e)))),
+            }
+
+            let start = this.read_buf.len();
+            this.read_buf.resize(start + READ_CHUNK, 0);
+
+            let res = Pin::new(&mut this.stream).poll_read(cx, &mut this.read_buf[start..]);
+            let n = match &res {
+                Poll::Ready(Ok(n)) => *n,
+                _ => 0,
+            };
+            this.read_buf.truncate(start + n);
+
+            match res {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                Poll::Ready(Ok(0)) => {
+                    this.close_received = true;
+                    return Poll::Ready(Some(Err(Error::ConnectionClosed)));
+                }
+                Poll::Ready(Ok(_)) => {}
+            }
+        }
+    }
+}
+
+impl<S> Sink<Message> for WebSocket<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+
+        if this.write_buf.len() >= WRITE_BUFFER_SIZE {
+            ready!(this.poll_write_buf(cx))?;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+
+        if this.close_sent {
+            return Err(Error::ConnectionClosed);
+        }
+
+        let frame = match item {
+            Message::Text(text) => Frame::new(OPCODE_TEXT, text.into_bytes()),
+            Message::Binary(data) => Frame::new(OPCODE_BINARY, data),
+            Message::Ping(data) => Frame::new(OPCODE_PING, data),
+            Message::Pong(data) => Frame::new(OPCODE_PONG, data),
+            Message::Close(close) => {
+                this.close_sent = true;
+                Frame::new(OPCODE_CLOSE, close.map(|c| c.encode()).unwrap_or_default())
+            }
+        };
+
+        this.queue(frame);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+
+        ready!(this.poll_write_buf(cx))?;
+        ready!(Pin::new(&mut this.stream).poll_flush(cx))?;
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+
+        if !this.close_sent {
+            this.queue(Frame::new(OPCODE_CLOSE, CLOSE_NORMAL.to_be_bytes().to_vec()));
+            this.close_sent = true;
+        }
+
+        ready!(this.poll_write_buf(cx))?;
+        ready!(Pin::new(&mut this.stream).poll_close(cx))?;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, io::Cursor};
+
+    use super::*;
+
+    #[test]
+    fn handshake_header_injection() {
+        let url = Url::parse("http://example.com/chat").unwrap();
+        let mut stream = Cursor::new(Vec::new());
+        let mut headers = Headers::new();
+        headers.insert("Authorization", "x\r\nX-Injected: 1");
+
+        let res = block_on(WebSocket::handshake(&mut stream, &url, headers));
+
+        assert!(matches!(res, Err(Error::Http(http::Error::InvalidHeader))));
+        assert!(stream.get_ref().is_empty());
+    }
+}